@@ -0,0 +1,1034 @@
+//! Places to keep ArtifactSets and Releases, other than The Abyss
+//!
+//! By default [`Gazenot`][crate::Gazenot] talks to The Abyss over HTTP, but any [`Backend`][]
+//! can be selected with [`GazenotBuilder::backend`][crate::GazenotBuilder::backend].
+//! The client still takes care of batching, concurrency limits, retries, reports, dry runs,
+//! and mocks; the backend only does the individual parts of each operation.
+//!
+//! [`LocalBackend`][] keeps everything in a directory, and serves files from `file://` URLs.
+//! This is handy for offline mirrors, and for staging releases on machines that can't
+//! reach The Abyss:
+//!
+//! ```
+//! use camino::Utf8PathBuf;
+//! use gazenot::{backend::LocalBackend, AnnouncementKey, Gazenot, ReleaseKey};
+//!
+//! # #[tokio::main]
+//! # async fn main() -> Result<(), miette::Report> {
+//! # let dir = std::env::temp_dir().join(format!("gazenot-backend-{}", std::process::id()));
+//! # let dir = Utf8PathBuf::try_from(dir).unwrap();
+//! let abyss = Gazenot::builder("github".parse()?, "axodotdev".parse()?)
+//!     .backend(LocalBackend::new(&dir)?)
+//!     .build()?;
+//!
+//! let sets = abyss.create_artifact_sets(["app1".parse()?]).await?;
+//! let files = vec![Utf8PathBuf::from("Cargo.toml")];
+//! abyss
+//!     .upload_files(sets.iter().map(|set| (set, files.clone())))
+//!     .await?;
+//! let key = ReleaseKey::from_tag("app1", "v1.0.0".parse()?)?;
+//! let releases = abyss
+//!     .create_releases(sets.iter().map(|set| (set, key.clone())))
+//!     .await?;
+//! let announcement = AnnouncementKey {
+//!     body: "# v1.0.0\n\nIt's out!".to_owned(),
+//! };
+//! abyss.create_announcements(&releases, announcement).await?;
+//!
+//! let url = abyss.download_release_url(&releases[0], "Cargo.toml")?;
+//! assert_eq!(url.scheme(), "file");
+//! assert!(dir.join("github/axodotdev/app1/v1.0.0/Cargo.toml").exists());
+//! # std::fs::remove_dir_all(&dir).unwrap();
+//! # Ok(())
+//! # }
+//! ```
+
+use std::{
+    io::ErrorKind,
+    time::{Duration, SystemTime, UNIX_EPOCH},
+};
+
+use camino::{Utf8Path, Utf8PathBuf};
+use futures_util::future::BoxFuture;
+use reqwest::Url;
+use serde::{de::DeserializeOwned, Deserialize, Serialize};
+use tokio::io::AsyncWriteExt;
+
+use crate::{
+    client::{check_filename, sha256_file},
+    error::{GazenotError, GazenotErrorInner, Result, ResultInner},
+    route::{Endpoints, Route},
+    types::encode_path_segment,
+    AnnouncementKey, ArtifactSet, ArtifactSetFile, ArtifactSetFilter, ArtifactSetId,
+    ArtifactSetInfo, ArtifactSetState, PackageName, Release, ReleaseArtifact, ReleaseInfo,
+    ReleaseKey, ReleaseList, ReleaseTag, UnparsedTimestamp, UnparsedUrl, UnparsedVersion,
+};
+
+/// Where [`LocalBackend`][] keeps what it knows about ArtifactSets, Releases, and announcements
+///
+/// Nothing hosted can start with a `.`, so this can't collide with anything.
+const RECORDS_DIR: &str = ".gazenot";
+
+/// A place to keep ArtifactSets and Releases, in place of The Abyss
+///
+/// Each method is a single part of a batch operation on [`Gazenot`][crate::Gazenot],
+/// which takes care of retrying it, so implementations should only try once.
+/// Failures other than I/O errors should be reported with [`GazenotErrorInner::Backend`][].
+pub trait Backend: Send + Sync {
+    /// A description of the backend, for error messages (e.g. "local hosting in ./dist")
+    fn description(&self) -> String;
+
+    /// Where a route lives on this backend
+    ///
+    /// This is what [`Gazenot::download_artifact_set_url`][crate::Gazenot::download_artifact_set_url]
+    /// and friends return, and what reports say each part of an operation accessed.
+    fn url(&self, endpoints: &Endpoints, route: Route) -> ResultInner<Url>;
+
+    /// Create a new ArtifactSet for a package
+    fn create_artifact_set<'a>(
+        &'a self,
+        endpoints: &'a Endpoints,
+        package: &'a PackageName,
+    ) -> BoxFuture<'a, ResultInner<ArtifactSet>>;
+
+    /// Get everything that's known about an ArtifactSet
+    fn get_artifact_set<'a>(
+        &'a self,
+        endpoints: &'a Endpoints,
+        package: &'a PackageName,
+        public_id: &'a ArtifactSetId,
+    ) -> BoxFuture<'a, ResultInner<ArtifactSetInfo>>;
+
+    /// List the ArtifactSets of a package
+    fn list_artifact_sets<'a>(
+        &'a self,
+        endpoints: &'a Endpoints,
+        package: &'a PackageName,
+        filter: &'a ArtifactSetFilter,
+    ) -> BoxFuture<'a, ResultInner<Vec<ArtifactSetInfo>>>;
+
+    /// Delete an ArtifactSet, along with everything uploaded to it
+    ///
+    /// ArtifactSets that are already gone should be skipped,
+    /// and ones that a Release was created from should be refused.
+    fn delete_artifact_set<'a>(
+        &'a self,
+        endpoints: &'a Endpoints,
+        set: &'a ArtifactSet,
+    ) -> BoxFuture<'a, ResultInner<()>>;
+
+    /// Upload the file at `path` to an ArtifactSet as `filename`, and return its size
+    ///
+    /// `sha256` is the hex-encoded SHA-256 of the file, which should be checked
+    /// against what was actually stored.
+    fn upload_file<'a>(
+        &'a self,
+        endpoints: &'a Endpoints,
+        set: &'a ArtifactSet,
+        filename: &'a str,
+        path: &'a Utf8Path,
+        sha256: &'a str,
+    ) -> BoxFuture<'a, ResultInner<u64>>;
+
+    /// Create a Release from an ArtifactSet
+    fn create_release<'a>(
+        &'a self,
+        endpoints: &'a Endpoints,
+        set: &'a ArtifactSet,
+        key: &'a ReleaseKey,
+    ) -> BoxFuture<'a, ResultInner<Release>>;
+
+    /// Announce some Releases
+    fn create_announcement<'a>(
+        &'a self,
+        endpoints: &'a Endpoints,
+        releases: &'a [Release],
+        announcement: &'a AnnouncementKey,
+    ) -> BoxFuture<'a, ResultInner<()>>;
+
+    /// List the Releases of a package, newest first
+    fn list_releases<'a>(
+        &'a self,
+        endpoints: &'a Endpoints,
+        package: &'a PackageName,
+    ) -> BoxFuture<'a, ResultInner<ReleaseList>>;
+
+    /// Download the file at one of this backend's URLs to `dest`
+    fn download_file<'a>(
+        &'a self,
+        url: &'a Url,
+        dest: &'a Utf8Path,
+    ) -> BoxFuture<'a, ResultInner<()>>;
+}
+
+/// Keep everything in a directory on the local filesystem
+///
+/// The directory mirrors the layout of the hosting server (with the owner in the path),
+/// under a directory for the source host. Alongside the hosted files are records of
+/// what each ArtifactSet and Release is, and every announcement:
+///
+/// ```text
+/// github/axodotdev/app1/<public_id>/app1.tar.gz    uploaded to an ArtifactSet
+/// github/axodotdev/app1/v1.0.0/app1.tar.gz         copied into a Release
+/// github/axodotdev/app1/.gazenot/sets/             a record of each ArtifactSet
+/// github/axodotdev/app1/.gazenot/releases/         a record of each Release
+/// github/axodotdev/.gazenot/announcements/         every announcement
+/// ```
+///
+/// Tags are percent-encoded to get their directory name, so "app1/v1.0.0" is kept in "app1%2Fv1.0.0".
+#[derive(Debug, Clone)]
+pub struct LocalBackend {
+    root: Utf8PathBuf,
+}
+
+/// What [`LocalBackend`][] knows about an ArtifactSet, besides its files
+#[derive(Serialize, Deserialize, Debug, Clone)]
+struct SetRecord {
+    public_id: ArtifactSetId,
+    created_at: UnparsedTimestamp,
+    created_unix_nanos: u64,
+}
+
+/// What [`LocalBackend`][] knows about a Release, besides its files
+#[derive(Serialize, Deserialize, Debug, Clone)]
+struct ReleaseRecord {
+    artifact_set_id: ArtifactSetId,
+    tag: ReleaseTag,
+    version: UnparsedVersion,
+    is_prerelease: bool,
+    created_at: UnparsedTimestamp,
+    created_unix_nanos: u64,
+}
+
+/// An announcement [`LocalBackend`][] was asked to make
+#[derive(Serialize, Deserialize, Debug, Clone)]
+struct AnnouncementRecord {
+    releases: Vec<AnnouncedRelease>,
+    body: String,
+    created_at: UnparsedTimestamp,
+}
+
+#[derive(Serialize, Deserialize, Debug, Clone)]
+struct AnnouncedRelease {
+    package: PackageName,
+    tag: ReleaseTag,
+}
+
+impl LocalBackend {
+    /// Keep everything in `root`, creating it if it doesn't exist
+    pub fn new(root: impl Into<Utf8PathBuf>) -> Result<Self> {
+        let root = root.into();
+        let desc = format!("use {root} for local hosting");
+        let io_err = |details| {
+            GazenotError::new(
+                &desc,
+                GazenotErrorInner::Io {
+                    path: root.clone(),
+                    details,
+                },
+            )
+        };
+        std::fs::create_dir_all(&root).map_err(io_err)?;
+        // Downloads are only allowed from inside root, so make sure we know where that really is
+        let root = root.canonicalize_utf8().map_err(io_err)?;
+        Ok(Self { root })
+    }
+
+    /// The directory everything is kept in
+    pub fn root(&self) -> &Utf8Path {
+        &self.root
+    }
+
+    fn error(&self, reason: impl Into<String>) -> GazenotErrorInner {
+        GazenotErrorInner::Backend {
+            backend: self.description(),
+            reason: reason.into(),
+        }
+    }
+
+    fn owner_dir(&self, endpoints: &Endpoints) -> Utf8PathBuf {
+        self.root
+            .join(endpoints.source_host.as_str())
+            .join(endpoints.owner.as_str())
+    }
+
+    fn package_dir(&self, endpoints: &Endpoints, package: &PackageName) -> Utf8PathBuf {
+        self.owner_dir(endpoints).join(package.as_str())
+    }
+
+    fn set_dir(
+        &self,
+        endpoints: &Endpoints,
+        package: &PackageName,
+        public_id: &ArtifactSetId,
+    ) -> ResultInner<Utf8PathBuf> {
+        // Ids in deserialized ArtifactSets aren't validated, but this one becomes a directory
+        let public_id = ArtifactSetId::new(public_id.as_str())?;
+        Ok(self
+            .package_dir(endpoints, package)
+            .join(public_id.as_str()))
+    }
+
+    fn release_dir(
+        &self,
+        endpoints: &Endpoints,
+        package: &PackageName,
+        tag: &ReleaseTag,
+    ) -> Utf8PathBuf {
+        self.package_dir(endpoints, package)
+            .join(encode_path_segment(tag))
+    }
+
+    fn set_records_dir(&self, endpoints: &Endpoints, package: &PackageName) -> Utf8PathBuf {
+        self.package_dir(endpoints, package)
+            .join(RECORDS_DIR)
+            .join("sets")
+    }
+
+    fn set_record(
+        &self,
+        endpoints: &Endpoints,
+        package: &PackageName,
+        public_id: &ArtifactSetId,
+    ) -> Utf8PathBuf {
+        self.set_records_dir(endpoints, package)
+            .join(format!("{public_id}.json"))
+    }
+
+    fn release_records_dir(&self, endpoints: &Endpoints, package: &PackageName) -> Utf8PathBuf {
+        self.package_dir(endpoints, package)
+            .join(RECORDS_DIR)
+            .join("releases")
+    }
+
+    fn release_record(
+        &self,
+        endpoints: &Endpoints,
+        package: &PackageName,
+        tag: &ReleaseTag,
+    ) -> Utf8PathBuf {
+        self.release_records_dir(endpoints, package)
+            .join(format!("{}.json", encode_path_segment(tag)))
+    }
+
+    fn announcements_dir(&self, endpoints: &Endpoints) -> Utf8PathBuf {
+        self.owner_dir(endpoints)
+            .join(RECORDS_DIR)
+            .join("announcements")
+    }
+
+    fn file_url(&self, path: &Utf8Path) -> ResultInner<Url> {
+        Url::from_file_path(path).map_err(|()| self.error(format!("{path} can't be a file:// URL")))
+    }
+
+    /// A URL that files in `dir` can be joined onto
+    fn dir_url(&self, dir: &Utf8Path) -> ResultInner<UnparsedUrl> {
+        let url = Url::from_directory_path(dir)
+            .map_err(|()| self.error(format!("{dir} can't be a file:// URL")))?;
+        Ok(UnparsedUrl::new(url.to_string()).expect("file:// URLs should be valid"))
+    }
+
+    async fn read_set_record(
+        &self,
+        endpoints: &Endpoints,
+        package: &PackageName,
+        public_id: &ArtifactSetId,
+    ) -> ResultInner<SetRecord> {
+        read_json_if_exists(&self.set_record(endpoints, package, public_id))
+            .await?
+            .ok_or_else(|| self.error(format!("{package} has no ArtifactSet {public_id}")))
+    }
+
+    async fn is_released(
+        &self,
+        endpoints: &Endpoints,
+        package: &PackageName,
+        public_id: &ArtifactSetId,
+    ) -> ResultInner<bool> {
+        let releases: Vec<ReleaseRecord> =
+            read_records(&self.release_records_dir(endpoints, package)).await?;
+        Ok(releases.iter().any(|r| r.artifact_set_id == *public_id))
+    }
+
+    async fn artifact_set_info(
+        &self,
+        endpoints: &Endpoints,
+        package: &PackageName,
+        record: SetRecord,
+    ) -> ResultInner<ArtifactSetInfo> {
+        let SetRecord {
+            public_id,
+            created_at,
+            created_unix_nanos: _,
+        } = record;
+        let dir = self.set_dir(endpoints, package, &public_id)?;
+        let set_download_url = self.dir_url(&dir)?;
+        let mut files = vec![];
+        for (name, size) in list_files(&dir).await? {
+            let path = dir.join(&name);
+            files.push(ArtifactSetFile {
+                sha256: sha256_file(path.clone()).await?,
+                download_url: Some(join_url(&set_download_url, &name)),
+                name,
+                size,
+            });
+        }
+        let state = if self.is_released(endpoints, package, &public_id).await? {
+            ArtifactSetState::Released
+        } else {
+            ArtifactSetState::Open
+        };
+        Ok(ArtifactSetInfo {
+            package: package.clone(),
+            public_id,
+            set_download_url: Some(set_download_url),
+            upload_url: None,
+            release_url: None,
+            announce_url: None,
+            created_at,
+            state,
+            files,
+        })
+    }
+}
+
+impl Backend for LocalBackend {
+    fn description(&self) -> String {
+        format!("local hosting in {}", self.root)
+    }
+
+    fn url(&self, endpoints: &Endpoints, route: Route) -> ResultInner<Url> {
+        let path = match route {
+            Route::CreateArtifactSet { package } | Route::ListArtifactSets { package, .. } => {
+                self.set_records_dir(endpoints, package)
+            }
+            Route::GetArtifactSet { package, public_id }
+            | Route::DeleteArtifactSet { package, public_id } => {
+                self.set_record(endpoints, package, public_id)
+            }
+            Route::UploadFile {
+                package,
+                public_id,
+                filename,
+            }
+            | Route::DownloadArtifactSetFile {
+                package,
+                public_id,
+                filename,
+            } => {
+                check_filename(filename)?;
+                self.set_dir(endpoints, package, public_id)?.join(filename)
+            }
+            Route::CreateRelease { package } | Route::ListReleases { package } => {
+                self.release_records_dir(endpoints, package)
+            }
+            Route::CreateAnnouncement => self.announcements_dir(endpoints),
+            Route::DownloadReleaseFile {
+                package,
+                tag,
+                filename,
+            } => {
+                check_filename(filename)?;
+                self.release_dir(endpoints, package, tag).join(filename)
+            }
+        };
+        self.file_url(&path)
+    }
+
+    fn create_artifact_set<'a>(
+        &'a self,
+        endpoints: &'a Endpoints,
+        package: &'a PackageName,
+    ) -> BoxFuture<'a, ResultInner<ArtifactSet>> {
+        Box::pin(async move {
+            let mut now = unix_nanos(SystemTime::now());
+            let record = loop {
+                let public_id =
+                    ArtifactSetId::new(format!("{now:x}")).expect("hex ids should be valid");
+                let record = SetRecord {
+                    created_at: rfc3339(now),
+                    created_unix_nanos: now,
+                    public_id,
+                };
+                let path = self.set_record(endpoints, package, &record.public_id);
+                if create_json(&path, &record).await? {
+                    break record;
+                }
+                // Another ArtifactSet was created at the exact same time, try the next id
+                now += 1;
+            };
+
+            let dir = self.set_dir(endpoints, package, &record.public_id)?;
+            tokio::fs::create_dir_all(&dir)
+                .await
+                .map_err(io_err(&dir))?;
+            Ok(ArtifactSet {
+                package: package.clone(),
+                public_id: record.public_id,
+                set_download_url: Some(self.dir_url(&dir)?),
+                upload_url: None,
+                release_url: None,
+                announce_url: None,
+            })
+        })
+    }
+
+    fn get_artifact_set<'a>(
+        &'a self,
+        endpoints: &'a Endpoints,
+        package: &'a PackageName,
+        public_id: &'a ArtifactSetId,
+    ) -> BoxFuture<'a, ResultInner<ArtifactSetInfo>> {
+        Box::pin(async move {
+            let record = self.read_set_record(endpoints, package, public_id).await?;
+            self.artifact_set_info(endpoints, package, record).await
+        })
+    }
+
+    fn list_artifact_sets<'a>(
+        &'a self,
+        endpoints: &'a Endpoints,
+        package: &'a PackageName,
+        filter: &'a ArtifactSetFilter,
+    ) -> BoxFuture<'a, ResultInner<Vec<ArtifactSetInfo>>> {
+        Box::pin(async move {
+            let ArtifactSetFilter {
+                unreleased,
+                older_than,
+            } = filter;
+            let mut records: Vec<SetRecord> =
+                read_records(&self.set_records_dir(endpoints, package)).await?;
+            records.sort_by_key(|r| r.created_unix_nanos);
+
+            let now = unix_nanos(SystemTime::now());
+            let mut sets = vec![];
+            for record in records {
+                let age = Duration::from_nanos(now.saturating_sub(record.created_unix_nanos));
+                if older_than.is_some_and(|older_than| age < older_than) {
+                    continue;
+                }
+                let info = self.artifact_set_info(endpoints, package, record).await?;
+                if *unreleased && info.state == ArtifactSetState::Released {
+                    continue;
+                }
+                sets.push(info);
+            }
+            Ok(sets)
+        })
+    }
+
+    fn delete_artifact_set<'a>(
+        &'a self,
+        endpoints: &'a Endpoints,
+        set: &'a ArtifactSet,
+    ) -> BoxFuture<'a, ResultInner<()>> {
+        Box::pin(async move {
+            let record = self.set_record(endpoints, &set.package, &set.public_id);
+            if read_json_if_exists::<SetRecord>(&record).await?.is_none() {
+                return Ok(());
+            }
+            if self
+                .is_released(endpoints, &set.package, &set.public_id)
+                .await?
+            {
+                return Err(self.error(format!(
+                    "a Release was created from {}'s ArtifactSet {}, so it can't be deleted",
+                    set.package, set.public_id
+                )));
+            }
+
+            let dir = self.set_dir(endpoints, &set.package, &set.public_id)?;
+            ignore_not_found(tokio::fs::remove_dir_all(&dir).await).map_err(io_err(&dir))?;
+            ignore_not_found(tokio::fs::remove_file(&record).await).map_err(io_err(&record))
+        })
+    }
+
+    fn upload_file<'a>(
+        &'a self,
+        endpoints: &'a Endpoints,
+        set: &'a ArtifactSet,
+        filename: &'a str,
+        path: &'a Utf8Path,
+        sha256: &'a str,
+    ) -> BoxFuture<'a, ResultInner<u64>> {
+        Box::pin(async move {
+            check_filename(filename)?;
+            self.read_set_record(endpoints, &set.package, &set.public_id)
+                .await?;
+
+            // Copy to a hidden file first, so a failed upload never looks like a finished one
+            let dir = self.set_dir(endpoints, &set.package, &set.public_id)?;
+            let part = dir.join(format!(".{filename}.part"));
+            tokio::fs::create_dir_all(&dir)
+                .await
+                .map_err(io_err(&dir))?;
+            let size = tokio::fs::copy(path, &part).await.map_err(io_err(path))?;
+
+            // Make sure we stored the same bytes we were given
+            let stored_sha256 = sha256_file(part.clone()).await?;
+            if !stored_sha256.eq_ignore_ascii_case(sha256) {
+                let _ = tokio::fs::remove_file(&part).await;
+                return Err(GazenotErrorInner::ChecksumMismatch {
+                    expected: sha256.to_owned(),
+                    actual: stored_sha256,
+                });
+            }
+
+            let dest = dir.join(filename);
+            tokio::fs::rename(&part, &dest)
+                .await
+                .map_err(io_err(&dest))?;
+            Ok(size)
+        })
+    }
+
+    fn create_release<'a>(
+        &'a self,
+        endpoints: &'a Endpoints,
+        set: &'a ArtifactSet,
+        key: &'a ReleaseKey,
+    ) -> BoxFuture<'a, ResultInner<Release>> {
+        Box::pin(async move {
+            let package = &set.package;
+            self.read_set_record(endpoints, package, &set.public_id)
+                .await?;
+
+            // Releases are hosted at their own path, so they get their own copy of the files.
+            // Copy them to a hidden directory first, so a failed copy never leaves a Release behind.
+            let now = unix_nanos(SystemTime::now());
+            let from = self.set_dir(endpoints, package, &set.public_id)?;
+            let to = self.release_dir(endpoints, package, &key.tag);
+            let part = self
+                .package_dir(endpoints, package)
+                .join(format!(".{}.{now}.part", encode_path_segment(&key.tag)));
+            if let Err(e) = copy_files(&from, &part).await {
+                let _ = tokio::fs::remove_dir_all(&part).await;
+                return Err(e);
+            }
+
+            // Claim the tag before moving the files into place, so we never clobber another Release
+            let record = ReleaseRecord {
+                artifact_set_id: set.public_id.clone(),
+                tag: key.tag.clone(),
+                version: key.version.clone(),
+                is_prerelease: key.is_prerelease,
+                created_at: rfc3339(now),
+                created_unix_nanos: now,
+            };
+            let path = self.release_record(endpoints, package, &key.tag);
+            let claimed = create_json(&path, &record).await;
+            if !matches!(claimed, Ok(true)) {
+                let _ = tokio::fs::remove_dir_all(&part).await;
+                claimed?;
+                return Err(self.error(format!("{package} already has a Release for {}", key.tag)));
+            }
+            if let Err(e) = tokio::fs::rename(&part, &to).await {
+                let _ = tokio::fs::remove_file(&path).await;
+                let _ = tokio::fs::remove_dir_all(&part).await;
+                return Err(io_err(&to)(e));
+            }
+
+            Ok(Release {
+                package: package.clone(),
+                tag: key.tag.clone(),
+                release_download_url: Some(self.dir_url(&to)?),
+                announce_url: set.announce_url.clone(),
+            })
+        })
+    }
+
+    fn create_announcement<'a>(
+        &'a self,
+        endpoints: &'a Endpoints,
+        releases: &'a [Release],
+        announcement: &'a AnnouncementKey,
+    ) -> BoxFuture<'a, ResultInner<()>> {
+        Box::pin(async move {
+            for release in releases {
+                let path = self.release_record(endpoints, &release.package, &release.tag);
+                if read_json_if_exists::<ReleaseRecord>(&path).await?.is_none() {
+                    return Err(self.error(format!(
+                        "{} has no Release for {}",
+                        release.package, release.tag
+                    )));
+                }
+            }
+
+            let mut now = unix_nanos(SystemTime::now());
+            let record = AnnouncementRecord {
+                releases: releases
+                    .iter()
+                    .map(|r| AnnouncedRelease {
+                        package: r.package.clone(),
+                        tag: r.tag.clone(),
+                    })
+                    .collect(),
+                body: announcement.body.clone(),
+                created_at: rfc3339(now),
+            };
+            let dir = self.announcements_dir(endpoints);
+            // Another announcement could be made at the exact same time, so keep trying names
+            while !create_json(&dir.join(format!("{now:x}.json")), &record).await? {
+                now += 1;
+            }
+            Ok(())
+        })
+    }
+
+    fn list_releases<'a>(
+        &'a self,
+        endpoints: &'a Endpoints,
+        package: &'a PackageName,
+    ) -> BoxFuture<'a, ResultInner<ReleaseList>> {
+        Box::pin(async move {
+            let mut records: Vec<ReleaseRecord> =
+                read_records(&self.release_records_dir(endpoints, package)).await?;
+            // Newest first, like The Abyss
+            records.sort_by_key(|r| std::cmp::Reverse(r.created_unix_nanos));
+
+            let mut releases = vec![];
+            for record in records {
+                let dir = self.release_dir(endpoints, package, &record.tag);
+                let release_download_url = self.dir_url(&dir)?;
+                let artifacts = list_files(&dir)
+                    .await?
+                    .into_iter()
+                    .map(|(name, _)| ReleaseArtifact {
+                        download_url: join_url(&release_download_url, &name),
+                        name,
+                    })
+                    .collect();
+                releases.push(ReleaseInfo {
+                    tag: record.tag,
+                    version: record.version,
+                    is_prerelease: record.is_prerelease,
+                    created_at: record.created_at,
+                    release_download_url: Some(release_download_url),
+                    artifacts,
+                });
+            }
+            Ok(ReleaseList {
+                package: package.clone(),
+                releases,
+            })
+        })
+    }
+
+    fn download_file<'a>(
+        &'a self,
+        url: &'a Url,
+        dest: &'a Utf8Path,
+    ) -> BoxFuture<'a, ResultInner<()>> {
+        Box::pin(async move {
+            // URLs can come from deserialized ArtifactSets, which could have been tampered with,
+            // so don't hand out anything that isn't ours
+            let path = (url.scheme() == "file")
+                .then(|| url.to_file_path().ok())
+                .flatten()
+                .and_then(|path| Utf8PathBuf::from_path_buf(path).ok())
+                .filter(|path| path.starts_with(&self.root))
+                .ok_or_else(|| self.error(format!("{url} isn't hosted in {}", self.root)))?;
+
+            let part = Utf8PathBuf::from(format!("{dest}.part"));
+            tokio::fs::copy(&path, &part).await.map_err(io_err(&path))?;
+            tokio::fs::rename(&part, dest).await.map_err(io_err(dest))
+        })
+    }
+}
+
+/// Append a filename to a URL from [`LocalBackend::dir_url`][]
+fn join_url(dir_url: &UnparsedUrl, filename: &str) -> UnparsedUrl {
+    UnparsedUrl::new(format!("{dir_url}{}", encode_path_segment(filename)))
+        .expect("file:// URLs should be valid")
+}
+
+fn io_err(path: &Utf8Path) -> impl FnOnce(std::io::Error) -> GazenotErrorInner {
+    let path = path.to_owned();
+    move |details| GazenotErrorInner::Io { path, details }
+}
+
+fn ignore_not_found(result: std::io::Result<()>) -> std::io::Result<()> {
+    match result {
+        Err(e) if e.kind() == ErrorKind::NotFound => Ok(()),
+        result => result,
+    }
+}
+
+async fn read_json_if_exists<T: DeserializeOwned>(path: &Utf8Path) -> ResultInner<Option<T>> {
+    match tokio::fs::read(path).await {
+        Ok(contents) => Ok(Some(serde_json::from_slice(&contents)?)),
+        Err(e) if e.kind() == ErrorKind::NotFound => Ok(None),
+        Err(e) => Err(io_err(path)(e)),
+    }
+}
+
+/// Read every json record in a directory (which doesn't have to exist)
+async fn read_records<T: DeserializeOwned>(dir: &Utf8Path) -> ResultInner<Vec<T>> {
+    let mut records = vec![];
+    for (name, _) in list_files(dir).await? {
+        if name.ends_with(".json") {
+            let path = dir.join(&name);
+            records.extend(read_json_if_exists(&path).await?);
+        }
+    }
+    Ok(records)
+}
+
+/// Write json to a new file, returning false if the file already existed
+async fn create_json(path: &Utf8Path, value: &impl Serialize) -> ResultInner<bool> {
+    if let Some(parent) = path.parent() {
+        tokio::fs::create_dir_all(parent)
+            .await
+            .map_err(io_err(parent))?;
+    }
+    let file = tokio::fs::OpenOptions::new()
+        .write(true)
+        .create_new(true)
+        .open(path)
+        .await;
+    let mut file = match file {
+        Err(e) if e.kind() == ErrorKind::AlreadyExists => return Ok(false),
+        file => file.map_err(io_err(path))?,
+    };
+    let contents = serde_json::to_vec_pretty(value)?;
+    file.write_all(&contents).await.map_err(io_err(path))?;
+    file.flush().await.map_err(io_err(path))?;
+    Ok(true)
+}
+
+/// Copy the (non-hidden) files in one directory to a new directory
+async fn copy_files(from: &Utf8Path, to: &Utf8Path) -> ResultInner<()> {
+    tokio::fs::create_dir_all(to).await.map_err(io_err(to))?;
+    for (name, _) in list_files(from).await? {
+        let dest = to.join(&name);
+        tokio::fs::copy(from.join(&name), &dest)
+            .await
+            .map_err(io_err(&dest))?;
+    }
+    Ok(())
+}
+
+/// The names and sizes of the (non-hidden) files in a directory, sorted by name
+///
+/// A directory that doesn't exist is treated as empty.
+async fn list_files(dir: &Utf8Path) -> ResultInner<Vec<(String, u64)>> {
+    let mut entries = match tokio::fs::read_dir(dir).await {
+        Ok(entries) => entries,
+        Err(e) if e.kind() == ErrorKind::NotFound => return Ok(vec![]),
+        Err(e) => return Err(io_err(dir)(e)),
+    };
+    let mut files = vec![];
+    while let Some(entry) = entries.next_entry().await.map_err(io_err(dir))? {
+        let Ok(name) = entry.file_name().into_string() else {
+            continue;
+        };
+        let metadata = entry.metadata().await.map_err(io_err(dir))?;
+        if !name.starts_with('.') && metadata.is_file() {
+            files.push((name, metadata.len()));
+        }
+    }
+    files.sort();
+    Ok(files)
+}
+
+fn unix_nanos(time: SystemTime) -> u64 {
+    let since_epoch = time.duration_since(UNIX_EPOCH).unwrap_or_default();
+    since_epoch.as_nanos() as u64
+}
+
+/// Format a time as an RFC 3339 timestamp in UTC, to the second
+fn rfc3339(unix_nanos: u64) -> UnparsedTimestamp {
+    let time = UNIX_EPOCH + Duration::from_nanos(unix_nanos);
+    humantime::format_rfc3339_seconds(time).to_string()
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::{testing::scratch_dir, Gazenot, ReleaseTag};
+
+    fn client(root: &Utf8Path) -> Gazenot {
+        Gazenot::builder("github".parse().unwrap(), "axodotdev".parse().unwrap())
+            .backend(LocalBackend::new(root).unwrap())
+            .build()
+            .unwrap()
+    }
+
+    fn tag() -> ReleaseTag {
+        "v1.0.0".parse().unwrap()
+    }
+
+    #[test]
+    fn timestamps_are_rfc3339() {
+        assert_eq!(rfc3339(0), "1970-01-01T00:00:00Z");
+        assert_eq!(rfc3339(1_700_000_000_123_456_789), "2023-11-14T22:13:20Z");
+    }
+
+    #[tokio::test]
+    async fn round_trip() {
+        let dir = scratch_dir("backend-round-trip");
+        let abyss = client(&dir.join("root"));
+        let files = vec![dir.join("app.tar.gz"), dir.join("app.zip")];
+        std::fs::write(&files[0], "tarball").unwrap();
+        std::fs::write(&files[1], "zipfile").unwrap();
+
+        let sets = abyss
+            .create_artifact_sets(["app1".parse().unwrap()])
+            .await
+            .unwrap();
+        let uploads = abyss
+            .upload_files(sets.iter().map(|set| (set, files.clone())))
+            .await
+            .unwrap();
+        let info = abyss
+            .get_artifact_set(sets[0].package.clone(), sets[0].public_id.clone())
+            .await
+            .unwrap();
+        assert_eq!(info.state, ArtifactSetState::Open);
+        assert_eq!(info.file("app.zip").unwrap().sha256, uploads[1].sha256);
+        humantime::parse_rfc3339(&info.created_at).unwrap();
+
+        let key = ReleaseKey::from_tag("app1", tag()).unwrap();
+        let releases = abyss
+            .create_releases(sets.iter().map(|set| (set, key.clone())))
+            .await
+            .unwrap();
+        let announcement = AnnouncementKey {
+            body: "# v1.0.0".to_owned(),
+        };
+        abyss
+            .create_announcements(&releases, announcement)
+            .await
+            .unwrap();
+
+        let list = abyss.list_releases("app1".parse().unwrap()).await.unwrap();
+        assert_eq!(list.releases.len(), 1);
+        assert_eq!(list.releases[0].tag, tag());
+        assert_eq!(list.releases[0].version, "1.0.0");
+        let mut artifacts = list.releases[0]
+            .artifacts
+            .iter()
+            .map(|artifact| artifact.name.as_str())
+            .collect::<Vec<_>>();
+        artifacts.sort();
+        assert_eq!(artifacts, ["app.tar.gz", "app.zip"]);
+
+        // Both the ArtifactSet and the Release serve the files
+        let from_set = dir.join("from-set");
+        let from_release = dir.join("from-release");
+        let names = vec!["app.tar.gz".to_owned(), "app.zip".to_owned()];
+        abyss
+            .download_files([(&sets[0], names.clone())], &from_set)
+            .await
+            .unwrap();
+        abyss
+            .download_files([(&releases[0], names)], &from_release)
+            .await
+            .unwrap();
+        for dest in [from_set, from_release] {
+            assert_eq!(std::fs::read(dest.join("app.tar.gz")).unwrap(), b"tarball");
+            assert_eq!(std::fs::read(dest.join("app.zip")).unwrap(), b"zipfile");
+        }
+
+        let info = abyss
+            .get_artifact_set(sets[0].package.clone(), sets[0].public_id.clone())
+            .await
+            .unwrap();
+        assert_eq!(info.state, ArtifactSetState::Released);
+    }
+
+    #[tokio::test]
+    async fn refuses_to_release_twice() {
+        let dir = scratch_dir("backend-release-twice");
+        let abyss = client(&dir);
+        let sets = abyss
+            .create_artifact_sets(["app1".parse().unwrap(), "app1".parse().unwrap()])
+            .await
+            .unwrap();
+        let key = ReleaseKey::from_tag("app1", tag()).unwrap();
+        abyss
+            .create_releases([(&sets[0], key.clone())])
+            .await
+            .unwrap();
+        assert!(abyss.create_releases([(&sets[1], key)]).await.is_err());
+    }
+
+    #[tokio::test]
+    async fn failed_releases_can_be_retried() {
+        let dir = scratch_dir("backend-release-retry");
+        let abyss = client(&dir);
+        let file = dir.join("app.tar.gz");
+        std::fs::write(&file, "tarball").unwrap();
+        let sets = abyss
+            .create_artifact_sets(["app1".parse().unwrap()])
+            .await
+            .unwrap();
+        abyss.upload_files([(&sets[0], vec![file])]).await.unwrap();
+
+        // Something already in the way of the Release's files makes it fail after claiming the tag
+        let package_dir = dir.join("github/axodotdev/app1");
+        let release_dir = package_dir.join("v1.0.0");
+        std::fs::create_dir_all(&release_dir).unwrap();
+        std::fs::write(release_dir.join("stray"), "stray").unwrap();
+        let key = ReleaseKey::from_tag("app1", tag()).unwrap();
+        assert!(abyss
+            .create_releases([(&sets[0], key.clone())])
+            .await
+            .is_err());
+        assert!(abyss
+            .list_releases("app1".parse().unwrap())
+            .await
+            .unwrap()
+            .releases
+            .is_empty());
+        let leftovers = std::fs::read_dir(&package_dir)
+            .unwrap()
+            .map(|entry| entry.unwrap().file_name().into_string().unwrap())
+            .filter(|name| name.ends_with(".part"))
+            .collect::<Vec<_>>();
+        assert_eq!(leftovers, Vec::<String>::new());
+
+        std::fs::remove_dir_all(&release_dir).unwrap();
+        abyss.create_releases([(&sets[0], key)]).await.unwrap();
+        assert_eq!(
+            std::fs::read(release_dir.join("app.tar.gz")).unwrap(),
+            b"tarball"
+        );
+    }
+
+    #[tokio::test]
+    async fn tampered_ids_stay_under_root() {
+        let dir = scratch_dir("backend-tampered-id");
+        let abyss = client(&dir.join("root"));
+        let file = dir.join("app.tar.gz");
+        std::fs::write(&file, "tarball").unwrap();
+        for public_id in ["..", "a/b", "."] {
+            let set: ArtifactSet = serde_json::from_value(serde_json::json!({
+                "package": "app1",
+                "public_id": public_id,
+            }))
+            .unwrap();
+            let err = abyss
+                .upload_files([(&set, vec![file.clone()])])
+                .await
+                .unwrap_err();
+            assert!(
+                matches!(*err.cause, GazenotErrorInner::InvalidValue(_)),
+                "{public_id}: {err:?}"
+            );
+        }
+        assert!(!dir.join("root/github/axodotdev/app.tar.gz").exists());
+        assert!(!dir.join("root/github/axodotdev/app1/a").exists());
+    }
+
+    #[tokio::test]
+    async fn only_serves_files_under_root() {
+        let dir = scratch_dir("backend-outside-root");
+        let backend = LocalBackend::new(dir.join("root")).unwrap();
+        std::fs::write(dir.join("secret"), "secret").unwrap();
+        let url = Url::from_file_path(dir.join("secret")).unwrap();
+        let dest = dir.join("dest");
+        assert!(backend.download_file(&url, &dest).await.is_err());
+        assert!(!dest.exists());
+    }
+}
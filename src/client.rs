@@ -1,20 +1,52 @@
-use std::{str::FromStr, sync::Arc};
+use std::{
+    collections::{HashMap, HashSet},
+    future::Future,
+    pin::Pin,
+    sync::{
+        atomic::{AtomicU64, Ordering},
+        Arc, Mutex,
+    },
+    task::{Context, Poll},
+    time::{Duration, Instant},
+};
 
 use crate::{
-    error::*, AnnouncementKey, ArtifactSet, ArtifactSetId, Owner, PackageName, Release, ReleaseKey,
-    ReleaseList, ReleaseTag, SourceHost, UnparsedUrl, UnparsedVersion,
+    backend::Backend,
+    credentials::{CredentialProvider, CredentialRequest, EnvVarCredentials, StaticCredentials},
+    dry_run::{self, PlannedRequest},
+    error::*,
+    report::{Report, ReportEntry, Reportable},
+    retry::RetryPolicy,
+    route::{join_segments, Endpoints, Route},
+    AnnouncementKey, ArtifactSet, ArtifactSetFile, ArtifactSetId, ArtifactSetInfo,
+    ArtifactSetState, Owner, PackageName, Release, ReleaseInfo, ReleaseKey, ReleaseList,
+    ReleaseTag, SourceHost, UnparsedTimestamp, UnparsedUrl, UnparsedVersion, UploadedFile,
 };
-use axoasset::LocalAsset;
-use camino::Utf8PathBuf;
+use camino::{Utf8Path, Utf8PathBuf};
+use futures_util::{stream::FuturesUnordered, StreamExt, TryStreamExt};
 use reqwest::{
-    header::{HeaderMap, HeaderName, HeaderValue},
-    Client, Url,
+    header::{
+        HeaderMap, HeaderName, HeaderValue, CONTENT_LENGTH, CONTENT_RANGE, CONTENT_TYPE, ETAG,
+        IF_RANGE, LAST_MODIFIED, RANGE,
+    },
+    Body, Client, Method, Request, RequestBuilder, Response, StatusCode, Url,
 };
+use schemars::JsonSchema;
 use serde::{Deserialize, Serialize};
+use sha2::{Digest, Sha256};
+use tokio::{
+    io::AsyncWriteExt,
+    sync::{OwnedSemaphorePermit, Semaphore},
+    task::JoinHandle,
+};
+use tokio_util::io::ReaderStream;
 
 /// A domain (as in part of a URL)
 type Domain = String;
 
+/// Header for telling the server the SHA-256 of an upload
+const CHECKSUM_HEADER: &str = "x-axo-checksum-sha256";
+
 /// A client for The Abyss
 ///
 /// This type intentionally does not implement Debug, to avoid leaking authentication secrets.
@@ -26,18 +58,34 @@ pub struct Gazenot(Arc<GazenotInner>);
 ///
 /// DO NOT IMPLEMENT DEBUG ON THIS TYPE, IT CONTAINS SECRET API KEYS AT RUNTIME
 pub struct GazenotInner {
-    /// Domain for the main abyss API
-    api_server: Domain,
-    /// Domain where ArtifactSet downloads are GETtable from
-    hosting_server: Domain,
+    /// Where all the servers are, and who we're talking about
+    pub(crate) endpoints: Endpoints,
+    /// Timeout for requests (or for stalls in uploads)
+    timeout: Duration,
     /// Auth for requests
     auth_headers: HeaderMap,
-    /// Owner of the project
-    owner: Owner,
-    /// Name of the project
-    source_host: SourceHost,
+    /// Whether requests carry credentials (even placeholder ones, in a dry run)
+    needs_auth: bool,
+    /// Why a dry run couldn't load credentials, and has placeholders in auth_headers
+    missing_credentials: Option<String>,
     /// reqwest client
     client: Client,
+    /// Limit on requests in flight for the whole client
+    concurrency: Arc<Semaphore>,
+    /// Limits on requests in flight for specific operations
+    concurrency_for: HashMap<Operation, Arc<Semaphore>>,
+    /// How to retry failed requests
+    retry_policy: RetryPolicy,
+    /// How to handle failures in batch operations
+    pub(crate) failure_mode: FailureMode,
+    /// Hosts we're willing to send auth_headers to
+    allowed_hosts: Vec<Domain>,
+    /// What batch operations have done, if we're recording that
+    pub(crate) report: Option<Mutex<Report>>,
+    /// Requests we would have sent, if this is a dry run
+    pub(crate) dry_run: Option<Mutex<Vec<PlannedRequest>>>,
+    /// Where ArtifactSets and Releases are kept, if not The Abyss
+    backend: Option<Arc<dyn Backend>>,
 }
 
 impl std::ops::Deref for Gazenot {
@@ -47,8 +95,412 @@ impl std::ops::Deref for Gazenot {
     }
 }
 
+/// A kind of batch operation a [`Gazenot`][] client can perform
+///
+/// Used to configure settings for specific operations, such as
+/// [`GazenotBuilder::max_concurrency_for`][].
+#[derive(Serialize, Deserialize, Debug, Clone, Copy, PartialEq, Eq, Hash, JsonSchema)]
+#[serde(rename_all = "snake_case")]
+pub enum Operation {
+    /// [`Gazenot::create_artifact_sets`][]
+    CreateArtifactSets,
+    /// [`Gazenot::get_artifact_set`][]
+    GetArtifactSet,
+    /// [`Gazenot::list_artifact_sets`][]
+    ListArtifactSets,
+    /// [`Gazenot::delete_artifact_sets`][]
+    DeleteArtifactSets,
+    /// [`Gazenot::upload_files`][]
+    UploadFiles,
+    /// [`Gazenot::create_releases`][]
+    CreateReleases,
+    /// [`Gazenot::create_announcements`][]
+    CreateAnnouncements,
+    /// [`Gazenot::list_releases_many`][]
+    ListReleases,
+    /// [`Gazenot::download_files`][]
+    DownloadFiles,
+}
+
+impl Operation {
+    /// Whether this operation can be safely repeated if we're not sure it went through
+    pub fn is_idempotent(&self) -> bool {
+        match self {
+            Operation::GetArtifactSet
+            | Operation::ListArtifactSets
+            | Operation::DeleteArtifactSets
+            | Operation::UploadFiles
+            | Operation::ListReleases
+            | Operation::DownloadFiles => true,
+            // Repeating a release that went through fails with a conflict
+            Operation::CreateArtifactSets
+            | Operation::CreateReleases
+            | Operation::CreateAnnouncements => false,
+        }
+    }
+}
+
+/// Something files can be downloaded from, see [`Gazenot::download_files`][]
+#[derive(Debug, Clone, Copy)]
+pub enum DownloadSource<'a> {
+    /// Download from an ArtifactSet (even if it hasn't been released yet)
+    ArtifactSet(&'a ArtifactSet),
+    /// Download from a Release
+    Release(&'a Release),
+}
+
+impl DownloadSource<'_> {
+    /// The package the files belong to
+    pub fn package(&self) -> &PackageName {
+        match self {
+            DownloadSource::ArtifactSet(set) => &set.package,
+            DownloadSource::Release(release) => &release.package,
+        }
+    }
+}
+
+impl<'a> From<&'a ArtifactSet> for DownloadSource<'a> {
+    fn from(set: &'a ArtifactSet) -> Self {
+        DownloadSource::ArtifactSet(set)
+    }
+}
+
+impl<'a> From<&'a Release> for DownloadSource<'a> {
+    fn from(release: &'a Release) -> Self {
+        DownloadSource::Release(release)
+    }
+}
+
+/// Which ArtifactSets [`Gazenot::list_artifact_sets`][] should return
+///
+/// The default returns every ArtifactSet of the package.
+#[derive(Debug, Clone, Default)]
+pub struct ArtifactSetFilter {
+    /// Only return ArtifactSets that no Release was created from
+    pub unreleased: bool,
+    /// Only return ArtifactSets that were created at least this long ago
+    pub older_than: Option<Duration>,
+}
+
+impl ArtifactSetFilter {
+    /// Only return ArtifactSets that no Release was created from
+    pub fn unreleased(mut self) -> Self {
+        self.unreleased = true;
+        self
+    }
+
+    /// Only return ArtifactSets that were created at least this long ago
+    pub fn older_than(mut self, age: Duration) -> Self {
+        self.older_than = Some(age);
+        self
+    }
+}
+
+/// How batch operations handle some of their parts failing
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq)]
+pub enum FailureMode {
+    /// Return the first error as soon as it happens, cancelling the rest of the batch
+    #[default]
+    FailFast,
+    /// Let every part of the batch finish, and report every failure
+    ///
+    /// If more than one part fails, the error will have all of them as related errors.
+    CollectAll,
+}
+
+/// Where a [`Gazenot`][] client gets its authentication from
+///
+/// This type intentionally does not implement Debug, to avoid leaking authentication secrets.
+#[derive(Clone, Default)]
+pub enum AuthSource {
+    /// Read an Axo Releases Token from the AXO_RELEASES_TOKEN environment variable
+    #[default]
+    Env,
+    /// Get an Axo Releases Token from a custom provider, see [`GazenotBuilder::credentials`][]
+    Provider(Arc<dyn CredentialProvider>),
+    /// Don't authenticate at all
+    ///
+    /// This is only suitable for public endpoints, see [`Gazenot::new_unauthed`][].
+    None,
+}
+
+/// A builder for [`Gazenot`][] clients, see [`Gazenot::builder`][]
+///
+/// This type intentionally does not implement Debug, to avoid leaking authentication secrets.
+#[derive(Clone)]
+pub struct GazenotBuilder {
+    source_host: SourceHost,
+    owner: Owner,
+    api_server: Domain,
+    hosting_server: Domain,
+    hosting_owner_subdomain: bool,
+    scheme: String,
+    timeout: Duration,
+    auth: AuthSource,
+    user_agent: String,
+    max_concurrency: usize,
+    max_concurrency_for: HashMap<Operation, usize>,
+    retry_policy: RetryPolicy,
+    failure_mode: FailureMode,
+    allowed_hosts: Vec<Domain>,
+    record_report: bool,
+    dry_run: bool,
+    backend: Option<Arc<dyn Backend>>,
+}
+
+impl GazenotBuilder {
+    /// Domain for the production abyss API
+    pub const DEFAULT_API_SERVER: &'static str = "axo-abyss.fly.dev";
+    /// Domain for production ArtifactSet hosting
+    pub const DEFAULT_HOSTING_SERVER: &'static str = "artifacts.axodotdev.host";
+    /// The default URL scheme for all servers
+    pub const DEFAULT_SCHEME: &'static str = "https";
+    /// The default timeout for requests
+    pub const DEFAULT_TIMEOUT: Duration = Duration::from_secs(10);
+    /// The default maximum number of requests in flight at once
+    pub const DEFAULT_MAX_CONCURRENCY: usize = 8;
+
+    fn new(source_host: SourceHost, owner: Owner) -> Self {
+        Self {
+            source_host,
+            owner,
+            api_server: Self::DEFAULT_API_SERVER.to_owned(),
+            hosting_server: Self::DEFAULT_HOSTING_SERVER.to_owned(),
+            hosting_owner_subdomain: true,
+            scheme: Self::DEFAULT_SCHEME.to_owned(),
+            timeout: Self::DEFAULT_TIMEOUT,
+            auth: AuthSource::default(),
+            user_agent: concat!("gazenot/", env!("CARGO_PKG_VERSION")).to_owned(),
+            max_concurrency: Self::DEFAULT_MAX_CONCURRENCY,
+            max_concurrency_for: HashMap::new(),
+            retry_policy: RetryPolicy::default(),
+            failure_mode: FailureMode::default(),
+            allowed_hosts: vec![],
+            record_report: false,
+            dry_run: false,
+            backend: None,
+        }
+    }
+
+    /// Set the source hosting provider (e.g. "github")
+    pub fn source_host(mut self, source_host: SourceHost) -> Self {
+        self.source_host = source_host;
+        self
+    }
+
+    /// Set the owner of the packages
+    pub fn owner(mut self, owner: Owner) -> Self {
+        self.owner = owner;
+        self
+    }
+
+    /// Set the domain (and optionally port) for the main abyss API
+    ///
+    /// e.g. "axo-abyss.fly.dev" or "127.0.0.1:8080"
+    pub fn api_server(mut self, api_server: impl Into<String>) -> Self {
+        self.api_server = api_server.into();
+        self
+    }
+
+    /// Set the domain (and optionally port) where ArtifactSet downloads are GETtable from
+    ///
+    /// e.g. "artifacts.axodotdev.host" or "127.0.0.1:8080"
+    pub fn hosting_server(mut self, hosting_server: impl Into<String>) -> Self {
+        self.hosting_server = hosting_server.into();
+        self
+    }
+
+    /// Set whether the owner is a subdomain of the hosting server (the default)
+    ///
+    /// If true, downloads look like `:owner.:hosting_server/:package/...`.
+    /// If false, downloads look like `:hosting_server/:owner/:package/...`,
+    /// which is useful for servers that are just an IP address.
+    pub fn hosting_owner_subdomain(mut self, enabled: bool) -> Self {
+        self.hosting_owner_subdomain = enabled;
+        self
+    }
+
+    /// Set the URL scheme used for all servers (e.g. "https" or "http")
+    pub fn scheme(mut self, scheme: impl Into<String>) -> Self {
+        self.scheme = scheme.into();
+        self
+    }
+
+    /// Set the timeout for requests
+    ///
+    /// For most requests this bounds the entire request, but file uploads
+    /// can take as long as they need, and only time out if they go this long
+    /// without making any progress. Once a file has been sent, the server can
+    /// take as long as it needs to respond.
+    pub fn timeout(mut self, timeout: Duration) -> Self {
+        self.timeout = timeout;
+        self
+    }
+
+    /// Set where authentication comes from
+    pub fn auth(mut self, auth: AuthSource) -> Self {
+        self.auth = auth;
+        self
+    }
+
+    /// Get the Axo Releases Token from the given provider
+    ///
+    /// See the [`credentials`][crate::credentials] module for the built-in providers.
+    pub fn credentials(self, provider: impl CredentialProvider + 'static) -> Self {
+        self.auth(AuthSource::Provider(Arc::new(provider)))
+    }
+
+    /// Set the User-Agent sent with every request
+    pub fn user_agent(mut self, user_agent: impl Into<String>) -> Self {
+        self.user_agent = user_agent.into();
+        self
+    }
+
+    /// Set the maximum number of requests this client will have in flight at once
+    ///
+    /// This limit is shared by every operation on the client (and its clones),
+    /// even if several are running at the same time. Values below 1 are treated as 1.
+    pub fn max_concurrency(mut self, max: usize) -> Self {
+        self.max_concurrency = max.max(1);
+        self
+    }
+
+    /// Set the maximum number of requests a specific kind of operation will have in flight at once
+    ///
+    /// This is applied in addition to [`GazenotBuilder::max_concurrency`][],
+    /// so it's only useful for making a specific operation more restrictive.
+    /// Values below 1 are treated as 1.
+    pub fn max_concurrency_for(mut self, operation: Operation, max: usize) -> Self {
+        self.max_concurrency_for.insert(operation, max.max(1));
+        self
+    }
+
+    /// Set how failed requests are retried
+    pub fn retry_policy(mut self, retry_policy: RetryPolicy) -> Self {
+        self.retry_policy = retry_policy;
+        self
+    }
+
+    /// Set how batch operations handle some of their parts failing
+    pub fn failure_mode(mut self, failure_mode: FailureMode) -> Self {
+        self.failure_mode = failure_mode;
+        self
+    }
+
+    /// Allow sending credentials to another host (and optionally port)
+    ///
+    /// By default credentials are only ever sent to the api server, so that URLs
+    /// in a tampered ArtifactSet can't be used to steal them. Only use this if
+    /// the real API is reachable under several names.
+    pub fn allow_host(mut self, host: impl Into<String>) -> Self {
+        self.allowed_hosts.push(host.into());
+        self
+    }
+
+    /// Keep a [`Report`][crate::report::Report] of everything batch operations do
+    ///
+    /// See [`Gazenot::take_report`][] for getting it back out.
+    pub fn record_report(mut self, enabled: bool) -> Self {
+        self.record_report = enabled;
+        self
+    }
+
+    /// Don't send any requests that would change something, just record them
+    ///
+    /// The reads a dry run still does are sent with the usual credentials. If those can't
+    /// be loaded the dry run still works, but with a placeholder token, and any reads
+    /// fail instead of being sent without credentials. See the
+    /// [`dry_run`][crate::dry_run] module for details.
+    pub fn dry_run(mut self, enabled: bool) -> Self {
+        self.dry_run = enabled;
+        self
+    }
+
+    /// Keep ArtifactSets and Releases somewhere other than The Abyss
+    ///
+    /// See the [`backend`][crate::backend] module for details. Other backends have no use
+    /// for credentials for The Abyss, so none are loaded.
+    pub fn backend(mut self, backend: impl Backend + 'static) -> Self {
+        self.backend = Some(Arc::new(backend));
+        self
+    }
+
+    /// Create the client
+    ///
+    /// This is where authentication is loaded, so it's an error for it to be missing.
+    pub fn build(self) -> Result<Gazenot> {
+        const DESC: &str = "create http client for axodotdev hosting (abyss)";
+
+        let request = CredentialRequest {
+            source_host: &self.source_host,
+            owner: &self.owner,
+            scheme: &self.scheme,
+            api_server: &self.api_server,
+        };
+        let needs_auth = self.backend.is_none() && !matches!(self.auth, AuthSource::None);
+        let mut missing_credentials = None;
+        let loaded = match (&self.backend, &self.auth) {
+            (Some(_), _) | (None, AuthSource::None) => Ok(HeaderMap::new()),
+            (None, AuthSource::Env) => auth_headers(&EnvVarCredentials::default(), &request),
+            (None, AuthSource::Provider(provider)) => auth_headers(provider.as_ref(), &request),
+        };
+        let auth_headers = match loaded {
+            Ok(headers) => headers,
+            // Only the reads of a dry run are really sent, so it can do without credentials
+            // by pretending to have them, and refusing to do any reads
+            Err(e) if self.dry_run => {
+                missing_credentials = Some(e.to_string());
+                auth_headers(&StaticCredentials::new(dry_run::REDACTED), &request)
+                    .map_err(|e| GazenotError::new("initializing Abyss authentication", e))?
+            }
+            Err(e) => return Err(GazenotError::new("initializing Abyss authentication", e)),
+        };
+
+        // Deliberately no whole-request timeout on the client, as that would kill large uploads.
+        // Instead regular requests get `timeout` applied individually, while uploads
+        // only time out if they stop making progress.
+        let client = Client::builder()
+            .connect_timeout(self.timeout)
+            .user_agent(self.user_agent)
+            .build()
+            .map_err(|e| GazenotError::new(DESC, e))?;
+
+        let allowed_hosts = std::iter::once(self.api_server.clone())
+            .chain(self.allowed_hosts)
+            .collect();
+
+        Ok(Gazenot(Arc::new(GazenotInner {
+            endpoints: Endpoints {
+                scheme: self.scheme,
+                api_server: self.api_server,
+                hosting_server: self.hosting_server,
+                hosting_owner_subdomain: self.hosting_owner_subdomain,
+                source_host: self.source_host,
+                owner: self.owner,
+            },
+            timeout: self.timeout,
+            auth_headers,
+            needs_auth,
+            missing_credentials,
+            client,
+            concurrency: Arc::new(Semaphore::new(self.max_concurrency)),
+            concurrency_for: self
+                .max_concurrency_for
+                .into_iter()
+                .map(|(op, max)| (op, Arc::new(Semaphore::new(max))))
+                .collect(),
+            retry_policy: self.retry_policy,
+            failure_mode: self.failure_mode,
+            allowed_hosts,
+            report: self.record_report.then(Mutex::default),
+            dry_run: self.dry_run.then(Mutex::default),
+            backend: self.backend,
+        })))
+    }
+}
+
 #[derive(Deserialize, Debug, Clone)]
-struct Response<T> {
+struct ApiResponse<T> {
     success: bool,
     result: Option<T>,
     errors: Option<Vec<String>>,
@@ -62,11 +514,72 @@ struct BasicResponse {
 
 #[derive(Deserialize, Debug, Clone)]
 struct ArtifactSetResponse {
+    #[serde(deserialize_with = "crate::types::lenient")]
+    public_id: ArtifactSetId,
+    #[serde(default, deserialize_with = "crate::types::lenient_option")]
+    set_download_url: Option<UnparsedUrl>,
+    #[serde(default, deserialize_with = "crate::types::lenient_option")]
+    upload_url: Option<UnparsedUrl>,
+    #[serde(default, deserialize_with = "crate::types::lenient_option")]
+    release_url: Option<UnparsedUrl>,
+    #[serde(default, deserialize_with = "crate::types::lenient_option")]
+    announce_url: Option<UnparsedUrl>,
+}
+
+#[derive(Deserialize, Debug, Clone)]
+struct ArtifactSetInfoResponse {
+    #[serde(deserialize_with = "crate::types::lenient")]
     public_id: ArtifactSetId,
+    #[serde(default, deserialize_with = "crate::types::lenient_option")]
     set_download_url: Option<UnparsedUrl>,
+    #[serde(default, deserialize_with = "crate::types::lenient_option")]
     upload_url: Option<UnparsedUrl>,
+    #[serde(default, deserialize_with = "crate::types::lenient_option")]
     release_url: Option<UnparsedUrl>,
+    #[serde(default, deserialize_with = "crate::types::lenient_option")]
     announce_url: Option<UnparsedUrl>,
+    created_at: UnparsedTimestamp,
+    state: ArtifactSetState,
+    #[serde(default)]
+    files: Vec<ArtifactSetFile>,
+}
+
+impl ArtifactSetInfoResponse {
+    /// Add extra context to make the response more useful in code
+    fn into_info(self, package: PackageName) -> ArtifactSetInfo {
+        let ArtifactSetInfoResponse {
+            public_id,
+            set_download_url,
+            upload_url,
+            release_url,
+            announce_url,
+            created_at,
+            state,
+            files,
+        } = self;
+        ArtifactSetInfo {
+            package,
+            public_id,
+            set_download_url,
+            upload_url,
+            release_url,
+            announce_url,
+            created_at,
+            state,
+            files,
+        }
+    }
+}
+
+#[derive(Deserialize, Debug, Clone)]
+struct ListArtifactSetsResponse {
+    artifact_sets: Vec<ArtifactSetInfoResponse>,
+}
+
+#[derive(Deserialize, Debug, Clone)]
+struct UploadResponse {
+    /// SHA-256 of what the server received, hex-encoded
+    sha256: String,
 }
 
 #[derive(Serialize, Debug, Clone)]
@@ -76,7 +589,7 @@ struct CreateReleaseRequest {
 
 #[derive(Serialize, Debug, Clone)]
 struct CreateReleaseRequestInner {
-    artifact_set_id: String,
+    artifact_set_id: ArtifactSetId,
     tag: ReleaseTag,
     version: UnparsedVersion,
     is_prerelease: bool,
@@ -84,6 +597,7 @@ struct CreateReleaseRequestInner {
 
 #[derive(Serialize, Deserialize, Debug, Clone)]
 struct ReleaseResponse {
+    #[serde(default, deserialize_with = "crate::types::lenient_option")]
     release_download_url: Option<UnparsedUrl>,
 }
 
@@ -101,77 +615,132 @@ struct AnnounceReleaseRequest {
 
 #[derive(Deserialize, Debug, Clone)]
 struct ListReleasesResponse {
-    // TBD
+    releases: Vec<ReleaseInfo>,
 }
 
 impl Gazenot {
     /// Gaze Not Into The Abyss, Lest You Become A Release Engineer
     ///
     /// This is the vastly superior alias for [`Gazenot::new`].
-    pub fn into_the_abyss(
-        source_host: impl Into<SourceHost>,
-        owner: impl Into<Owner>,
-    ) -> Result<Self> {
+    pub fn into_the_abyss(source_host: SourceHost, owner: Owner) -> Result<Self> {
         Self::new(source_host, owner)
     }
 
     /// Create a new authenticated client for The Abyss
     ///
     /// Authentication requires an Axo Releases Token, whose value
-    /// is currently sourced from an AXO_RELEASES_TOKEN environment variable.
+    /// is sourced from an AXO_RELEASES_TOKEN environment variable.
     /// It's an error for that variable to not be properly set.
+    /// Use [`GazenotBuilder::credentials`][] to get it from somewhere else.
     ///
     /// This is the vastly inferior alias for [`Gazenot::into_the_abyss`].
     ///
     /// See also, `[Abyss::new_unauthed][]`.
-    pub fn new(source_host: impl Into<SourceHost>, owner: impl Into<Owner>) -> Result<Self> {
-        let source_host = source_host.into();
-        let owner = owner.into();
-
-        let auth_headers = auth_headers(&source_host, &owner)
-            .map_err(|e| GazenotError::new("initializing Abyss authentication", e))?;
-
-        Self::new_with_auth_headers(source_host, owner, auth_headers)
+    pub fn new(source_host: SourceHost, owner: Owner) -> Result<Self> {
+        Self::builder(source_host, owner).build()
     }
 
     /// Create a new client for The Abyss with no authentication
     ///
     /// This creates a client that is only suitable for accessing certain kinds of endpoint, such as:
     ///
+    /// * [`Gazenot::list_releases``][]
     /// * [`Gazenot::list_releases_many``][]
+    /// * [`Gazenot::download_files``][]
     /// * [`Gazenot::download_artifact_set_url``][]
-    pub fn new_unauthed(
-        source_host: impl Into<SourceHost>,
-        owner: impl Into<Owner>,
-    ) -> Result<Self> {
-        let auth_headers = HeaderMap::new();
+    pub fn new_unauthed(source_host: SourceHost, owner: Owner) -> Result<Self> {
+        Self::builder(source_host, owner)
+            .auth(AuthSource::None)
+            .build()
+    }
 
-        Self::new_with_auth_headers(source_host.into(), owner.into(), auth_headers)
+    /// Start configuring a client for The Abyss
+    ///
+    /// By default this produces the same client as [`Gazenot::new`][], but every
+    /// setting (including which servers to talk to) can be overridden.
+    pub fn builder(source_host: SourceHost, owner: Owner) -> GazenotBuilder {
+        GazenotBuilder::new(source_host, owner)
     }
 
-    fn new_with_auth_headers(
-        source_host: SourceHost,
-        owner: Owner,
-        auth_headers: HeaderMap,
-    ) -> Result<Self> {
-        const DESC: &str = "create http client for axodotdev hosting (abyss)";
-        const API_SERVER: &str = "axo-abyss.fly.dev";
-        const HOSTING_SERVER: &str = "artifacts.axodotdev.host";
+    /// Wait for a turn to send requests for `operation`, under the concurrency limits
+    ///
+    /// The turn lasts until the returned permits are dropped.
+    fn permits(&self, operation: Operation) -> impl Future<Output = Permits> + Send + 'static {
+        let concurrency = self.concurrency.clone();
+        let concurrency_for = self.concurrency_for.get(&operation).cloned();
+        async move {
+            // Acquire the more specific permit first, so that we don't hog
+            // a global permit while waiting on a busy operation.
+            //
+            // These semaphores are never closed, so acquiring can't fail.
+            let permit_for = match concurrency_for {
+                Some(sem) => Some(sem.acquire_owned().await.expect("semaphore closed")),
+                None => None,
+            };
+            let permit = concurrency.acquire_owned().await.expect("semaphore closed");
+            (permit_for, permit)
+        }
+    }
 
-        let timeout = std::time::Duration::from_secs(10);
-        let client = Client::builder()
-            .timeout(timeout)
-            .build()
-            .map_err(|e| GazenotError::new(DESC, e))?;
+    /// Spawn a task for part of a batch operation, respecting the concurrency limits
+    ///
+    /// The task also reports how long it ran for (not counting waiting for its turn).
+    fn spawn_limited<T: Send + 'static>(
+        &self,
+        operation: Operation,
+        task: impl Future<Output = ResultInner<T>> + Send + 'static,
+    ) -> Task<(ResultInner<T>, Duration)> {
+        let permits = self.permits(operation);
+        Task(tokio::spawn(async move {
+            let _permits = permits.await;
+            let start = Instant::now();
+            let result = task.await;
+            (result, start.elapsed())
+        }))
+    }
 
-        Ok(Self(Arc::new(GazenotInner {
-            api_server: API_SERVER.to_owned(),
-            hosting_server: HOSTING_SERVER.to_owned(),
-            owner,
-            source_host,
-            auth_headers,
-            client,
-        })))
+    /// Run a request until it succeeds, or fails in a way the retry policy doesn't allow retrying
+    async fn with_retries<T, Fut>(
+        &self,
+        operation: Operation,
+        mut request: impl FnMut() -> Fut,
+    ) -> ResultInner<T>
+    where
+        Fut: Future<Output = ResultInner<T>>,
+    {
+        let policy = &self.retry_policy;
+        let mut history = vec![];
+        let mut attempt = 1;
+        loop {
+            let err = match request().await {
+                Ok(result) => return Ok(result),
+                Err(e) => e,
+            };
+            let backoff =
+                if attempt < policy.max_attempts && err.is_retryable(operation.is_idempotent()) {
+                    policy.backoff(attempt, err.retry_after())
+                } else {
+                    None
+                };
+            let Some(backoff) = backoff else {
+                // Only bother with the history if we actually retried
+                if history.is_empty() {
+                    return Err(err);
+                }
+                return Err(GazenotErrorInner::Retried {
+                    attempts: attempt,
+                    history,
+                    cause: Box::new(err),
+                });
+            };
+            tracing::warn!(
+                "attempt {attempt} failed ({err}), retrying in {:.1}s",
+                backoff.as_secs_f32()
+            );
+            history.push(SimpleError(format!("attempt {attempt}: {err}")));
+            tokio::time::sleep(backoff).await;
+            attempt += 1;
+        }
     }
 
     /// Ask The Abyss to create new ArtifactSets for the given packages
@@ -179,27 +748,49 @@ impl Gazenot {
         &self,
         packages: impl IntoIterator<Item = PackageName>,
     ) -> Result<Vec<ArtifactSet>> {
-        // Spawn all the queries in parallel...
-        let mut queries = Vec::new();
+        let queries = self.create_artifact_set_queries(packages)?;
+        self.join_all(Operation::CreateArtifactSets, "create hosting", queries)
+            .await
+    }
+
+    /// Spawn the queries for [`Gazenot::create_artifact_sets`][]
+    pub(crate) fn create_artifact_set_queries(
+        &self,
+        packages: impl IntoIterator<Item = PackageName>,
+    ) -> Result<Vec<Query<ArtifactSet>>> {
+        // Check everything before spawning anything, so a bad input can't leave the batch half done
+        let mut checked = Vec::new();
         for package in packages {
-            // Abyss is just an Arc wrapper around the real client, so Cloning is fine
-            let handle = self.clone();
             let desc = format!(
                 "create hosting for {}/{}/{}",
-                self.source_host, self.owner, package
+                self.endpoints.source_host, self.endpoints.owner, package
             );
             let url = self
                 .create_artifact_set_url(&package)
                 .map_err(|e| GazenotError::new(&desc, e))?;
-            queries.push((
-                desc,
-                url.clone(),
-                tokio::spawn(async move { handle.create_artifact_set(url, package).await }),
-            ));
+            checked.push((desc, url, package));
         }
 
-        // Then join on them all
-        join_all(queries).await
+        // Then spawn all the queries in parallel
+        let queries = checked
+            .into_iter()
+            .map(|(desc, url, package)| {
+                // Abyss is just an Arc wrapper around the real client, so Cloning is fine
+                let handle = self.clone();
+                (
+                    desc,
+                    url.clone(),
+                    self.spawn_limited(Operation::CreateArtifactSets, async move {
+                        handle
+                            .with_retries(Operation::CreateArtifactSets, || {
+                                handle.create_artifact_set(url.clone(), package.clone())
+                            })
+                            .await
+                    }),
+                )
+            })
+            .collect();
+        Ok(queries)
     }
 
     /// Ask The Abyss to create a new ArtifactSets for the given package
@@ -209,12 +800,17 @@ impl Gazenot {
         package: PackageName,
     ) -> ResultInner<ArtifactSet> {
         // No body
-        let response = self
-            .client
-            .post(url.clone())
-            .headers(self.auth_headers.clone())
-            .send()
-            .await?;
+        let request = self
+            .authed_request(Route::CreateArtifactSet { package: &package }.method(), url)?
+            .timeout(self.timeout);
+        if self.is_dry_run() {
+            self.plan_request(Operation::CreateArtifactSets, request, None)?;
+            return Ok(ArtifactSet::mock(package));
+        }
+        if let Some(backend) = &self.backend {
+            return backend.create_artifact_set(&self.endpoints, &package).await;
+        }
+        let response = request.send().await?;
 
         // Process the response
         let ArtifactSetResponse {
@@ -236,139 +832,542 @@ impl Gazenot {
         })
     }
 
+    /// Ask The Abyss what's in an ArtifactSet
+    ///
+    /// This includes the files that have been uploaded to it (with their sizes and
+    /// checksums), so you can check that all the uploads arrived before creating a Release.
+    pub async fn get_artifact_set(
+        &self,
+        package: PackageName,
+        public_id: ArtifactSetId,
+    ) -> Result<ArtifactSetInfo> {
+        let desc = format!(
+            "get hosting for {}/{}/{}",
+            self.endpoints.source_host, self.endpoints.owner, package
+        );
+        reject_mock(&public_id).map_err(|e| GazenotError::new(&desc, e))?;
+        let url = self
+            .get_artifact_set_url(&package, &public_id)
+            .map_err(|e| GazenotError::new(&desc, e))?;
+        let _permits = self.permits(Operation::GetArtifactSet).await;
+        self.with_retries(Operation::GetArtifactSet, || {
+            self.get_artifact_set_info(url.clone(), &package, &public_id)
+        })
+        .await
+        .map_err(|e| GazenotError::with_url(&desc, &url, e))
+    }
+
+    async fn get_artifact_set_info(
+        &self,
+        url: Url,
+        package: &PackageName,
+        public_id: &ArtifactSetId,
+    ) -> ResultInner<ArtifactSetInfo> {
+        if let Some(backend) = &self.backend {
+            return backend
+                .get_artifact_set(&self.endpoints, package, public_id)
+                .await;
+        }
+
+        // No body
+        let response = self
+            .authed_request(Route::GetArtifactSet { package, public_id }.method(), url)?
+            .timeout(self.timeout)
+            .send()
+            .await?;
+
+        // Process the response
+        let info: ArtifactSetInfoResponse = process_response(response).await?;
+        Ok(info.into_info(package.clone()))
+    }
+
+    /// Ask The Abyss about the ArtifactSets of a package
+    ///
+    /// This is useful for cleaning up ArtifactSets that were left behind by failed releases:
+    ///
+    /// ```no_run
+    /// use std::time::Duration;
+    /// use gazenot::{ArtifactSetFilter, ArtifactSetInfo, Gazenot};
+    ///
+    /// # async fn janitor(abyss: Gazenot) -> Result<(), miette::Report> {
+    /// let filter = ArtifactSetFilter::default()
+    ///     .unreleased()
+    ///     .older_than(Duration::from_secs(7 * 24 * 60 * 60));
+    /// let orphans = abyss.list_artifact_sets("app1".parse()?, filter).await?;
+    /// let orphans = orphans
+    ///     .iter()
+    ///     .map(ArtifactSetInfo::to_artifact_set)
+    ///     .collect::<Vec<_>>();
+    /// abyss.delete_artifact_sets(&orphans).await?;
+    /// # Ok(())
+    /// # }
+    /// ```
+    pub async fn list_artifact_sets(
+        &self,
+        package: PackageName,
+        filter: ArtifactSetFilter,
+    ) -> Result<Vec<ArtifactSetInfo>> {
+        let desc = format!(
+            "list hosting for {}/{}/{}",
+            self.endpoints.source_host, self.endpoints.owner, package
+        );
+        let url = self
+            .list_artifact_sets_url(&package, &filter)
+            .map_err(|e| GazenotError::new(&desc, e))?;
+        let _permits = self.permits(Operation::ListArtifactSets).await;
+        self.with_retries(Operation::ListArtifactSets, || {
+            self.get_artifact_set_list(url.clone(), &package, &filter)
+        })
+        .await
+        .map_err(|e| GazenotError::with_url(&desc, &url, e))
+    }
+
+    async fn get_artifact_set_list(
+        &self,
+        url: Url,
+        package: &PackageName,
+        filter: &ArtifactSetFilter,
+    ) -> ResultInner<Vec<ArtifactSetInfo>> {
+        if let Some(backend) = &self.backend {
+            return backend
+                .list_artifact_sets(&self.endpoints, package, filter)
+                .await;
+        }
+
+        // No body
+        let response = self
+            .authed_request(Route::ListArtifactSets { package, filter }.method(), url)?
+            .timeout(self.timeout)
+            .send()
+            .await?;
+
+        // Process the response
+        let ListArtifactSetsResponse { artifact_sets } = process_response(response).await?;
+        Ok(artifact_sets
+            .into_iter()
+            .map(|info| info.into_info(package.clone()))
+            .collect())
+    }
+
+    /// Ask The Abyss to delete ArtifactSets, along with everything uploaded to them
+    ///
+    /// ArtifactSets that a Release was created from can't be deleted.
+    /// ArtifactSets that are already gone are skipped, so this is safe to run again
+    /// if it failed partway through.
+    pub async fn delete_artifact_sets<'a>(
+        &self,
+        sets: impl IntoIterator<Item = &'a ArtifactSet>,
+    ) -> Result<()> {
+        // Check everything before spawning anything, so a bad input can't leave the batch half done
+        let mut checked = Vec::new();
+        for set in sets {
+            let desc = format!(
+                "delete hosting for {}/{}/{}",
+                self.endpoints.source_host, self.endpoints.owner, set.package
+            );
+            let url = self
+                .delete_artifact_set_url(set)
+                .map_err(|e| GazenotError::new(&desc, e))?;
+            checked.push((desc, url, set.clone()));
+        }
+
+        // Then spawn all the queries in parallel...
+        let queries = checked
+            .into_iter()
+            .map(|(desc, url, set)| {
+                // Abyss is just an Arc wrapper around the real client, so Cloning is fine
+                let handle = self.clone();
+                (
+                    desc,
+                    url.clone(),
+                    self.spawn_limited(Operation::DeleteArtifactSets, async move {
+                        handle
+                            .with_retries(Operation::DeleteArtifactSets, || {
+                                handle.delete_artifact_set(url.clone(), &set)
+                            })
+                            .await
+                    }),
+                )
+            })
+            .collect::<Vec<_>>();
+
+        // Then join on them all
+        self.join_all(Operation::DeleteArtifactSets, "delete hosting", queries)
+            .await?;
+        Ok(())
+    }
+
+    async fn delete_artifact_set(&self, url: Url, set: &ArtifactSet) -> ResultInner<()> {
+        // No body
+        let request = self
+            .authed_request(
+                Route::DeleteArtifactSet {
+                    package: &set.package,
+                    public_id: &set.public_id,
+                }
+                .method(),
+                url,
+            )?
+            .timeout(self.timeout);
+        if self.is_dry_run() || set.is_mock() {
+            return self.plan_request(Operation::DeleteArtifactSets, request, None);
+        }
+        if let Some(backend) = &self.backend {
+            return backend.delete_artifact_set(&self.endpoints, set).await;
+        }
+        let response = request.send().await?;
+
+        // If a retry follows a delete that went through, it'll be gone already
+        if response.status() == StatusCode::NOT_FOUND {
+            return Ok(());
+        }
+        process_response_basic(response).await
+    }
+
     /// Upload files to several ArtifactSets
     ///
     /// The input is a list of files to upload, but with each file parented
     /// to the ArtifactSet it should be uploaded to.
     ///
+    /// The SHA-256 of each file is sent along with it, and the upload fails if the
+    /// server reports receiving something different. The checksums are returned
+    /// (in the same order as the input) so they can be recorded elsewhere.
+    ///
+    /// This means each file is read twice: once to hash it, and again while it's
+    /// streamed to the server. The checksum goes in a header, so it has to be known
+    /// before the body is sent, and hashing up front means it's only done once even
+    /// if the upload is retried. A file that changes in between is caught by the
+    /// server's checksum not matching.
+    ///
     /// This is a bit of an awkward signature, but it lets us handle all the parallelism for you!
     pub async fn upload_files(
         &self,
-        files: impl IntoIterator<Item = (&ArtifactSet, Vec<Utf8PathBuf>)>,
-    ) -> Result<()> {
-        // Spawn all the queries in parallel...
-        let mut queries = vec![];
+        files: impl IntoIterator<Item = (&ArtifactSet, Vec<Utf8PathBuf>)>,
+    ) -> Result<Vec<UploadedFile>> {
+        let queries = self.upload_file_queries(files)?;
+        self.join_all(Operation::UploadFiles, "upload files", queries)
+            .await
+    }
+
+    /// Spawn the queries for [`Gazenot::upload_files`][]
+    pub(crate) fn upload_file_queries<'a>(
+        &self,
+        files: impl IntoIterator<Item = (&'a ArtifactSet, Vec<Utf8PathBuf>)>,
+    ) -> Result<Vec<Query<UploadedFile>>> {
+        // Check everything before spawning anything, so a bad input can't leave the batch half done
+        let mut checked = vec![];
         for (set, sub_files) in files {
             for file in sub_files {
-                let handle = self.clone();
-                let filename = file.file_name().unwrap();
+                let filename = upload_filename(&file)
+                    .map_err(|e| {
+                        let desc = format!(
+                            "upload {file} to hosting for {}/{}/{}",
+                            self.endpoints.source_host, self.endpoints.owner, set.package
+                        );
+                        GazenotError::new(desc, e)
+                    })?
+                    .to_owned();
                 let desc = format!(
                     "upload {filename} to hosting for {}/{}/{}",
-                    self.source_host, self.owner, set.package
+                    self.endpoints.source_host, self.endpoints.owner, set.package
                 );
-                reject_mock(set).map_err(|e| GazenotError::new(&desc, e))?;
                 let url = self
-                    .upload_artifact_set_url(set, filename)
+                    .upload_artifact_set_url(set, &filename)
                     .map_err(|e| GazenotError::new(&desc, e))?;
-                queries.push((
-                    desc,
-                    url.clone(),
-                    tokio::spawn(async move { handle.upload_file(url, file).await }),
-                ));
+                checked.push((desc, url, set.clone(), filename, file));
             }
         }
 
-        // Then join on them all
-        join_all(queries).await?;
-
-        Ok(())
+        // Then spawn all the queries in parallel
+        let queries = checked
+            .into_iter()
+            .map(|(desc, url, set, filename, file)| {
+                let handle = self.clone();
+                (
+                    desc,
+                    url.clone(),
+                    self.spawn_limited(Operation::UploadFiles, async move {
+                        // Hash before sending (the checksum goes in a header), and only once
+                        // even if we need to retry the upload
+                        let sha256 = sha256_file(file.clone()).await?;
+                        let size = handle
+                            .with_retries(Operation::UploadFiles, || {
+                                handle.upload_file(url.clone(), &set, &filename, &file, &sha256)
+                            })
+                            .await?;
+                        Ok(UploadedFile {
+                            package: set.package,
+                            public_id: set.public_id,
+                            filename,
+                            size,
+                            sha256,
+                        })
+                    }),
+                )
+            })
+            .collect();
+        Ok(queries)
     }
 
     /// Single file portion of upload_file
     ///
     /// Not exposed as a public because you shouldn't use this directly,
     /// and we might want to rework it.
-    async fn upload_file(&self, url: Url, path: Utf8PathBuf) -> ResultInner<()> {
-        // Load the bytes from disk
-        //
-        // FIXME: this should be streamed to the request as it's loaded to disk
-        let data = LocalAsset::load(path)?;
+    ///
+    /// Returns the size of the file.
+    async fn upload_file(
+        &self,
+        url: Url,
+        set: &ArtifactSet,
+        filename: &str,
+        path: &Utf8Path,
+        sha256: &str,
+    ) -> ResultInner<u64> {
+        // Stream the bytes from disk, so we never have to hold the whole file in memory
+        let io_err = |details| GazenotErrorInner::Io {
+            path: path.to_owned(),
+            details,
+        };
+        let file = tokio::fs::File::open(path).await.map_err(io_err)?;
+        let len = file.metadata().await.map_err(io_err)?.len();
+
+        // Keep track of how much of the file has been sent, so we can detect stalls
+        let progress = Arc::new(AtomicU64::new(0));
+        let body = {
+            let progress = progress.clone();
+            ReaderStream::new(file).inspect_ok(move |chunk| {
+                progress.fetch_add(chunk.len() as u64, Ordering::Relaxed);
+            })
+        };
 
         // Send the bytes
-        let response = self
-            .client
-            .post(url.clone())
-            .headers(self.auth_headers.clone())
-            .header("content-type", "application/octet-stream")
-            .body(data.contents)
-            .send()
-            .await?;
+        let request = self
+            .authed_request(
+                Route::UploadFile {
+                    package: &set.package,
+                    public_id: &set.public_id,
+                    filename,
+                }
+                .method(),
+                url,
+            )?
+            .header(CONTENT_TYPE, "application/octet-stream")
+            .header(CONTENT_LENGTH, len)
+            .header(CHECKSUM_HEADER, sha256)
+            .body(Body::wrap_stream(body));
+        if self.is_dry_run() || set.is_mock() {
+            let summary = format!("{len} bytes from {path}");
+            self.plan_request(Operation::UploadFiles, request, Some(summary))?;
+            return Ok(len);
+        }
+        if let Some(backend) = &self.backend {
+            return backend
+                .upload_file(&self.endpoints, set, filename, path, sha256)
+                .await;
+        }
+        let response = self.send_until_stalled(request, &progress, len).await?;
 
-        process_response_basic(response).await?;
+        // Make sure the server got the same bytes we sent
+        let UploadResponse {
+            sha256: server_sha256,
+        } = process_response(response).await?;
+        if !server_sha256.eq_ignore_ascii_case(sha256) {
+            return Err(GazenotErrorInner::ChecksumMismatch {
+                expected: sha256.to_owned(),
+                actual: server_sha256,
+            });
+        }
 
-        Ok(())
+        Ok(len)
+    }
+
+    /// Start a request with our credentials attached
+    ///
+    /// URLs can come from deserialized ArtifactSets, which could have been tampered with,
+    /// so this refuses to attach credentials for anything but the allowed hosts.
+    fn authed_request(&self, method: Method, url: Url) -> ResultInner<RequestBuilder> {
+        // Checked even for dry runs, so they reject the same URLs a real run would
+        if self.needs_auth {
+            self.check_allowed_host(&url)?;
+        }
+        // A dry run's reads are really sent, and a placeholder token won't do for them
+        if let (Some(reason), &Method::GET) = (&self.missing_credentials, &method) {
+            return Err(GazenotErrorInner::DryRunWithoutCredentials {
+                url: url.to_string(),
+                reason: reason.clone(),
+            });
+        }
+        // Built from parts because reqwest refuses URLs without a host (like the file:// URLs
+        // of a LocalBackend) up front, even though we'd only ever plan those, not send them
+        let request = Request::new(method, url);
+        Ok(RequestBuilder::from_parts(self.client.clone(), request)
+            .headers(self.auth_headers.clone()))
+    }
+
+    /// Check that a URL points at a host we trust with our credentials
+    fn check_allowed_host(&self, url: &Url) -> ResultInner<()> {
+        // Parse the allowed hosts the same way, so that explicit default ports
+        // (and differences in case) don't matter
+        let is_same_server = |allowed: &Domain| {
+            Url::parse(&format!("{}://{allowed}", self.endpoints.scheme)).is_ok_and(|allowed| {
+                allowed.host_str() == url.host_str()
+                    && allowed.port_or_known_default() == url.port_or_known_default()
+            })
+        };
+        let allowed =
+            url.scheme() == self.endpoints.scheme && self.allowed_hosts.iter().any(is_same_server);
+        if allowed {
+            Ok(())
+        } else {
+            Err(GazenotErrorInner::UntrustedHost {
+                url: url.to_string(),
+                allowed: self
+                    .allowed_hosts
+                    .iter()
+                    .map(|host| format!("{}://{host}", self.endpoints.scheme))
+                    .collect::<Vec<_>>()
+                    .join(", "),
+            })
+        }
+    }
+
+    /// Send a request with a streaming body, only timing out if the upload stops making progress
+    ///
+    /// A whole-request timeout would kill large uploads on slow connections,
+    /// so instead we check that `progress` has changed every `timeout`.
+    /// Once all `len` bytes have been sent the check stops, so a server that's
+    /// slow to respond (say, because it's hashing a large file) isn't mistaken
+    /// for a stalled upload.
+    async fn send_until_stalled(
+        &self,
+        request: RequestBuilder,
+        progress: &AtomicU64,
+        len: u64,
+    ) -> ResultInner<Response> {
+        let send = request.send();
+        tokio::pin!(send);
+        let mut last_progress = 0;
+        loop {
+            tokio::select! {
+                response = &mut send => return Ok(response?),
+                _ = tokio::time::sleep(self.timeout) => {
+                    let cur_progress = progress.load(Ordering::Relaxed);
+                    if cur_progress >= len {
+                        break;
+                    }
+                    if cur_progress == last_progress {
+                        return Err(GazenotErrorInner::Stalled {
+                            timeout: self.timeout,
+                        });
+                    }
+                    last_progress = cur_progress;
+                }
+            }
+        }
+        Ok(send.await?)
     }
 
     /// Create Releases for all the given ArtifactSets
+    ///
+    /// Every [`ReleaseKey`][] is checked with [`ReleaseKey::validate`][] before anything is sent.
     pub async fn create_releases(
         &self,
         releases: impl IntoIterator<Item = (&ArtifactSet, ReleaseKey)>,
     ) -> Result<Vec<Release>> {
-        // Spawn all the queries in parallel...
-        let mut queries = Vec::new();
+        let queries = self.create_release_queries(releases)?;
+        self.join_all(Operation::CreateReleases, "create releases", queries)
+            .await
+    }
+
+    /// Spawn the queries for [`Gazenot::create_releases`][]
+    pub(crate) fn create_release_queries<'a>(
+        &self,
+        releases: impl IntoIterator<Item = (&'a ArtifactSet, ReleaseKey)>,
+    ) -> Result<Vec<Query<Release>>> {
+        // Check everything before spawning anything, so a bad input can't leave the batch half done
+        let mut checked = Vec::new();
         for (set, key) in releases {
-            // Abyss is just an Arc wrapper around the real client, so Cloning is fine
-            let handle = self.clone();
-            let package = set.package.clone();
-            let announce_url = set.announce_url.clone();
-            let set_id = set.public_id.clone();
             let desc = format!(
                 "create release for {}/{}/{}",
-                self.source_host, self.owner, set.package
+                self.endpoints.source_host, self.endpoints.owner, set.package
             );
-            reject_mock(set).map_err(|e| GazenotError::new(&desc, e))?;
+            key.validate(&set.package)
+                .map_err(|e| GazenotError::new(&desc, e))?;
             let url = self
                 .create_release_url(set)
                 .map_err(|e| GazenotError::new(&desc, e))?;
-            queries.push((
-                desc,
-                url.clone(),
-                tokio::spawn(async move {
-                    handle
-                        .create_release(url, set_id, package, announce_url, key)
-                        .await
-                }),
-            ));
+            checked.push((desc, url, set.clone(), key));
         }
 
-        // Then join on them all
-        join_all(queries).await
+        // Then spawn all the queries in parallel
+        let queries = checked
+            .into_iter()
+            .map(|(desc, url, set, key)| {
+                // Abyss is just an Arc wrapper around the real client, so Cloning is fine
+                let handle = self.clone();
+                (
+                    desc,
+                    url.clone(),
+                    self.spawn_limited(Operation::CreateReleases, async move {
+                        handle
+                            .with_retries(Operation::CreateReleases, || {
+                                handle.create_release(url.clone(), &set, &key)
+                            })
+                            .await
+                    }),
+                )
+            })
+            .collect();
+        Ok(queries)
     }
 
     async fn create_release(
         &self,
         url: Url,
-        set_id: ArtifactSetId,
-        package: PackageName,
-        announce_url: Option<UnparsedUrl>,
-        release: ReleaseKey,
+        set: &ArtifactSet,
+        release: &ReleaseKey,
     ) -> ResultInner<Release> {
         let request = CreateReleaseRequest {
             release: CreateReleaseRequestInner {
-                artifact_set_id: set_id,
+                artifact_set_id: set.public_id.clone(),
                 tag: release.tag.clone(),
                 version: release.version.clone(),
                 is_prerelease: release.is_prerelease,
             },
         };
 
-        let response = self
-            .client
-            .post(url.clone())
-            .headers(self.auth_headers.clone())
-            .json(&request)
-            .send()
-            .await?;
+        let request = self
+            .authed_request(
+                Route::CreateRelease {
+                    package: &set.package,
+                }
+                .method(),
+                url,
+            )?
+            .timeout(self.timeout)
+            .json(&request);
+        if set.is_mock() {
+            self.plan_request(Operation::CreateReleases, request, None)?;
+            return Ok(Release::mock(set.package.clone(), release.tag.clone()));
+        }
+        if self.is_dry_run() {
+            self.plan_request(Operation::CreateReleases, request, None)?;
+            return Ok(set.to_release(release.tag.clone()));
+        }
+        if let Some(backend) = &self.backend {
+            return backend.create_release(&self.endpoints, set, release).await;
+        }
+        let response = request.send().await?;
 
         // Parse the result
         let ReleaseResponse {
             release_download_url,
         } = process_response(response).await?;
         Ok(Release {
-            package,
-            tag: release.tag,
+            package: set.package.clone(),
+            tag: release.tag.clone(),
             release_download_url,
-            announce_url,
+            announce_url: set.announce_url.clone(),
         })
     }
 
@@ -384,57 +1383,81 @@ impl Gazenot {
         };
         let desc = format!(
             "create announcement for {}/{}/{}",
-            self.source_host, self.owner, some_release.tag
+            self.endpoints.source_host, self.endpoints.owner, some_release.tag
         );
         let url = self
             .create_announcement_url(some_release)
             .map_err(|e| GazenotError::new(&desc, e))?;
+        // An announcement is a single request, so it can't be half real and half mock
+        // (unless it's a dry run, which only plans it anyway)
+        let simulate = releases.iter().all(|r| r.is_mock());
+        if !simulate && !self.is_dry_run() && releases.iter().any(|r| r.is_mock()) {
+            return Err(GazenotError::new(&desc, GazenotErrorInner::IsMocked));
+        }
 
         // Spawn all the queries in parallel... (there's only one lol)
         let mut queries = Vec::new();
         {
             let handle = self.clone();
-            let releases = releases
-                .iter()
-                .map(|r| AnnounceReleaseKey {
-                    package: r.package.clone(),
-                    tag: r.tag.clone(),
-                })
-                .collect();
-            let announcement = announcement.clone();
+            let releases = releases.into_iter().cloned().collect::<Vec<_>>();
             queries.push((
                 desc,
                 url.clone(),
-                tokio::spawn(async move {
+                self.spawn_limited(Operation::CreateAnnouncements, async move {
                     handle
-                        .create_announcement(url, releases, announcement)
+                        .with_retries(Operation::CreateAnnouncements, || {
+                            handle.create_announcement(
+                                url.clone(),
+                                &releases,
+                                &announcement,
+                                simulate,
+                            )
+                        })
                         .await
                 }),
             ));
         }
 
         // Then join on them all
-        join_all(queries).await?;
+        self.join_all(
+            Operation::CreateAnnouncements,
+            "create announcement",
+            queries,
+        )
+        .await?;
         Ok(())
     }
 
     async fn create_announcement(
         &self,
         url: Url,
-        releases: Vec<AnnounceReleaseKey>,
-        announcement: AnnouncementKey,
+        releases: &[Release],
+        announcement: &AnnouncementKey,
+        simulate: bool,
     ) -> ResultInner<()> {
         let request = AnnounceReleaseRequest {
-            releases,
-            body: announcement.body,
+            releases: releases
+                .iter()
+                .map(|r| AnnounceReleaseKey {
+                    package: r.package.clone(),
+                    tag: r.tag.clone(),
+                })
+                .collect(),
+            body: announcement.body.clone(),
         };
-        let response = self
-            .client
-            .post(url.clone())
-            .headers(self.auth_headers.clone())
-            .json(&request)
-            .send()
-            .await?;
+        let request = self
+            .authed_request(Route::CreateAnnouncement.method(), url)?
+            .timeout(self.timeout)
+            .json(&request);
+        if self.is_dry_run() || simulate {
+            return self.plan_request(Operation::CreateAnnouncements, request, None);
+        }
+        if let Some(backend) = &self.backend {
+            return backend
+                .create_announcement(&self.endpoints, releases, announcement)
+                .await;
+        }
+        let response = request.send().await?;
 
         process_response_basic(response).await
     }
@@ -444,170 +1467,561 @@ impl Gazenot {
         &self,
         packages: impl IntoIterator<Item = PackageName>,
     ) -> Result<Vec<ReleaseList>> {
-        // Spawn all the queries in parallel...
-        let mut queries = Vec::new();
+        // Check everything before spawning anything, so a bad input can't leave the batch half done
+        let mut checked = Vec::new();
         for package in packages {
-            // Abyss is just an Arc wrapper around the real client, so Cloning is fine
-            let handle = self.clone();
             let desc = format!(
                 "get releases for {}/{}/{}",
-                self.source_host, self.owner, package
+                self.endpoints.source_host, self.endpoints.owner, package
             );
             let url = self
                 .list_releases_url(&package)
                 .map_err(|e| GazenotError::new(&desc, e))?;
-            queries.push((
-                desc,
-                url.clone(),
-                tokio::spawn(async move { handle.list_releases(url, package).await }),
-            ));
+            checked.push((desc, url, package));
         }
 
+        // Then spawn all the queries in parallel...
+        let queries = checked
+            .into_iter()
+            .map(|(desc, url, package)| {
+                // Abyss is just an Arc wrapper around the real client, so Cloning is fine
+                let handle = self.clone();
+                (
+                    desc,
+                    url.clone(),
+                    self.spawn_limited(Operation::ListReleases, async move {
+                        handle
+                            .with_retries(Operation::ListReleases, || {
+                                handle.get_release_list(url.clone(), &package)
+                            })
+                            .await
+                    }),
+                )
+            })
+            .collect::<Vec<_>>();
+
         // Then join on them all
-        join_all(queries).await
+        self.join_all(Operation::ListReleases, "get releases", queries)
+            .await
+    }
+
+    /// Ask The Abyss about releases for a single package
+    ///
+    /// See [`Gazenot::list_releases_many`][] for querying several packages in parallel.
+    pub async fn list_releases(&self, package: PackageName) -> Result<ReleaseList> {
+        let desc = format!(
+            "get releases for {}/{}/{}",
+            self.endpoints.source_host, self.endpoints.owner, package
+        );
+        let url = self
+            .list_releases_url(&package)
+            .map_err(|e| GazenotError::new(&desc, e))?;
+        let _permits = self.permits(Operation::ListReleases).await;
+        self.with_retries(Operation::ListReleases, || {
+            self.get_release_list(url.clone(), &package)
+        })
+        .await
+        .map_err(|e| GazenotError::with_url(&desc, &url, e))
     }
 
     /// Ask The Abyss about releases
-    async fn list_releases(&self, url: Url, package: PackageName) -> ResultInner<ReleaseList> {
+    async fn get_release_list(&self, url: Url, package: &PackageName) -> ResultInner<ReleaseList> {
+        if let Some(backend) = &self.backend {
+            return backend.list_releases(&self.endpoints, package).await;
+        }
+
         // No body
         let response = self
-            .client
-            .get(url.clone())
-            .headers(self.auth_headers.clone())
+            .authed_request(Route::ListReleases { package }.method(), url)?
+            .timeout(self.timeout)
             .send()
             .await?;
 
         // Process the response
-        let ListReleasesResponse {} = process_response(response).await?;
+        let ListReleasesResponse { releases } = process_response(response).await?;
 
         // Add extra context to make the response more useful in code
-        Ok(ReleaseList { package })
+        Ok(ReleaseList {
+            package: package.clone(),
+            releases,
+        })
+    }
+
+    /// Download files from several ArtifactSets or Releases into `dest_dir`
+    ///
+    /// The input is a list of filenames to download, but with each filename parented
+    /// to the ArtifactSet or Release it should be downloaded from (just like [`Gazenot::upload_files`][]).
+    ///
+    /// Files are streamed to disk, and the length of each one is checked against
+    /// what the server reported. Returns the paths of the downloaded files,
+    /// in the order they were requested.
+    ///
+    /// Every file is saved under its own name in `dest_dir`, so downloading two files
+    /// with the same name (say, from different packages) is an error.
+    ///
+    /// Interrupted downloads leave behind a `.part` file, which later attempts
+    /// (including retries) will try to resume where the server supports it.
+    pub async fn download_files<'a, S: Into<DownloadSource<'a>>>(
+        &self,
+        files: impl IntoIterator<Item = (S, Vec<String>)>,
+        dest_dir: &Utf8Path,
+    ) -> Result<Vec<Utf8PathBuf>> {
+        tokio::fs::create_dir_all(dest_dir)
+            .await
+            .map_err(|details| {
+                GazenotError::new(
+                    "create download directory",
+                    GazenotErrorInner::Io {
+                        path: dest_dir.to_owned(),
+                        details,
+                    },
+                )
+            })?;
+
+        // Check everything before spawning anything, so a bad input can't leave the batch half done
+        let mut checked = vec![];
+        let mut dests = HashSet::new();
+        for (source, filenames) in files {
+            let source = source.into();
+            for filename in filenames {
+                let desc = format!(
+                    "download {filename} from hosting for {}/{}/{}",
+                    self.endpoints.source_host,
+                    self.endpoints.owner,
+                    source.package()
+                );
+                check_filename(&filename).map_err(|e| GazenotError::new(&desc, e))?;
+                // Nothing was really uploaded to mocks, so there's nothing to download
+                let is_mock = match source {
+                    DownloadSource::ArtifactSet(set) => set.is_mock(),
+                    DownloadSource::Release(release) => release.is_mock(),
+                };
+                if is_mock {
+                    return Err(GazenotError::new(&desc, GazenotErrorInner::IsMocked));
+                }
+                let url = match source {
+                    DownloadSource::ArtifactSet(set) => {
+                        self.download_artifact_set_url(set, &filename)
+                    }
+                    DownloadSource::Release(release) => {
+                        self.download_release_url(release, &filename)
+                    }
+                }
+                .map_err(|e| GazenotError::new(&desc, e))?;
+                // Two downloads of the same file would clobber each other (and each other's .part)
+                let dest = dest_dir.join(&filename);
+                if !dests.insert(dest.clone()) {
+                    return Err(GazenotError::new(
+                        &desc,
+                        GazenotErrorInner::DuplicateDownload { path: dest },
+                    ));
+                }
+                checked.push((desc, url, dest));
+            }
+        }
+
+        // Then spawn all the queries in parallel...
+        let queries = checked
+            .into_iter()
+            .map(|(desc, url, dest)| {
+                let handle = self.clone();
+                (
+                    desc,
+                    url.clone(),
+                    self.spawn_limited(Operation::DownloadFiles, async move {
+                        handle
+                            .with_retries(Operation::DownloadFiles, || {
+                                handle.download_file(url.clone(), dest.clone())
+                            })
+                            .await
+                    }),
+                )
+            })
+            .collect::<Vec<_>>();
+
+        // Then join on them all
+        self.join_all(Operation::DownloadFiles, "download files", queries)
+            .await
+    }
+
+    /// Single file portion of download_files
+    ///
+    /// Downloads go to `{dest}.part` before being moved to `dest`. If a previous
+    /// attempt left a partial file behind (along with a validator identifying the
+    /// version of the file it was downloading), we try to resume it with a Range request.
+    async fn download_file(&self, url: Url, dest: Utf8PathBuf) -> ResultInner<Utf8PathBuf> {
+        if let Some(backend) = &self.backend {
+            backend.download_file(&url, &dest).await?;
+            return Ok(dest);
+        }
+
+        let part = Utf8PathBuf::from(format!("{dest}.part"));
+        let validator_path = Utf8PathBuf::from(format!("{dest}.part.validator"));
+        let io_err = |path: &Utf8Path| {
+            let path = path.to_owned();
+            move |details| GazenotErrorInner::Io { path, details }
+        };
+
+        // See if there's anything to resume
+        let mut resume_from = resumable_len(&part, &validator_path).await;
+
+        let response = loop {
+            // No auth, hosting is public (and we don't want to hand our secrets to the CDN)
+            let mut request = self.client.get(url.clone());
+            if let Some((offset, validator)) = &resume_from {
+                request = request
+                    .header(RANGE, format!("bytes={offset}-"))
+                    .header(IF_RANGE, validator);
+            }
+            let response = tokio::time::timeout(self.timeout, request.send())
+                .await
+                .map_err(|_| GazenotErrorInner::Stalled {
+                    timeout: self.timeout,
+                })??;
+
+            if let Some((offset, _)) = &resume_from {
+                // Our partial file is no good, or the server sent us some other part of the
+                // file than we asked for (which we can't safely stitch together), so throw
+                // out what we have and start over
+                let wrong_range = response.status() == StatusCode::PARTIAL_CONTENT
+                    && content_range_start(&response) != Some(*offset);
+                if response.status() == StatusCode::RANGE_NOT_SATISFIABLE || wrong_range {
+                    discard_partial(&part, &validator_path).await;
+                    resume_from = None;
+                    continue;
+                }
+            }
+            break response;
+        };
+
+        if !response.status().is_success() {
+            // Hosting isn't the API, so there's no json to parse, just report whatever it said
+            let status = response.status();
+            let retry_after = retry_after(&response);
+            let text = response.text().await?;
+            let errors = if text.is_empty() {
+                vec![]
+            } else {
+                vec![SimpleError(text)]
+            };
+            return Err(GazenotErrorInner::ResponseError {
+                status,
+                errors,
+                retry_after,
+            });
+        }
+
+        // If the server honored our Range (because the file hasn't changed), append to the
+        // partial file. Otherwise it's sending us the whole file, so start from scratch.
+        let offset = match resume_from {
+            Some((offset, _)) if response.status() == StatusCode::PARTIAL_CONTENT => offset,
+            _ => 0,
+        };
+        let expected_len = response.content_length().map(|len| len + offset);
+        let mut file = if offset == 0 {
+            // Remember what version of the file this is, so an interrupted download can be resumed
+            match response_validator(&response) {
+                Some(validator) => tokio::fs::write(&validator_path, validator)
+                    .await
+                    .map_err(io_err(&validator_path))?,
+                None => {
+                    let _ = tokio::fs::remove_file(&validator_path).await;
+                }
+            }
+            tokio::fs::File::create(&part)
+                .await
+                .map_err(io_err(&part))?
+        } else {
+            tokio::fs::OpenOptions::new()
+                .append(true)
+                .open(&part)
+                .await
+                .map_err(io_err(&part))?
+        };
+
+        // Stream the response to disk, only timing out if it stalls
+        let mut response = response;
+        let mut len = offset;
+        loop {
+            let chunk = tokio::time::timeout(self.timeout, response.chunk())
+                .await
+                .map_err(|_| GazenotErrorInner::Stalled {
+                    timeout: self.timeout,
+                })??;
+            let Some(chunk) = chunk else {
+                break;
+            };
+            len += chunk.len() as u64;
+            file.write_all(&chunk).await.map_err(io_err(&part))?;
+        }
+        file.flush().await.map_err(io_err(&part))?;
+        drop(file);
+
+        if let Some(expected) = expected_len {
+            if len != expected {
+                // Resuming a file with the wrong length is pointless
+                discard_partial(&part, &validator_path).await;
+                return Err(GazenotErrorInner::LengthMismatch {
+                    expected,
+                    actual: len,
+                });
+            }
+        }
+
+        tokio::fs::rename(&part, &dest)
+            .await
+            .map_err(io_err(&dest))?;
+        let _ = tokio::fs::remove_file(&validator_path).await;
+        Ok(dest)
     }
 
     pub fn create_artifact_set_url(&self, package: &PackageName) -> ResultInner<Url> {
-        // POST /:sourcehost/:owner/:package/artifacts
-        let server = &self.api_server;
-        let source_host = &self.source_host;
-        let owner = &self.owner;
-        let url = Url::from_str(&format!(
-            "https://{server}/{source_host}/{owner}/{package}/artifacts"
-        ))?;
-        Ok(url)
+        self.route_url(Route::CreateArtifactSet { package })
+    }
+
+    pub fn get_artifact_set_url(
+        &self,
+        package: &PackageName,
+        public_id: &ArtifactSetId,
+    ) -> ResultInner<Url> {
+        self.route_url(Route::GetArtifactSet { package, public_id })
+    }
+
+    pub fn list_artifact_sets_url(
+        &self,
+        package: &PackageName,
+        filter: &ArtifactSetFilter,
+    ) -> ResultInner<Url> {
+        self.route_url(Route::ListArtifactSets { package, filter })
+    }
+
+    pub fn delete_artifact_set_url(&self, set: &ArtifactSet) -> ResultInner<Url> {
+        self.route_url(Route::DeleteArtifactSet {
+            package: &set.package,
+            public_id: &set.public_id,
+        })
     }
 
     pub fn download_artifact_set_url(&self, set: &ArtifactSet, filename: &str) -> ResultInner<Url> {
-        // TODO: update this to new signature
-        // GET :owner.:hosting_server/:package/:public_id/
-        let base = set.set_download_url.clone().unwrap_or_else(|| {
-            let server = &self.hosting_server;
-            let owner = &self.owner;
-            let ArtifactSet {
-                package, public_id, ..
-            } = set;
-            format!("https://{owner}.{server}/{package}/{public_id}")
-        });
-        let url = Url::from_str(&format!("{base}/{filename}"))?;
-        Ok(url)
+        // Prefer the URL the server gave us
+        if let Some(base) = &set.set_download_url {
+            return join_segments(base, [filename]);
+        }
+        self.route_url(Route::DownloadArtifactSetFile {
+            package: &set.package,
+            public_id: &set.public_id,
+            filename,
+        })
+    }
+
+    pub fn download_release_url(&self, release: &Release, filename: &str) -> ResultInner<Url> {
+        // Prefer the URL the server gave us
+        if let Some(base) = &release.release_download_url {
+            return join_segments(base, [filename]);
+        }
+        self.route_url(Route::DownloadReleaseFile {
+            package: &release.package,
+            tag: &release.tag,
+            filename,
+        })
     }
 
     pub fn upload_artifact_set_url(&self, set: &ArtifactSet, filename: &str) -> ResultInner<Url> {
-        // POST /:sourcehost/:owner/:package/artifacts/:id/
-        let base = set.upload_url.clone().unwrap_or_else(|| {
-            let server = &self.api_server;
-            let source_host = &self.source_host;
-            let owner = &self.owner;
-            let ArtifactSet {
-                package, public_id, ..
-            } = set;
-            format!("https://{server}/{source_host}/{owner}/{package}/artifacts/{public_id}/upload")
-        });
-        let url = Url::from_str(&format!("{base}/{filename}"))?;
-        Ok(url)
+        // Prefer the URL the server gave us
+        if let Some(base) = &set.upload_url {
+            return join_segments(base, [filename]);
+        }
+        self.route_url(Route::UploadFile {
+            package: &set.package,
+            public_id: &set.public_id,
+            filename,
+        })
     }
 
     pub fn create_release_url(&self, set: &ArtifactSet) -> ResultInner<Url> {
-        // POST /:sourcehost/:owner/:package/releases
-        let url = set.release_url.clone().unwrap_or_else(|| {
-            let server = &self.api_server;
-            let source_host = &self.source_host;
-            let owner = &self.owner;
-            let package = &set.package;
-            format!("https://{server}/{source_host}/{owner}/{package}/releases")
-        });
-        let url = Url::from_str(&url)?;
-        Ok(url)
+        // Prefer the URL the server gave us
+        if let Some(url) = &set.release_url {
+            return Ok(Url::parse(url)?);
+        }
+        self.route_url(Route::CreateRelease {
+            package: &set.package,
+        })
     }
 
     pub fn create_announcement_url(&self, release: &Release) -> ResultInner<Url> {
-        // POST /:sourcehost/:owner/announcements
-        let url = release.announce_url.clone().unwrap_or_else(|| {
-            let server = &self.api_server;
-            let source_host = &self.source_host;
-            let owner = &self.owner;
-            format!("https://{server}/{source_host}/{owner}/announcements")
-        });
-        let url = Url::from_str(&url)?;
-        Ok(url)
+        // Prefer the URL the server gave us
+        if let Some(url) = &release.announce_url {
+            return Ok(Url::parse(url)?);
+        }
+        self.route_url(Route::CreateAnnouncement)
     }
 
     pub fn list_releases_url(&self, package: &PackageName) -> ResultInner<Url> {
-        // GET /:sourcehost/:owner/:projects/releases
-        let server = &self.api_server;
-        let source_host = &self.source_host;
-        let owner = &self.owner;
-        let package = &package;
-        let url = Url::from_str(&format!(
-            "https://{server}/{source_host}{owner}/{package}/releases"
-        ))?;
-        Ok(url)
-    }
-}
-
-async fn join_all<T>(
-    queries: impl IntoIterator<Item = (String, Url, tokio::task::JoinHandle<ResultInner<T>>)>,
-) -> Result<Vec<T>> {
-    let mut results = Vec::new();
-    for (desc, url, query) in queries {
-        let result = query
+        self.route_url(Route::ListReleases { package })
+    }
+
+    /// Where a route lives, on whichever backend this client uses
+    fn route_url(&self, route: Route) -> ResultInner<Url> {
+        match &self.backend {
+            Some(backend) => backend.url(&self.endpoints, route),
+            None => route.url(&self.endpoints),
+        }
+    }
+}
+
+/// A spawned task that gets cancelled if nothing is waiting on it anymore
+///
+/// This ensures that if a batch operation bails out early, the rest of the batch stops too.
+pub(crate) struct Task<T>(JoinHandle<T>);
+
+impl<T> Drop for Task<T> {
+    fn drop(&mut self) {
+        // This is a no-op if the task already finished
+        self.0.abort();
+    }
+}
+
+impl<T> Future for Task<T> {
+    type Output = std::result::Result<T, tokio::task::JoinError>;
+    fn poll(mut self: Pin<&mut Self>, cx: &mut Context<'_>) -> Poll<Self::Output> {
+        Pin::new(&mut self.0).poll(cx)
+    }
+}
+
+/// One part of a batch operation: a description, the endpoint, and the spawned request
+pub(crate) type Query<T> = (String, Url, Task<(ResultInner<T>, Duration)>);
+
+/// A turn to send requests, see [`Gazenot::permits`][]
+///
+/// The permit for the specific operation (if it has a limit) is released first.
+type Permits = (Option<OwnedSemaphorePermit>, OwnedSemaphorePermit);
+
+impl Gazenot {
+    /// Wait for all the parts of a batch operation, handling failures according to
+    /// the client's [`FailureMode`][]
+    ///
+    /// `what` describes the whole batch, for reporting several failures at once.
+    /// Results are returned in the same order as the queries.
+    async fn join_all<T: Reportable>(
+        &self,
+        operation: Operation,
+        what: &str,
+        queries: impl IntoIterator<Item = Query<T>>,
+    ) -> Result<Vec<T>> {
+        self.join_all_recording(operation, what, queries, |_| Ok(()))
             .await
-            .map_err(|e| GazenotError::with_url(&desc, url.to_string(), e))?
-            .map_err(|e| GazenotError::with_url(&desc, url.to_string(), e))?;
-        results.push(result);
     }
-    Ok(results)
+
+    /// [`Gazenot::join_all`][] but `record` is called on each result as soon as it succeeds
+    ///
+    /// This lets callers keep track of the parts of a batch that succeeded, even if the
+    /// batch as a whole fails. If `record` fails, the whole batch fails with that error.
+    pub(crate) async fn join_all_recording<T: Reportable>(
+        &self,
+        operation: Operation,
+        what: &str,
+        queries: impl IntoIterator<Item = Query<T>>,
+        mut record: impl FnMut(&T) -> Result<()>,
+    ) -> Result<Vec<T>> {
+        let queries = queries.into_iter().collect::<Vec<_>>();
+        // Everything starts out cancelled, and gets filled in as it finishes
+        let mut entries = match self.report {
+            Some(_) => queries
+                .iter()
+                .map(|(desc, url, _)| ReportEntry::cancelled(operation, desc, url))
+                .collect(),
+            None => vec![],
+        };
+
+        // Wait on the queries in whatever order they complete, so we learn about failures asap
+        let mut pending = queries
+            .into_iter()
+            .enumerate()
+            .map(|(idx, (desc, url, query))| async move {
+                let (result, duration) = match query.await {
+                    Ok((result, duration)) => (result, Some(duration)),
+                    Err(e) => (Err(e.into()), None),
+                };
+                let result = result.map_err(|e| GazenotError::with_url(&desc, url.to_string(), e));
+                (idx, result, duration)
+            })
+            .collect::<FuturesUnordered<_>>();
+        let total = pending.len();
+
+        let mut results = Vec::with_capacity(total);
+        let mut errors = Vec::new();
+        let mut fatal = None;
+        while let Some((idx, result, duration)) = pending.next().await {
+            if let Some(entry) = entries.get_mut(idx) {
+                entry.finish(&result, duration);
+            }
+            match result {
+                Ok(result) => {
+                    if let Err(e) = record(&result) {
+                        fatal = Some(e);
+                        break;
+                    }
+                    results.push((idx, result));
+                }
+                Err(e) if self.failure_mode == FailureMode::FailFast => {
+                    fatal = Some(e);
+                    break;
+                }
+                Err(e) => errors.push(e),
+            }
+        }
+        // Dropping `pending` cancels everything that's still running
+        drop(pending);
+        self.extend_report(entries);
+
+        if let Some(e) = fatal {
+            return Err(e);
+        }
+        if errors.len() == 1 {
+            return Err(errors.pop().unwrap());
+        }
+        if !errors.is_empty() {
+            return Err(GazenotError::new(
+                format!("{what} ({} of {total} failed)", errors.len()),
+                GazenotErrorInner::Batch { errors },
+            ));
+        }
+
+        results.sort_by_key(|(idx, _)| *idx);
+        Ok(results.into_iter().map(|(_, result)| result).collect())
+    }
 }
 
-fn auth_headers(source: &SourceHost, owner: &Owner) -> ResultInner<HeaderMap> {
+fn auth_headers(
+    provider: &dyn CredentialProvider,
+    request: &CredentialRequest,
+) -> ResultInner<HeaderMap> {
     // extra-awkard code so you're on your toes and properly treat this like radioactive waste
     // DO NOT UNDER ANY CIRCUMSTANCES PRINT THIS VALUE.
     // DO NOT IMPLEMENT DEBUG ON Abyss OR AbyssInner!!
     let auth = {
-        // Intentionally hidden so we only do this here
-        const AUTH_KEY_ENV_VAR: &str = "AXO_RELEASES_TOKEN";
-        // Load from env-var
-        let Ok(auth_key) = std::env::var(AUTH_KEY_ENV_VAR) else {
-            return Err(GazenotErrorInner::AuthKey {
-                reason: "could not load env var",
-                env_var_name: AUTH_KEY_ENV_VAR,
-            });
-        };
+        let auth_key = provider.token(request)?;
         if auth_key.is_empty() {
             return Err(GazenotErrorInner::AuthKey {
-                reason: "no value in env var",
-                env_var_name: AUTH_KEY_ENV_VAR,
+                reason: "no value".to_owned(),
+                provider: provider.description(),
             });
         }
         // Create http header
-        let Ok(auth) = HeaderValue::from_str(&format!("Bearer {auth_key}")) else {
+        let Ok(mut auth) = HeaderValue::from_str(&format!("Bearer {auth_key}")) else {
             return Err(GazenotErrorInner::AuthKey {
-                reason: "had invalid characters for an http header",
-                env_var_name: AUTH_KEY_ENV_VAR,
+                reason: "had invalid characters for an http header".to_owned(),
+                provider: provider.description(),
             });
         };
+        auth.set_sensitive(true);
         auth
     };
 
-    let id = HeaderValue::from_str(&format!("{source}/{owner}"))?;
+    let CredentialRequest {
+        source_host, owner, ..
+    } = request;
+    let id = HeaderValue::from_str(&format!("{source_host}/{owner}"))?;
     let auth_headers = HeaderMap::from_iter([
         (HeaderName::from_static("authorization"), auth),
         (HeaderName::from_static("x-axo-identifier"), id),
@@ -615,17 +2029,17 @@ fn auth_headers(source: &SourceHost, owner: &Owner) -> ResultInner<HeaderMap> {
     Ok(auth_headers)
 }
 
-async fn process_response<T: for<'a> Deserialize<'a>>(
-    response: reqwest::Response,
-) -> ResultInner<T> {
+async fn process_response<T: for<'a> Deserialize<'a>>(response: Response) -> ResultInner<T> {
     // don't use status_for_error, we want to try to parse errors!
     let status = response.status();
+    let retry_after = retry_after(&response);
 
     // Load the text of the response
     let text = response.text().await?;
 
     // Try to parse the response as json
-    let Ok(parsed): std::result::Result<Response<T>, _> = axoasset::serde_json::de::from_str(&text)
+    let Ok(parsed): std::result::Result<ApiResponse<T>, _> =
+        axoasset::serde_json::de::from_str(&text)
     else {
         // Failed to parse response as json, error out and display whatever text as an error
         let errors = if text.is_empty() {
@@ -633,7 +2047,11 @@ async fn process_response<T: for<'a> Deserialize<'a>>(
         } else {
             vec![SimpleError(text.clone())]
         };
-        return Err(GazenotErrorInner::ResponseError { status, errors });
+        return Err(GazenotErrorInner::ResponseError {
+            status,
+            errors,
+            retry_after,
+        });
     };
 
     // Only return success if everything agrees
@@ -663,12 +2081,14 @@ async fn process_response<T: for<'a> Deserialize<'a>>(
             .chain(extra_error)
             .map(SimpleError)
             .collect(),
+        retry_after,
     })
 }
 
-async fn process_response_basic(response: reqwest::Response) -> ResultInner<()> {
+async fn process_response_basic(response: Response) -> ResultInner<()> {
     // don't use status_for_error, we want to try to parse errors!
     let status = response.status();
+    let retry_after = retry_after(&response);
 
     // Load the text of the response
     let text = response.text().await?;
@@ -683,7 +2103,11 @@ async fn process_response_basic(response: reqwest::Response) -> ResultInner<()>
         } else {
             vec![SimpleError(text.clone())]
         };
-        return Err(GazenotErrorInner::ResponseError { status, errors });
+        return Err(GazenotErrorInner::ResponseError {
+            status,
+            errors,
+            retry_after,
+        });
     };
 
     // Only return success if everything agrees
@@ -713,13 +2137,119 @@ async fn process_response_basic(response: reqwest::Response) -> ResultInner<()>
             .chain(extra_error)
             .map(SimpleError)
             .collect(),
+        retry_after,
+    })
+}
+
+/// Get the Retry-After of a response, if it's the kind of response that should have one
+fn retry_after(response: &Response) -> Option<Duration> {
+    use reqwest::header::RETRY_AFTER;
+    if !matches!(
+        response.status(),
+        StatusCode::TOO_MANY_REQUESTS | StatusCode::SERVICE_UNAVAILABLE
+    ) {
+        return None;
+    }
+    let value = response.headers().get(RETRY_AFTER)?.to_str().ok()?.trim();
+    // Either a number of seconds, or an http-date to wait until
+    if let Ok(secs) = value.parse::<u64>() {
+        return Some(Duration::from_secs(secs));
+    }
+    let date = httpdate::parse_http_date(value).ok()?;
+    Some(
+        date.duration_since(std::time::SystemTime::now())
+            .unwrap_or_default(),
+    )
+}
+
+/// Compute the SHA-256 of a file, hex-encoded
+pub(crate) async fn sha256_file(path: Utf8PathBuf) -> ResultInner<String> {
+    // Hashing big files takes a while, so keep it off the async workers
+    tokio::task::spawn_blocking(move || {
+        let mut file = std::fs::File::open(&path).map_err(|details| GazenotErrorInner::Io {
+            path: path.clone(),
+            details,
+        })?;
+        let mut hasher = Sha256::new();
+        std::io::copy(&mut file, &mut hasher)
+            .map_err(|details| GazenotErrorInner::Io { path, details })?;
+        Ok(format!("{:x}", hasher.finalize()))
     })
+    .await?
+}
+
+/// If there's a partial download we can resume, get its length and validator
+async fn resumable_len(part: &Utf8Path, validator_path: &Utf8Path) -> Option<(u64, String)> {
+    let len = tokio::fs::metadata(part).await.ok()?.len();
+    if len == 0 {
+        return None;
+    }
+    let validator = tokio::fs::read_to_string(validator_path).await.ok()?;
+    Some((len, validator))
+}
+
+/// Get rid of a partial download (best-effort)
+async fn discard_partial(part: &Utf8Path, validator_path: &Utf8Path) {
+    let _ = tokio::fs::remove_file(part).await;
+    let _ = tokio::fs::remove_file(validator_path).await;
+}
+
+/// Get a value that identifies the version of the file in a response, for use with If-Range
+///
+/// If-Range requires a strong ETag, otherwise we fall back to Last-Modified.
+fn response_validator(response: &Response) -> Option<String> {
+    let headers = response.headers();
+    let etag = headers
+        .get(ETAG)
+        .and_then(|v| v.to_str().ok())
+        .filter(|etag| !etag.starts_with("W/"));
+    let validator = etag.or_else(|| headers.get(LAST_MODIFIED)?.to_str().ok())?;
+    Some(validator.to_owned())
+}
+
+/// Get the first byte of a 206 response's Content-Range (`bytes start-end/total`)
+fn content_range_start(response: &Response) -> Option<u64> {
+    let range = response.headers().get(CONTENT_RANGE)?.to_str().ok()?;
+    let (start, _) = range.strip_prefix("bytes ")?.split_once('-')?;
+    start.trim().parse().ok()
+}
+
+/// Make sure a filename is just a filename, and not a path that could escape a directory
+pub(crate) fn check_filename(filename: &str) -> ResultInner<()> {
+    let is_plain = !filename.is_empty()
+        && filename != "."
+        && filename != ".."
+        && !filename.contains(['/', '\\']);
+    if is_plain {
+        Ok(())
+    } else {
+        Err(GazenotErrorInner::InvalidFilename {
+            filename: filename.to_owned(),
+        })
+    }
 }
 
-fn reject_mock(artifact_set: &ArtifactSet) -> ResultInner<()> {
-    if artifact_set.is_mock() {
+/// The name a file is uploaded as
+///
+/// Paths like `/` or `foo/..` have no name, so they can't be uploaded.
+pub(crate) fn upload_filename(path: &Utf8Path) -> ResultInner<&str> {
+    let filename = path
+        .file_name()
+        .ok_or_else(|| GazenotErrorInner::InvalidFilename {
+            filename: path.to_string(),
+        })?;
+    check_filename(filename)?;
+    Ok(filename)
+}
+
+/// Refuse to ask The Abyss about a mock ArtifactSet, since it never heard of it
+fn reject_mock(public_id: &ArtifactSetId) -> ResultInner<()> {
+    if *public_id == crate::MOCK_ARTIFACT_SET_PUBLIC_ID {
         Err(GazenotErrorInner::IsMocked)
     } else {
         Ok(())
     }
 }
+
+#[cfg(test)]
+mod tests;
@@ -0,0 +1,1007 @@
+use std::time::Duration;
+
+use camino::Utf8PathBuf;
+use reqwest::StatusCode;
+use sha2::{Digest, Sha256};
+use url::Url;
+
+use crate::{
+    credentials::{FileCredentials, StaticCredentials},
+    error::{GazenotErrorInner, Result},
+    report::{ReportResult, ReportStatus},
+    testing::{scratch_dir, FakeAbyss},
+    AnnouncementKey, ArtifactSet, ArtifactSetFilter, ArtifactSetInfo, FailureMode, Gazenot,
+    GazenotBuilder, Operation, PackageName, Release, ReleaseKey, RetryPolicy,
+};
+
+/// Contents that are big enough to be worth resuming
+fn contents() -> Vec<u8> {
+    (0..1000u32).map(|i| (i % 251) as u8).collect()
+}
+
+fn client(fake: &FakeAbyss) -> Gazenot {
+    fake.client_builder("github".parse().unwrap(), "axodotdev".parse().unwrap())
+        .retry_policy(RetryPolicy::none())
+        .build()
+        .unwrap()
+}
+
+/// Create an ArtifactSet with `contents` uploaded to it as `app.tar.gz`
+async fn hosted_file(abyss: &Gazenot, dir: &Utf8PathBuf, contents: &[u8]) -> ArtifactSet {
+    let path = dir.join("app.tar.gz");
+    std::fs::write(&path, contents).unwrap();
+    let sets = abyss
+        .create_artifact_sets(["app1".parse().unwrap()])
+        .await
+        .unwrap();
+    abyss
+        .upload_files(sets.iter().map(|set| (set, vec![path.clone()])))
+        .await
+        .unwrap();
+    sets.into_iter().next().unwrap()
+}
+
+/// Leave behind what an interrupted download of `contents` would have
+fn partial_download(dest: &Utf8PathBuf, contents: &[u8], partial: &[u8]) {
+    let etag = format!("\"{:x}\"", Sha256::digest(contents));
+    std::fs::write(dest.join("app.tar.gz.part"), partial).unwrap();
+    std::fs::write(dest.join("app.tar.gz.part.validator"), etag).unwrap();
+}
+
+async fn download(abyss: &Gazenot, set: &ArtifactSet, dest: &Utf8PathBuf) -> Vec<u8> {
+    let paths = abyss
+        .download_files([(set, vec!["app.tar.gz".to_owned()])], dest)
+        .await
+        .unwrap();
+    assert!(!dest.join("app.tar.gz.part").exists());
+    assert!(!dest.join("app.tar.gz.part.validator").exists());
+    std::fs::read(&paths[0]).unwrap()
+}
+
+#[tokio::test]
+async fn download_resumes_partial_file() {
+    let dir = scratch_dir("download-resumes");
+    let fake = FakeAbyss::start().await.unwrap();
+    let abyss = client(&fake);
+    let contents = contents();
+    let set = hosted_file(&abyss, &dir, &contents).await;
+
+    // The partial file is trusted as-is, so fill it with something that shows it was kept
+    let dest = dir.join("dest");
+    std::fs::create_dir(&dest).unwrap();
+    partial_download(&dest, &contents, &[0; 400]);
+
+    let downloaded = download(&abyss, &set, &dest).await;
+    assert_eq!(downloaded[..400], [0; 400]);
+    assert_eq!(downloaded[400..], contents[400..]);
+}
+
+#[tokio::test]
+async fn download_restarts_when_file_changed() {
+    let dir = scratch_dir("download-restarts");
+    let fake = FakeAbyss::start().await.unwrap();
+    let abyss = client(&fake);
+    let contents = contents();
+    let set = hosted_file(&abyss, &dir, &contents).await;
+
+    // Left behind by a download of some other version of the file
+    let dest = dir.join("dest");
+    std::fs::create_dir(&dest).unwrap();
+    partial_download(&dest, b"some other file", &[0; 400]);
+
+    assert_eq!(download(&abyss, &set, &dest).await, contents);
+}
+
+#[tokio::test]
+async fn download_restarts_on_wrong_range() {
+    let dir = scratch_dir("download-wrong-range");
+    let fake = FakeAbyss::start().await.unwrap();
+    let abyss = client(&fake);
+    let contents = contents();
+    let set = hosted_file(&abyss, &dir, &contents).await;
+
+    let dest = dir.join("dest");
+    std::fs::create_dir(&dest).unwrap();
+    partial_download(&dest, &contents, &contents[..400]);
+    fake.misalign_next_ranges(1);
+
+    assert_eq!(download(&abyss, &set, &dest).await, contents);
+}
+
+#[tokio::test]
+async fn download_reports_missing_files() {
+    let dir = scratch_dir("download-missing");
+    let fake = FakeAbyss::start().await.unwrap();
+    let abyss = client(&fake);
+    let set = hosted_file(&abyss, &dir, &contents()).await;
+
+    let err = abyss
+        .download_files(
+            [(&set, vec!["missing.tar.gz".to_owned()])],
+            &dir.join("dest"),
+        )
+        .await
+        .unwrap_err();
+    assert!(
+        matches!(
+            *err.cause,
+            GazenotErrorInner::ResponseError {
+                status: StatusCode::NOT_FOUND,
+                ..
+            }
+        ),
+        "{err:?}"
+    );
+}
+
+#[tokio::test]
+async fn download_rejects_duplicate_destinations() {
+    let dir = scratch_dir("download-duplicates");
+    let fake = FakeAbyss::start().await.unwrap();
+    let abyss = client(&fake);
+    let sets = abyss
+        .create_artifact_sets(["app1".parse().unwrap(), "app2".parse().unwrap()])
+        .await
+        .unwrap();
+    let source = dir.join("source.tar.gz");
+    std::fs::write(&source, contents()).unwrap();
+    abyss
+        .upload_files(sets.iter().map(|set| (set, vec![source.clone()])))
+        .await
+        .unwrap();
+
+    let dest = dir.join("dest");
+    let err = abyss
+        .download_files(
+            sets.iter()
+                .map(|set| (set, vec!["source.tar.gz".to_owned()])),
+            &dest,
+        )
+        .await
+        .unwrap_err();
+    assert!(
+        matches!(*err.cause, GazenotErrorInner::DuplicateDownload { .. }),
+        "{err:?}"
+    );
+    // Nothing was downloaded, not even the first one
+    assert!(!dest.join("source.tar.gz").exists());
+}
+
+fn production_builder() -> GazenotBuilder {
+    Gazenot::builder("github".parse().unwrap(), "axodotdev".parse().unwrap())
+        .credentials(StaticCredentials::new("secret"))
+}
+
+fn check_host(abyss: &Gazenot, url: &str) -> bool {
+    match abyss.check_allowed_host(&Url::parse(url).unwrap()) {
+        Ok(()) => true,
+        Err(GazenotErrorInner::UntrustedHost { .. }) => false,
+        Err(e) => panic!("unexpected error: {e}"),
+    }
+}
+
+#[test]
+fn credentials_only_go_to_api_server() {
+    let abyss = production_builder().build().unwrap();
+    assert!(check_host(
+        &abyss,
+        "https://axo-abyss.fly.dev/github/axodotdev"
+    ));
+    assert!(check_host(
+        &abyss,
+        "https://AXO-ABYSS.fly.dev/github/axodotdev"
+    ));
+    assert!(!check_host(
+        &abyss,
+        "https://evil.example.com/github/axodotdev"
+    ));
+    assert!(!check_host(
+        &abyss,
+        "https://axo-abyss.fly.dev.evil.example.com/"
+    ));
+    assert!(!check_host(&abyss, "https://axo-abyss.fly.dev:8443/"));
+}
+
+#[test]
+fn credentials_never_downgrade_scheme() {
+    let abyss = production_builder().build().unwrap();
+    assert!(!check_host(
+        &abyss,
+        "http://axo-abyss.fly.dev/github/axodotdev"
+    ));
+    assert!(!check_host(
+        &abyss,
+        "http://axo-abyss.fly.dev:443/github/axodotdev"
+    ));
+}
+
+#[test]
+fn credentials_ignore_explicit_default_port() {
+    let abyss = production_builder()
+        .allow_host("mirror.example.com:443")
+        .build()
+        .unwrap();
+    assert!(check_host(
+        &abyss,
+        "https://axo-abyss.fly.dev:443/github/axodotdev"
+    ));
+    assert!(check_host(
+        &abyss,
+        "https://mirror.example.com/github/axodotdev"
+    ));
+    assert!(check_host(
+        &abyss,
+        "https://mirror.example.com:443/github/axodotdev"
+    ));
+}
+
+#[test]
+fn credentials_go_to_allowed_hosts() {
+    let abyss = production_builder()
+        .allow_host("mirror.example.com:8443")
+        .build()
+        .unwrap();
+    assert!(check_host(
+        &abyss,
+        "https://mirror.example.com:8443/github/axodotdev"
+    ));
+    assert!(!check_host(
+        &abyss,
+        "https://mirror.example.com/github/axodotdev"
+    ));
+}
+
+#[test]
+fn responses_are_parsed_leniently() {
+    // Neither of these would be accepted from us, but the server gets the final say
+    assert!(crate::UnparsedVersion::new("2024.1").is_err());
+    let releases: super::ListReleasesResponse = serde_json::from_str(
+        r#"{ "releases": [
+            {
+                "tag": "v1.0.0",
+                "version": "1.0.0",
+                "is_prerelease": false,
+                "created_at": "2023-10-20T12:00:00Z"
+            },
+            {
+                "tag": "2024.1",
+                "version": "2024.1",
+                "is_prerelease": false,
+                "created_at": "2024-01-01T12:00:00Z",
+                "release_download_url": null
+            }
+        ] }"#,
+    )
+    .unwrap();
+    assert_eq!(releases.releases[1].version, "2024.1");
+
+    let set: super::ArtifactSetResponse =
+        serde_json::from_str(r#"{ "public_id": "set.with.dots" }"#).unwrap();
+    assert_eq!(set.public_id, "set.with.dots");
+    assert!(set.upload_url.is_none());
+}
+
+#[test]
+fn lenient_responses_survive_a_round_trip() {
+    let info: ArtifactSetInfo = serde_json::from_str(
+        r#"{
+            "package": "app1",
+            "public_id": "set.with.dots",
+            "set_download_url": "/relative",
+            "created_at": "2024-01-01T12:00:00Z",
+            "state": "open"
+        }"#,
+    )
+    .unwrap();
+    let set = info.to_artifact_set();
+    let json = serde_json::to_string(&set).unwrap();
+    let set: ArtifactSet = serde_json::from_str(&json).unwrap();
+    assert_eq!(set.public_id, "set.with.dots");
+    assert_eq!(set.set_download_url.as_deref(), Some("/relative"));
+
+    let json = serde_json::to_string(&set.to_release("v1.0.0".parse().unwrap())).unwrap();
+    serde_json::from_str::<Release>(&json).unwrap();
+
+    // What we chose ourselves is still checked
+    let json = json.replace("app1", "app 1");
+    assert!(serde_json::from_str::<Release>(&json).is_err());
+}
+
+#[tokio::test]
+async fn dry_run_sets_are_never_uploaded_for_real() {
+    let dir = scratch_dir("dry-run-sets");
+    let fake = FakeAbyss::start().await.unwrap();
+    let dry_run = fake
+        .client_builder("github".parse().unwrap(), "axodotdev".parse().unwrap())
+        .dry_run(true)
+        .build()
+        .unwrap();
+    let sets = dry_run
+        .create_artifact_sets(["app1".parse().unwrap()])
+        .await
+        .unwrap();
+    assert!(sets[0].is_mock());
+
+    // Say they were saved, and then handed to a real run by mistake
+    let sets: Vec<ArtifactSet> =
+        serde_json::from_str(&serde_json::to_string(&sets).unwrap()).unwrap();
+    let path = dir.join("app.tar.gz");
+    std::fs::write(&path, contents()).unwrap();
+    let abyss = client(&fake);
+    abyss
+        .upload_files(sets.iter().map(|set| (set, vec![path.clone()])))
+        .await
+        .unwrap();
+    assert!(fake.state().artifact_sets.is_empty());
+}
+
+#[tokio::test]
+async fn dry_run_needs_no_credentials() {
+    let missing = scratch_dir("dry-run-credentials").join("missing-token");
+    let builder = || production_builder().credentials(FileCredentials::new(missing.clone()));
+    assert!(builder().build().is_err());
+    let abyss = builder().dry_run(true).build().unwrap();
+    assert_eq!(
+        abyss.auth_headers.get("authorization").unwrap(),
+        "Bearer <redacted>"
+    );
+
+    // But it won't pretend for the reads it really sends
+    let err = abyss
+        .list_artifact_sets("app1".parse().unwrap(), ArtifactSetFilter::default())
+        .await
+        .unwrap_err();
+    assert!(
+        matches!(
+            *err.cause,
+            GazenotErrorInner::DryRunWithoutCredentials { .. }
+        ),
+        "{err:?}"
+    );
+}
+
+#[test]
+fn dry_run_uses_credentials_it_has() {
+    let abyss = production_builder().dry_run(true).build().unwrap();
+    assert_eq!(
+        abyss.auth_headers.get("authorization").unwrap(),
+        "Bearer secret"
+    );
+}
+
+#[tokio::test]
+async fn dry_run_plans_redacted_credentials() {
+    let fake = FakeAbyss::start().await.unwrap();
+    let dry_run = fake
+        .client_builder("github".parse().unwrap(), "axodotdev".parse().unwrap())
+        .dry_run(true)
+        .build()
+        .unwrap();
+    dry_run
+        .create_artifact_sets(["app1".parse().unwrap()])
+        .await
+        .unwrap();
+    let planned = dry_run.take_planned_requests();
+    assert_eq!(planned.len(), 1);
+    assert_eq!(planned[0].headers["authorization"], "<redacted>");
+    assert_eq!(planned[0].headers["x-axo-identifier"], "github/axodotdev");
+}
+
+#[tokio::test]
+async fn dry_run_rejects_untrusted_hosts_like_a_real_run() {
+    let dir = scratch_dir("dry-run-untrusted");
+    let path = dir.join("app.tar.gz");
+    std::fs::write(&path, contents()).unwrap();
+    // Say this was tampered with on its way to us
+    let set: ArtifactSet = serde_json::from_str(
+        r#"{
+            "package": "app1",
+            "public_id": "set-123",
+            "upload_url": "https://evil.example.com/upload"
+        }"#,
+    )
+    .unwrap();
+    for dry_run in [false, true] {
+        let abyss = production_builder().dry_run(dry_run).build().unwrap();
+        let err = abyss
+            .upload_files([(&set, vec![path.clone()])])
+            .await
+            .unwrap_err();
+        assert!(
+            matches!(*err.cause, GazenotErrorInner::UntrustedHost { .. }),
+            "dry run: {dry_run}, {err:?}"
+        );
+        assert!(abyss.take_planned_requests().is_empty());
+    }
+}
+
+/// Retries quickly enough for tests
+fn fast_retries() -> RetryPolicy {
+    RetryPolicy {
+        max_attempts: 3,
+        initial_backoff: Duration::from_millis(1),
+        max_backoff: Duration::from_millis(10),
+        jitter: false,
+    }
+}
+
+fn retrying_client(fake: &FakeAbyss) -> Gazenot {
+    fake.client_builder("github".parse().unwrap(), "axodotdev".parse().unwrap())
+        .retry_policy(fast_retries())
+        .build()
+        .unwrap()
+}
+
+async fn create_set(abyss: &Gazenot) -> Result<ArtifactSet> {
+    let sets = abyss
+        .create_artifact_sets(["app1".parse().unwrap()])
+        .await?;
+    Ok(sets.into_iter().next().unwrap())
+}
+
+#[test]
+fn backoff_honors_retry_after() {
+    let policy = fast_retries();
+    assert_eq!(policy.backoff(1, None), Some(Duration::from_millis(1)));
+    assert_eq!(policy.backoff(3, None), Some(Duration::from_millis(4)));
+    assert_eq!(policy.backoff(10, None), Some(Duration::from_millis(10)));
+    let soon = Duration::from_millis(7);
+    assert_eq!(policy.backoff(1, Some(soon)), Some(soon));
+    // Better to give up than to hammer a server that asked us to wait
+    assert_eq!(policy.backoff(1, Some(Duration::from_secs(60))), None);
+}
+
+#[test]
+fn turned_away_requests_are_always_retryable() {
+    let response = |status, retry_after| GazenotErrorInner::ResponseError {
+        status,
+        errors: vec![],
+        retry_after,
+    };
+    let later = Some(Duration::from_secs(1));
+    assert!(response(StatusCode::TOO_MANY_REQUESTS, None).is_retryable(false));
+    assert!(response(StatusCode::SERVICE_UNAVAILABLE, later).is_retryable(false));
+    assert!(!response(StatusCode::SERVICE_UNAVAILABLE, None).is_retryable(false));
+    assert!(response(StatusCode::SERVICE_UNAVAILABLE, None).is_retryable(true));
+    assert!(!response(StatusCode::BAD_REQUEST, None).is_retryable(true));
+}
+
+#[tokio::test]
+async fn retries_transient_failures() {
+    let fake = FakeAbyss::start().await.unwrap();
+    let abyss = retrying_client(&fake);
+    let set = create_set(&abyss).await.unwrap();
+
+    fake.fail_next_requests(2, StatusCode::SERVICE_UNAVAILABLE);
+    let info = abyss
+        .get_artifact_set(set.package.clone(), set.public_id.clone())
+        .await
+        .unwrap();
+    assert_eq!(info.public_id, set.public_id);
+}
+
+#[tokio::test]
+async fn gives_up_after_max_attempts() {
+    let fake = FakeAbyss::start().await.unwrap();
+    let abyss = retrying_client(&fake);
+    let set = create_set(&abyss).await.unwrap();
+
+    fake.fail_next_requests(3, StatusCode::BAD_GATEWAY);
+    let err = abyss
+        .get_artifact_set(set.package.clone(), set.public_id.clone())
+        .await
+        .unwrap_err();
+    let GazenotErrorInner::Retried {
+        attempts,
+        history,
+        cause,
+    } = *err.cause
+    else {
+        panic!("expected retries, got {err:?}");
+    };
+    assert_eq!(attempts, 3);
+    assert_eq!(history.len(), 2);
+    assert!(matches!(
+        *cause,
+        GazenotErrorInner::ResponseError {
+            status: StatusCode::BAD_GATEWAY,
+            ..
+        }
+    ));
+}
+
+#[tokio::test]
+async fn creating_sets_is_not_retried_after_server_errors() {
+    let fake = FakeAbyss::start().await.unwrap();
+    let abyss = retrying_client(&fake);
+
+    // The server might have created the set before failing, so retrying could make two
+    fake.fail_next_requests(1, StatusCode::INTERNAL_SERVER_ERROR);
+    let err = create_set(&abyss).await.unwrap_err();
+    assert!(
+        matches!(*err.cause, GazenotErrorInner::ResponseError { .. }),
+        "{err:?}"
+    );
+    assert!(fake.state().artifact_sets.is_empty());
+
+    // But a 429 means it was never looked at
+    fake.fail_next_requests(1, StatusCode::TOO_MANY_REQUESTS);
+    create_set(&abyss).await.unwrap();
+    assert_eq!(fake.state().artifact_sets.len(), 1);
+}
+
+#[tokio::test]
+async fn creating_releases_is_not_retried_after_server_errors() {
+    let fake = FakeAbyss::start().await.unwrap();
+    let abyss = retrying_client(&fake);
+    let set = create_set(&abyss).await.unwrap();
+    let key = ReleaseKey::from_tag(&set.package, "v1.0.0".parse().unwrap()).unwrap();
+
+    // The release was made, so trying again would only get a confusing conflict
+    fake.fail_next_responses(1, StatusCode::BAD_GATEWAY);
+    let err = abyss
+        .create_releases([(&set, key.clone())])
+        .await
+        .unwrap_err();
+    assert!(
+        matches!(
+            *err.cause,
+            GazenotErrorInner::ResponseError {
+                status: StatusCode::BAD_GATEWAY,
+                ..
+            }
+        ),
+        "{err:?}"
+    );
+    assert_eq!(fake.state().releases.len(), 1);
+
+    // But a release that was turned away is tried again
+    let key = ReleaseKey::from_tag(&set.package, "v1.0.1".parse().unwrap()).unwrap();
+    fake.fail_next_requests(1, StatusCode::TOO_MANY_REQUESTS);
+    abyss.create_releases([(&set, key)]).await.unwrap();
+    assert_eq!(fake.state().releases.len(), 2);
+}
+
+#[tokio::test]
+async fn list_artifact_sets_filters_on_the_server() {
+    let fake = FakeAbyss::start().await.unwrap();
+    let abyss = client(&fake);
+    let released = create_set(&abyss).await.unwrap();
+    let unreleased = create_set(&abyss).await.unwrap();
+    let key = ReleaseKey::from_tag(&released.package, "v1.0.0".parse().unwrap()).unwrap();
+    abyss.create_releases([(&released, key)]).await.unwrap();
+
+    let list = |filter| abyss.list_artifact_sets(released.package.clone(), filter);
+    let ids = |sets: Vec<ArtifactSetInfo>| {
+        sets.into_iter()
+            .map(|set| set.public_id)
+            .collect::<Vec<_>>()
+    };
+    let all = ids(list(ArtifactSetFilter::default()).await.unwrap());
+    assert_eq!(
+        all,
+        [released.public_id.clone(), unreleased.public_id.clone()]
+    );
+    let open = ids(list(ArtifactSetFilter::default().unreleased())
+        .await
+        .unwrap());
+    assert_eq!(open, std::slice::from_ref(&unreleased.public_id));
+    let filter = ArtifactSetFilter::default().older_than(Duration::from_secs(60 * 60));
+    assert!(list(filter).await.unwrap().is_empty());
+    let filter = ArtifactSetFilter::default()
+        .unreleased()
+        .older_than(Duration::ZERO);
+    assert_eq!(ids(list(filter).await.unwrap()), open);
+}
+
+#[tokio::test]
+async fn released_sets_cant_be_deleted() {
+    let fake = FakeAbyss::start().await.unwrap();
+    let abyss = client(&fake);
+    let set = create_set(&abyss).await.unwrap();
+    let key = ReleaseKey::from_tag(&set.package, "v1.0.0".parse().unwrap()).unwrap();
+    abyss.create_releases([(&set, key)]).await.unwrap();
+
+    let err = abyss.delete_artifact_sets([&set]).await.unwrap_err();
+    assert!(
+        matches!(
+            *err.cause,
+            GazenotErrorInner::ResponseError {
+                status: StatusCode::CONFLICT,
+                ..
+            }
+        ),
+        "{err:?}"
+    );
+    assert_eq!(fake.state().artifact_sets.len(), 1);
+}
+
+#[tokio::test]
+async fn deleting_is_safe_to_repeat() {
+    let fake = FakeAbyss::start().await.unwrap();
+    let abyss = retrying_client(&fake);
+    let gone = create_set(&abyss).await.unwrap();
+    let kept = create_set(&abyss).await.unwrap();
+
+    abyss.delete_artifact_sets([&gone]).await.unwrap();
+    // Already gone, like when a retry follows a delete that went through
+    abyss.delete_artifact_sets([&gone]).await.unwrap();
+    fake.fail_next_responses(1, StatusCode::BAD_GATEWAY);
+    abyss.delete_artifact_sets([&kept]).await.unwrap();
+    assert!(fake.state().artifact_sets.is_empty());
+}
+
+#[tokio::test]
+async fn uploads_check_what_the_server_received() {
+    let dir = scratch_dir("upload-checksum");
+    let path = dir.join("app.tar.gz");
+    std::fs::write(&path, contents()).unwrap();
+    let fake = FakeAbyss::start().await.unwrap();
+    let abyss = retrying_client(&fake);
+    let set = create_set(&abyss).await.unwrap();
+
+    // Trying again would most likely just send the same changed file
+    fake.misreport_next_checksums(1);
+    let err = abyss
+        .upload_files([(&set, vec![path.clone()])])
+        .await
+        .unwrap_err();
+    assert!(
+        matches!(*err.cause, GazenotErrorInner::ChecksumMismatch { .. }),
+        "{err:?}"
+    );
+
+    let uploaded = abyss
+        .upload_files([(&set, vec![path.clone()])])
+        .await
+        .unwrap();
+    assert_eq!(
+        uploaded[0].sha256,
+        format!("{:x}", Sha256::digest(contents()))
+    );
+    assert_eq!(
+        fake.state().artifact_sets[0].files["app.tar.gz"],
+        contents()
+    );
+}
+
+#[tokio::test]
+async fn uploads_need_a_filename() {
+    let dir = scratch_dir("upload-filename");
+    let path = dir.join("app.tar.gz");
+    std::fs::write(&path, contents()).unwrap();
+    let fake = FakeAbyss::start().await.unwrap();
+    let abyss = client(&fake);
+    let set = create_set(&abyss).await.unwrap();
+
+    for bad in ["/", "..", "foo/..", "foo\\bar"] {
+        let files = vec![path.clone(), Utf8PathBuf::from(bad)];
+        let err = abyss.upload_files([(&set, files)]).await.unwrap_err();
+        assert!(
+            matches!(*err.cause, GazenotErrorInner::InvalidFilename { .. }),
+            "{bad}: {err:?}"
+        );
+    }
+    // Nothing was sent, not even the good file
+    assert!(fake.state().artifact_sets[0].files.is_empty());
+}
+
+fn batch_client(fake: &FakeAbyss, failure_mode: FailureMode) -> Gazenot {
+    fake.client_builder("github".parse().unwrap(), "axodotdev".parse().unwrap())
+        .retry_policy(RetryPolicy::none())
+        .failure_mode(failure_mode)
+        .record_report(true)
+        .build()
+        .unwrap()
+}
+
+fn packages() -> Vec<PackageName> {
+    ["app1", "app2", "app3"]
+        .into_iter()
+        .map(|name| name.parse().unwrap())
+        .collect()
+}
+
+#[tokio::test]
+async fn collect_all_reports_every_failure() {
+    let fake = FakeAbyss::start().await.unwrap();
+    let abyss = batch_client(&fake, FailureMode::CollectAll);
+
+    fake.fail_next_requests(2, StatusCode::BAD_REQUEST);
+    let err = abyss.create_artifact_sets(packages()).await.unwrap_err();
+    let GazenotErrorInner::Batch { errors } = &*err.cause else {
+        panic!("expected a batch error, got {err:?}");
+    };
+    assert_eq!(errors.len(), 2);
+    assert_eq!(fake.state().artifact_sets.len(), 1);
+
+    let statuses = abyss
+        .take_report()
+        .entries
+        .into_iter()
+        .map(|entry| entry.status)
+        .collect::<Vec<_>>();
+    assert_eq!(statuses.len(), 3);
+    assert_eq!(
+        statuses
+            .iter()
+            .filter(|&&status| status == ReportStatus::Failed)
+            .count(),
+        2
+    );
+}
+
+#[tokio::test]
+async fn fail_fast_stops_at_the_first_failure() {
+    let fake = FakeAbyss::start().await.unwrap();
+    let abyss = batch_client(&fake, FailureMode::FailFast);
+
+    fake.fail_next_requests(1, StatusCode::BAD_REQUEST);
+    let err = abyss.create_artifact_sets(packages()).await.unwrap_err();
+    assert!(
+        matches!(*err.cause, GazenotErrorInner::ResponseError { .. }),
+        "{err:?}"
+    );
+
+    let entries = abyss.take_report().entries;
+    assert_eq!(entries.len(), 3);
+    let failed = entries
+        .iter()
+        .filter(|entry| entry.status == ReportStatus::Failed)
+        .collect::<Vec<_>>();
+    assert_eq!(failed.len(), 1);
+    assert!(failed[0].error.is_some());
+    // Whatever else was in flight may have reached the server before it was cancelled
+    let succeeded = entries
+        .iter()
+        .filter(|entry| entry.status == ReportStatus::Succeeded)
+        .count();
+    assert!(succeeded <= fake.state().artifact_sets.len());
+}
+
+#[tokio::test]
+async fn report_records_results() {
+    let dir = scratch_dir("report");
+    let path = dir.join("app.tar.gz");
+    std::fs::write(&path, contents()).unwrap();
+    let fake = FakeAbyss::start().await.unwrap();
+    let abyss = batch_client(&fake, FailureMode::FailFast);
+
+    let set = create_set(&abyss).await.unwrap();
+    abyss
+        .upload_files([(&set, vec![path.clone()])])
+        .await
+        .unwrap();
+
+    let entries = abyss.take_report().entries;
+    assert_eq!(entries.len(), 2);
+    assert!(entries
+        .iter()
+        .all(|entry| entry.status == ReportStatus::Succeeded
+            && entry.error.is_none()
+            && entry.duration_secs.is_some()));
+    assert_eq!(entries[0].operation, Operation::CreateArtifactSets);
+    assert!(matches!(
+        &entries[0].result,
+        Some(ReportResult::ArtifactSet(created)) if created.public_id == set.public_id
+    ));
+    assert_eq!(entries[1].operation, Operation::UploadFiles);
+    assert!(matches!(
+        &entries[1].result,
+        Some(ReportResult::UploadedFile(file)) if file.filename == "app.tar.gz"
+    ));
+
+    // Taking the report starts a new one
+    assert!(abyss.take_report().entries.is_empty());
+}
+
+#[tokio::test]
+async fn mocks_never_reach_the_server() {
+    let dir = scratch_dir("mocks");
+    let path = dir.join("app.tar.gz");
+    std::fs::write(&path, contents()).unwrap();
+    let fake = FakeAbyss::start().await.unwrap();
+    let abyss = client(&fake);
+    let mock = ArtifactSet::mock("app1".parse().unwrap());
+
+    abyss
+        .upload_files([(&mock, vec![path.clone()])])
+        .await
+        .unwrap();
+    let key = ReleaseKey::from_tag(&mock.package, "v1.0.0".parse().unwrap()).unwrap();
+    let releases = abyss.create_releases([(&mock, key)]).await.unwrap();
+    assert!(releases[0].is_mock());
+    let announcement = AnnouncementKey {
+        body: "# v1.0.0".to_owned(),
+    };
+    abyss
+        .create_announcements(&releases, announcement)
+        .await
+        .unwrap();
+
+    let state = fake.state();
+    assert!(state.artifact_sets.is_empty());
+    assert!(state.releases.is_empty());
+    assert!(state.announcements.is_empty());
+}
+
+#[tokio::test]
+async fn mocks_have_nothing_to_read() {
+    let dir = scratch_dir("mocks-read");
+    let fake = FakeAbyss::start().await.unwrap();
+    let abyss = client(&fake);
+    let mock = ArtifactSet::mock("app1".parse().unwrap());
+
+    let err = abyss
+        .download_files([(&mock, vec!["app.tar.gz".to_owned()])], &dir)
+        .await
+        .unwrap_err();
+    assert!(matches!(*err.cause, GazenotErrorInner::IsMocked), "{err:?}");
+    let err = abyss
+        .get_artifact_set(mock.package.clone(), mock.public_id.clone())
+        .await
+        .unwrap_err();
+    assert!(matches!(*err.cause, GazenotErrorInner::IsMocked), "{err:?}");
+
+    // Files still have to exist, so a mock run catches a bad path
+    let missing = dir.join("missing.tar.gz");
+    assert!(abyss.upload_files([(&mock, vec![missing])]).await.is_err());
+}
+
+#[tokio::test]
+async fn announcements_cant_mix_mocks_and_real_releases() {
+    let fake = FakeAbyss::start().await.unwrap();
+    let abyss = client(&fake);
+    let set = create_set(&abyss).await.unwrap();
+    let mock = ArtifactSet::mock("app2".parse().unwrap());
+
+    let releases = [&set, &mock]
+        .into_iter()
+        .map(|set| set.to_release("v1.0.0".parse().unwrap()))
+        .collect::<Vec<_>>();
+    let announcement = AnnouncementKey {
+        body: "# v1.0.0".to_owned(),
+    };
+    let err = abyss
+        .create_announcements(&releases, announcement)
+        .await
+        .unwrap_err();
+    assert!(matches!(*err.cause, GazenotErrorInner::IsMocked), "{err:?}");
+    assert!(fake.state().announcements.is_empty());
+}
+
+fn limited_client(
+    fake: &FakeAbyss,
+    limit: impl FnOnce(GazenotBuilder) -> GazenotBuilder,
+) -> Gazenot {
+    let builder = fake
+        .client_builder("github".parse().unwrap(), "axodotdev".parse().unwrap())
+        .retry_policy(RetryPolicy::none());
+    limit(builder).build().unwrap()
+}
+
+fn many_packages(count: usize) -> Vec<PackageName> {
+    (1..=count)
+        .map(|i| format!("app{i}").parse().unwrap())
+        .collect()
+}
+
+#[tokio::test]
+async fn batches_respect_max_concurrency() {
+    let fake = FakeAbyss::start().await.unwrap();
+    fake.delay_responses(Duration::from_millis(20));
+    let abyss = limited_client(&fake, |builder| builder.max_concurrency(2));
+
+    abyss.create_artifact_sets(many_packages(6)).await.unwrap();
+    assert_eq!(fake.max_in_flight(), 2);
+}
+
+#[tokio::test]
+async fn operations_can_be_limited_further() {
+    let fake = FakeAbyss::start().await.unwrap();
+    fake.delay_responses(Duration::from_millis(20));
+    let abyss = limited_client(&fake, |builder| {
+        builder
+            .max_concurrency(4)
+            .max_concurrency_for(Operation::CreateArtifactSets, 1)
+    });
+
+    abyss.create_artifact_sets(many_packages(4)).await.unwrap();
+    assert_eq!(fake.max_in_flight(), 1);
+    let set = &abyss.create_artifact_sets(many_packages(1)).await.unwrap()[0];
+
+    // Other operations still get the whole client's limit
+    let path = scratch_dir("limited-uploads").join("app.tar.gz");
+    std::fs::write(&path, contents()).unwrap();
+    let files = (0..4).map(|_| path.clone()).collect::<Vec<_>>();
+    abyss.upload_files([(set, files)]).await.unwrap();
+    assert_eq!(fake.max_in_flight(), 4);
+}
+
+#[tokio::test]
+async fn single_requests_respect_max_concurrency() {
+    let fake = FakeAbyss::start().await.unwrap();
+    let abyss = limited_client(&fake, |builder| builder.max_concurrency(1));
+    let set = create_set(&abyss).await.unwrap();
+
+    fake.delay_responses(Duration::from_millis(20));
+    let (info, sets, releases) = tokio::join!(
+        abyss.get_artifact_set(set.package.clone(), set.public_id.clone()),
+        abyss.list_artifact_sets(set.package.clone(), ArtifactSetFilter::default()),
+        abyss.list_releases(set.package.clone()),
+    );
+    info.unwrap();
+    sets.unwrap();
+    releases.unwrap();
+    assert_eq!(fake.max_in_flight(), 1);
+}
+
+#[tokio::test]
+async fn lifecycle_keeps_uploads_with_their_sets() {
+    let dir = scratch_dir("lifecycle-uploads");
+    let fake = FakeAbyss::start().await.unwrap();
+    let abyss = client(&fake);
+    let files = |package: &str, count: usize| {
+        (0..count)
+            .map(|i| {
+                let path = dir.join(format!("{package}-{i}.txt"));
+                std::fs::write(&path, format!("{package} {i}")).unwrap();
+                path
+            })
+            .collect::<Vec<_>>()
+    };
+    let uploads = [("app1", files("app1", 3)), ("app2", files("app2", 1))];
+
+    let pending = abyss
+        .create_pending_sets(["app1".parse().unwrap(), "app2".parse().unwrap()])
+        .await
+        .unwrap();
+    let uploaded = abyss
+        .upload_pending_sets(pending.into_iter().zip(uploads.clone()).map(
+            |(set, (package, files))| {
+                assert_eq!(set.artifact_set().package, package);
+                (set, files)
+            },
+        ))
+        .await
+        .unwrap();
+    for (set, (package, files)) in uploaded.iter().zip(&uploads) {
+        let filenames = set
+            .uploaded_files()
+            .iter()
+            .map(|file| {
+                assert_eq!(file.package, *package);
+                assert_eq!(file.public_id, set.artifact_set().public_id);
+                file.filename.as_str()
+            })
+            .collect::<Vec<_>>();
+        let expected = files
+            .iter()
+            .map(|path| path.file_name().unwrap())
+            .collect::<Vec<_>>();
+        assert_eq!(filenames, expected);
+    }
+
+    let key = |package: &str| ReleaseKey::from_tag(package, "v1.0.0".parse().unwrap()).unwrap();
+    let published = abyss
+        .publish_uploaded_sets(uploaded.into_iter().map(|set| {
+            let key = key(set.artifact_set().package.as_str());
+            (set, key)
+        }))
+        .await
+        .unwrap();
+    let announcement = AnnouncementKey {
+        body: "# v1.0.0".to_owned(),
+    };
+    let announced = abyss
+        .announce_published_releases(published, announcement)
+        .await
+        .unwrap();
+    for (release, (package, files)) in announced.releases().iter().zip(&uploads) {
+        assert_eq!(release.release().package, *package);
+        assert_eq!(release.uploaded_files().len(), files.len());
+    }
+    let state = fake.state();
+    assert_eq!(state.releases.len(), 2);
+    assert_eq!(state.announcements.len(), 1);
+}
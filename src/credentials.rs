@@ -0,0 +1,261 @@
+//! Sources of Axo Releases Tokens for authenticating with The Abyss
+//!
+//! By default [`Gazenot`][crate::Gazenot] reads the token from the AXO_RELEASES_TOKEN
+//! environment variable ([`EnvVarCredentials`][]), but any [`CredentialProvider`][]
+//! can be selected with [`GazenotBuilder::credentials`][crate::GazenotBuilder::credentials].
+//!
+//! None of the types in this module implement Debug, to avoid leaking authentication secrets.
+//! If you implement your own provider, please extend it the same courtesy.
+
+use std::{
+    io::{ErrorKind, Write},
+    process::{Command, Stdio},
+};
+
+use camino::Utf8PathBuf;
+
+use crate::{
+    error::{GazenotErrorInner, ResultInner},
+    Owner, SourceHost,
+};
+
+/// The environment variable [`EnvVarCredentials::default`][] reads from
+const AUTH_KEY_ENV_VAR: &str = "AXO_RELEASES_TOKEN";
+
+/// What a [`CredentialProvider`][] is being asked for a token for
+pub struct CredentialRequest<'a> {
+    /// The source hosting provider of the owner (e.g. "github")
+    pub source_host: &'a SourceHost,
+    /// The owner whose packages will be accessed
+    pub owner: &'a Owner,
+    /// The URL scheme the token will be sent over (e.g. "https")
+    pub scheme: &'a str,
+    /// The domain of the API server the token will be sent to
+    pub api_server: &'a str,
+}
+
+/// A source of Axo Releases Tokens
+pub trait CredentialProvider: Send + Sync {
+    /// A description of where the token comes from, for error messages
+    ///
+    /// e.g. "the AXO_RELEASES_TOKEN environment variable". This must not include the token!
+    fn description(&self) -> String;
+
+    /// Get the token
+    ///
+    /// Failures should be reported with [`GazenotErrorInner::AuthKey`][].
+    fn token(&self, request: &CredentialRequest) -> ResultInner<String>;
+}
+
+/// Read the token from an environment variable
+pub struct EnvVarCredentials {
+    var: String,
+}
+
+impl EnvVarCredentials {
+    /// Read the token from the given environment variable
+    pub fn new(var: impl Into<String>) -> Self {
+        Self { var: var.into() }
+    }
+}
+
+impl Default for EnvVarCredentials {
+    /// Read the token from AXO_RELEASES_TOKEN
+    fn default() -> Self {
+        Self::new(AUTH_KEY_ENV_VAR)
+    }
+}
+
+impl CredentialProvider for EnvVarCredentials {
+    fn description(&self) -> String {
+        format!("the {} environment variable", self.var)
+    }
+
+    fn token(&self, _request: &CredentialRequest) -> ResultInner<String> {
+        std::env::var(&self.var).map_err(|_| GazenotErrorInner::AuthKey {
+            reason: "could not load env var".to_owned(),
+            provider: self.description(),
+        })
+    }
+}
+
+/// Read the token from a file (such as a mounted secret)
+///
+/// Leading and trailing whitespace is ignored.
+pub struct FileCredentials {
+    path: Utf8PathBuf,
+}
+
+impl FileCredentials {
+    /// Read the token from the given file
+    pub fn new(path: impl Into<Utf8PathBuf>) -> Self {
+        Self { path: path.into() }
+    }
+}
+
+impl CredentialProvider for FileCredentials {
+    fn description(&self) -> String {
+        format!("the credentials file {}", self.path)
+    }
+
+    fn token(&self, _request: &CredentialRequest) -> ResultInner<String> {
+        let contents =
+            std::fs::read_to_string(&self.path).map_err(|e| GazenotErrorInner::AuthKey {
+                reason: format!("could not read file ({e})"),
+                provider: self.description(),
+            })?;
+        Ok(contents.trim().to_owned())
+    }
+}
+
+/// Get the token from an external credential helper, in the style of git-credential
+///
+/// The command is run with the following written to its stdin:
+///
+/// ```text
+/// protocol=<scheme>
+/// host=<api server>
+/// path=<source host>/<owner>
+///
+/// ```
+///
+/// And it should print lines of `key=value` to stdout, one of which is `password=<token>`.
+/// Other lines are ignored. A non-zero exit status is treated as a failure.
+pub struct CommandCredentials {
+    program: String,
+    args: Vec<String>,
+}
+
+impl CommandCredentials {
+    /// Run the given program with the given arguments
+    pub fn new(
+        program: impl Into<String>,
+        args: impl IntoIterator<Item = impl Into<String>>,
+    ) -> Self {
+        Self {
+            program: program.into(),
+            args: args.into_iter().map(|arg| arg.into()).collect(),
+        }
+    }
+}
+
+impl CredentialProvider for CommandCredentials {
+    fn description(&self) -> String {
+        format!("the credential helper `{}`", self.program)
+    }
+
+    fn token(&self, request: &CredentialRequest) -> ResultInner<String> {
+        let err = |reason: String| GazenotErrorInner::AuthKey {
+            reason,
+            provider: self.description(),
+        };
+
+        let mut child = Command::new(&self.program)
+            .args(&self.args)
+            .stdin(Stdio::piped())
+            .stdout(Stdio::piped())
+            .stderr(Stdio::inherit())
+            .spawn()
+            .map_err(|e| err(format!("could not run command ({e})")))?;
+
+        let CredentialRequest {
+            source_host,
+            owner,
+            scheme,
+            api_server,
+        } = request;
+        let input = format!("protocol={scheme}\nhost={api_server}\npath={source_host}/{owner}\n\n");
+        // Dropping stdin closes it, so the helper knows we're done
+        let mut stdin = child.stdin.take().expect("stdin was piped");
+        match stdin.write_all(input.as_bytes()) {
+            // Helpers don't have to read any of it, and may exit before we're done.
+            // If that's because they failed, their exit status is more useful.
+            Err(e) if e.kind() == ErrorKind::BrokenPipe => {}
+            result => result.map_err(|e| err(format!("could not write to command ({e})")))?,
+        }
+        drop(stdin);
+
+        let output = child
+            .wait_with_output()
+            .map_err(|e| err(format!("could not run command ({e})")))?;
+        if !output.status.success() {
+            return Err(err(format!("command failed ({})", output.status)));
+        }
+
+        // DO NOT include the output in any error, it's full of secrets!
+        let stdout = String::from_utf8(output.stdout)
+            .map_err(|_| err("command output wasn't utf8".to_owned()))?;
+        stdout
+            .lines()
+            .find_map(|line| line.strip_prefix("password="))
+            .map(|token| token.trim().to_owned())
+            .ok_or_else(|| err("command didn't output a password".to_owned()))
+    }
+}
+
+/// A token that's already known
+pub struct StaticCredentials {
+    token: String,
+}
+
+impl StaticCredentials {
+    /// Use the given token
+    pub fn new(token: impl Into<String>) -> Self {
+        Self {
+            token: token.into(),
+        }
+    }
+}
+
+impl CredentialProvider for StaticCredentials {
+    fn description(&self) -> String {
+        "the provided static token".to_owned()
+    }
+
+    fn token(&self, _request: &CredentialRequest) -> ResultInner<String> {
+        Ok(self.token.clone())
+    }
+}
+
+#[cfg(all(test, unix))]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn command_credentials_are_asked_for_the_configured_scheme() {
+        // A "helper" that hands back whatever protocol it was asked about as the token
+        let helper = CommandCredentials::new("sh", ["-c", "sed -n 's/^protocol=/password=/p'"]);
+        let request = CredentialRequest {
+            source_host: &"github".parse().unwrap(),
+            owner: &"axodotdev".parse().unwrap(),
+            scheme: "http",
+            api_server: "localhost:8080",
+        };
+        assert_eq!(helper.token(&request).unwrap(), "http");
+    }
+
+    fn token(helper: &CommandCredentials) -> ResultInner<String> {
+        helper.token(&CredentialRequest {
+            source_host: &"github".parse().unwrap(),
+            owner: &"axodotdev".parse().unwrap(),
+            scheme: "https",
+            api_server: "localhost:8080",
+        })
+    }
+
+    #[test]
+    fn command_credentials_dont_have_to_read_the_request() {
+        let helper = CommandCredentials::new("sh", ["-c", "echo password=secret"]);
+        assert_eq!(token(&helper).unwrap(), "secret");
+    }
+
+    #[test]
+    fn command_credentials_report_how_the_helper_failed() {
+        let helper = CommandCredentials::new("sh", ["-c", "exit 3"]);
+        let Err(GazenotErrorInner::AuthKey { reason, .. }) = token(&helper) else {
+            panic!("expected the helper to fail");
+        };
+        assert!(reason.starts_with("command failed"), "{reason}");
+        assert!(reason.contains('3'), "{reason}");
+    }
+}
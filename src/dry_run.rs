@@ -0,0 +1,134 @@
+//! Running operations without actually changing anything on The Abyss
+//!
+//! Clients built with [`GazenotBuilder::dry_run`][] do everything they normally would
+//! (validating inputs, reading and hashing files, building URLs and requests), except
+//! actually sending requests that would change something. Those are recorded as
+//! [`PlannedRequest`][]s instead, with any secrets redacted, and the operation returns
+//! a plausible result so later steps can be dry-run too. The ArtifactSets a dry run
+//! pretends to create are [mocks][ArtifactSet::mock], so if they're saved and handed
+//! to a real client by mistake, it still won't send anything for them:
+//!
+//! ```no_run
+//! use camino::Utf8PathBuf;
+//! use gazenot::Gazenot;
+//!
+//! # #[tokio::main]
+//! # async fn main() -> Result<(), miette::Report> {
+//! let abyss = Gazenot::builder("github".parse()?, "axodotdev".parse()?)
+//!     .dry_run(true)
+//!     .build()?;
+//! let sets = abyss.create_artifact_sets(["app1".parse()?]).await?;
+//! let files = vec![Utf8PathBuf::from("dist-manifest.json")];
+//! abyss
+//!     .upload_files(sets.iter().map(|set| (set, files.clone())))
+//!     .await?;
+//!
+//! for request in abyss.take_planned_requests() {
+//!     println!("{} {}", request.method, request.url);
+//! }
+//! # Ok(())
+//! # }
+//! ```
+//!
+//! Operations that only read from The Abyss (like [`Gazenot::list_releases`][]
+//! and [`Gazenot::download_files`][]) don't change anything, so they still run for real,
+//! with real credentials. A dry run can be done without credentials, but then it
+//! refuses to read anything that would need them.
+//!
+//! [mock ArtifactSets][ArtifactSet::mock] get the same treatment on any client: their
+//! requests are logged but never sent, and are only recorded if the client is a dry run.
+//!
+//! [`GazenotBuilder::dry_run`]: crate::GazenotBuilder::dry_run
+//! [ArtifactSet::mock]: crate::ArtifactSet::mock
+
+use std::collections::BTreeMap;
+
+use reqwest::{header::AUTHORIZATION, RequestBuilder};
+use schemars::JsonSchema;
+use serde::{Deserialize, Serialize};
+
+use crate::{error::ResultInner, Gazenot, Operation};
+
+/// What gets recorded in place of a secret
+pub(crate) const REDACTED: &str = "<redacted>";
+
+/// A request a dry run would have sent
+#[derive(Serialize, Deserialize, Debug, Clone, JsonSchema)]
+pub struct PlannedRequest {
+    /// The operation that would have sent it
+    pub operation: Operation,
+    /// The HTTP method
+    pub method: String,
+    /// The URL
+    pub url: String,
+    /// The headers, with any secrets replaced by `"<redacted>"`
+    pub headers: BTreeMap<String, String>,
+    /// What the body would have been: the body itself for json, or a summary of a file upload
+    pub body: Option<String>,
+}
+
+impl Gazenot {
+    /// Take all the requests that were planned by a dry run so far
+    ///
+    /// This is always empty unless the client was built with
+    /// [`GazenotBuilder::dry_run`][crate::GazenotBuilder::dry_run].
+    pub fn take_planned_requests(&self) -> Vec<PlannedRequest> {
+        match &self.dry_run {
+            Some(planned) => std::mem::take(&mut *planned.lock().expect("dry run lock poisoned")),
+            None => vec![],
+        }
+    }
+
+    /// Whether this client is only pretending to send requests
+    pub fn is_dry_run(&self) -> bool {
+        self.dry_run.is_some()
+    }
+
+    /// Log a request (and record it, if this is a dry run) instead of sending it
+    ///
+    /// `body` summarizes the body for requests where it can't just be read back,
+    /// such as streaming uploads.
+    pub(crate) fn plan_request(
+        &self,
+        operation: Operation,
+        request: RequestBuilder,
+        body: Option<String>,
+    ) -> ResultInner<()> {
+        let request = request.build()?;
+        let headers = request
+            .headers()
+            .iter()
+            .map(|(name, value)| {
+                let value = if value.is_sensitive() || name == AUTHORIZATION {
+                    REDACTED.to_owned()
+                } else {
+                    String::from_utf8_lossy(value.as_bytes()).into_owned()
+                };
+                (name.to_string(), value)
+            })
+            .collect();
+        let body = body.or_else(|| {
+            let bytes = request.body()?.as_bytes()?;
+            Some(String::from_utf8_lossy(bytes).into_owned())
+        });
+        let planned_request = PlannedRequest {
+            operation,
+            method: request.method().to_string(),
+            url: request.url().to_string(),
+            headers,
+            body,
+        };
+        tracing::info!(
+            "would have sent {} {}",
+            planned_request.method,
+            planned_request.url
+        );
+        if let Some(planned) = &self.dry_run {
+            planned
+                .lock()
+                .expect("dry run lock poisoned")
+                .push(planned_request);
+        }
+        Ok(())
+    }
+}
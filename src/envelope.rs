@@ -0,0 +1,296 @@
+//! Tamper-evident envelopes for handing ArtifactSets and Releases between machines
+//!
+//! In typical usage the different steps of a release happen on different machines,
+//! which pass serialized ArtifactSets between each other. Anyone who can modify those
+//! files in between can point later steps at the wrong ArtifactSet (or the wrong server).
+//! Sealing the values with a key shared by those machines lets them detect that:
+//!
+//! ```
+//! use gazenot::{envelope, ArtifactSet};
+//!
+//! # fn main() -> Result<(), miette::Report> {
+//! let key = envelope::SealingKey::new(b"a secret shared by the release machines".to_vec());
+//! let sets = vec![ArtifactSet::mock("my-app".parse()?)];
+//!
+//! // On the plan machine
+//! let sealed = envelope::seal(&sets, &key)?;
+//!
+//! // On the hosting machine
+//! let opened: Vec<ArtifactSet> = envelope::open(&sealed, &key)?;
+//! assert_eq!(opened[0].package, "my-app");
+//!
+//! // Any modification is rejected
+//! let tampered = sealed.replace("my-app", "evil-app");
+//! assert!(envelope::open::<Vec<ArtifactSet>>(&tampered, &key).is_err());
+//! # Ok(())
+//! # }
+//! ```
+
+use hmac::{Hmac, Mac};
+use schemars::JsonSchema;
+use serde::{de::DeserializeOwned, Deserialize, Serialize};
+use sha2::Sha256;
+
+use crate::{
+    error::{GazenotError, GazenotErrorInner, Result, ResultInner},
+    ArtifactSet, Release,
+};
+
+/// The current version of the envelope format
+///
+/// Envelopes with any other version are rejected.
+pub const ENVELOPE_FORMAT_VERSION: u32 = 1;
+
+/// A sealed value, as produced by [`seal`][]
+#[derive(Serialize, Deserialize, Debug, Clone, JsonSchema)]
+pub struct Envelope {
+    /// Version of the envelope format, see [`ENVELOPE_FORMAT_VERSION`][]
+    pub format_version: u32,
+    /// What kind of value is in the payload (see [`Sealable::KIND`][])
+    pub kind: String,
+    /// The value, serialized as json
+    pub payload: String,
+    /// HMAC-SHA256 of the format_version, kind, and payload, hex-encoded
+    pub signature: String,
+}
+
+/// A value that can be put in an [`Envelope`][]
+pub trait Sealable: Serialize + DeserializeOwned {
+    /// A name for this kind of value, so one kind can't be passed off as another
+    const KIND: &'static str;
+}
+
+impl Sealable for ArtifactSet {
+    const KIND: &'static str = "ArtifactSet";
+}
+impl Sealable for Vec<ArtifactSet> {
+    const KIND: &'static str = "ArtifactSets";
+}
+impl Sealable for Release {
+    const KIND: &'static str = "Release";
+}
+impl Sealable for Vec<Release> {
+    const KIND: &'static str = "Releases";
+}
+
+/// A secret key for sealing and opening [`Envelope`][]s
+///
+/// This type intentionally does not implement Debug, to avoid leaking secrets.
+pub struct SealingKey(Vec<u8>);
+
+impl SealingKey {
+    /// Use the given bytes as a key
+    ///
+    /// Any length works, but it should be at least 32 random bytes.
+    pub fn new(key: Vec<u8>) -> Self {
+        Self(key)
+    }
+
+    fn mac(&self, format_version: u32, kind: &str, payload: &str) -> Hmac<Sha256> {
+        let mut mac =
+            Hmac::<Sha256>::new_from_slice(&self.0).expect("hmac accepts keys of any length");
+        // kind never contains a newline, and payload is last, so this is unambiguous
+        mac.update(format!("gazenot-envelope\n{format_version}\n{kind}\n").as_bytes());
+        mac.update(payload.as_bytes());
+        mac
+    }
+}
+
+/// Seal a value in an [`Envelope`][], returning it serialized as json
+pub fn seal<T: Sealable>(value: &T, key: &SealingKey) -> Result<String> {
+    let desc = format!("seal {}", T::KIND);
+    let payload = serde_json::to_string(value).map_err(|e| GazenotError::new(&desc, e))?;
+    let signature = key
+        .mac(ENVELOPE_FORMAT_VERSION, T::KIND, &payload)
+        .finalize()
+        .into_bytes();
+    let envelope = Envelope {
+        format_version: ENVELOPE_FORMAT_VERSION,
+        kind: T::KIND.to_owned(),
+        payload,
+        signature: format!("{signature:x}"),
+    };
+    serde_json::to_string_pretty(&envelope).map_err(|e| GazenotError::new(&desc, e))
+}
+
+/// Open a serialized [`Envelope`][] produced by [`seal`][], checking that it wasn't modified
+pub fn open<T: Sealable>(sealed: &str, key: &SealingKey) -> Result<T> {
+    let desc = format!("open sealed {}", T::KIND);
+    open_inner(sealed, key).map_err(|e| GazenotError::new(desc, e))
+}
+
+fn open_inner<T: Sealable>(sealed: &str, key: &SealingKey) -> ResultInner<T> {
+    let envelope: Envelope = serde_json::from_str(sealed)
+        .map_err(|e| GazenotErrorInner::EnvelopeMalformed { details: e })?;
+    let Envelope {
+        format_version,
+        kind,
+        payload,
+        signature,
+    } = envelope;
+
+    if format_version != ENVELOPE_FORMAT_VERSION {
+        return Err(GazenotErrorInner::EnvelopeVersion {
+            found: format_version,
+            expected: ENVELOPE_FORMAT_VERSION,
+        });
+    }
+    // Check the signature before looking at anything else it covers
+    let signature = decode_hex(&signature).ok_or(GazenotErrorInner::EnvelopeSignature)?;
+    key.mac(format_version, &kind, &payload)
+        .verify_slice(&signature)
+        .map_err(|_| GazenotErrorInner::EnvelopeSignature)?;
+    if kind != T::KIND {
+        return Err(GazenotErrorInner::EnvelopeKind {
+            found: kind,
+            expected: T::KIND,
+        });
+    }
+    serde_json::from_str(&payload).map_err(|e| GazenotErrorInner::EnvelopeMalformed { details: e })
+}
+
+fn decode_hex(hex: &str) -> Option<Vec<u8>> {
+    // from_str_radix would also accept a sign
+    if hex.len() % 2 != 0 || !hex.bytes().all(|b| b.is_ascii_hexdigit()) {
+        return None;
+    }
+    (0..hex.len())
+        .step_by(2)
+        .map(|i| u8::from_str_radix(&hex[i..i + 2], 16).ok())
+        .collect()
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn key() -> SealingKey {
+        SealingKey::new(b"test key".to_vec())
+    }
+
+    fn sealed_set() -> String {
+        seal(&ArtifactSet::mock("my-app".parse().unwrap()), &key()).unwrap()
+    }
+
+    /// Seal an ArtifactSet, and then change the envelope
+    fn tampered(change: impl FnOnce(&mut Envelope)) -> String {
+        let mut envelope: Envelope = serde_json::from_str(&sealed_set()).unwrap();
+        change(&mut envelope);
+        serde_json::to_string(&envelope).unwrap()
+    }
+
+    fn open_set(sealed: &str) -> ResultInner<ArtifactSet> {
+        open_inner(sealed, &key())
+    }
+
+    #[test]
+    fn round_trip() {
+        let set = open_set(&sealed_set()).unwrap();
+        assert_eq!(set.package, "my-app");
+        assert!(set.is_mock());
+    }
+
+    #[test]
+    fn rejects_wrong_key() {
+        let wrong_key = SealingKey::new(b"some other key".to_vec());
+        let err = open_inner::<ArtifactSet>(&sealed_set(), &wrong_key).unwrap_err();
+        assert!(
+            matches!(err, GazenotErrorInner::EnvelopeSignature),
+            "{err:?}"
+        );
+    }
+
+    #[test]
+    fn rejects_wrong_format_version() {
+        let sealed = tampered(|envelope| envelope.format_version = 2);
+        let err = open_set(&sealed).unwrap_err();
+        assert!(
+            matches!(err, GazenotErrorInner::EnvelopeVersion { found: 2, .. }),
+            "{err:?}"
+        );
+    }
+
+    #[test]
+    fn rejects_wrong_kind() {
+        let err = open_inner::<Release>(&sealed_set(), &key()).unwrap_err();
+        assert!(
+            matches!(&err, GazenotErrorInner::EnvelopeKind { found, .. } if found == "ArtifactSet"),
+            "{err:?}"
+        );
+
+        // Relabelling it doesn't help, since the kind is signed
+        let sealed = tampered(|envelope| envelope.kind = "Release".to_owned());
+        let err = open_inner::<Release>(&sealed, &key()).unwrap_err();
+        assert!(
+            matches!(err, GazenotErrorInner::EnvelopeSignature),
+            "{err:?}"
+        );
+    }
+
+    #[test]
+    fn rejects_modified_payload() {
+        let sealed =
+            tampered(|envelope| envelope.payload = envelope.payload.replace("my-app", "evil-app"));
+        let err = open_set(&sealed).unwrap_err();
+        assert!(
+            matches!(err, GazenotErrorInner::EnvelopeSignature),
+            "{err:?}"
+        );
+    }
+
+    #[test]
+    fn rejects_tampered_signature() {
+        let flip_first = |envelope: &mut Envelope| {
+            let first = if envelope.signature.starts_with('0') {
+                "1"
+            } else {
+                "0"
+            };
+            envelope.signature.replace_range(..1, first);
+        };
+        let truncate = |envelope: &mut Envelope| {
+            envelope.signature.truncate(envelope.signature.len() - 2);
+        };
+        let empty = |envelope: &mut Envelope| envelope.signature.clear();
+        for sealed in [tampered(flip_first), tampered(truncate), tampered(empty)] {
+            let err = open_set(&sealed).unwrap_err();
+            assert!(
+                matches!(err, GazenotErrorInner::EnvelopeSignature),
+                "{err:?}"
+            );
+        }
+    }
+
+    #[test]
+    fn rejects_bad_hex() {
+        let replace_with = |bad: &'static str| {
+            move |envelope: &mut Envelope| {
+                let len = envelope.signature.len();
+                envelope.signature.replace_range(len - bad.len().., bad);
+            }
+        };
+        for bad in ["zz", "é", "+f", "-1"] {
+            let err = open_set(&tampered(replace_with(bad))).unwrap_err();
+            assert!(
+                matches!(err, GazenotErrorInner::EnvelopeSignature),
+                "{bad}: {err:?}"
+            );
+        }
+        assert_eq!(decode_hex("00ff7F"), Some(vec![0x00, 0xff, 0x7f]));
+        assert_eq!(decode_hex("abc"), None);
+        assert_eq!(decode_hex("+f"), None);
+        assert_eq!(decode_hex("éa"), None);
+    }
+
+    #[test]
+    fn rejects_malformed_envelopes() {
+        let sealed = sealed_set();
+        for sealed in [&sealed[..sealed.len() / 2], "", "[]"] {
+            let err = open_set(sealed).unwrap_err();
+            assert!(
+                matches!(err, GazenotErrorInner::EnvelopeMalformed { .. }),
+                "{err:?}"
+            );
+        }
+    }
+}
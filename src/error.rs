@@ -4,15 +4,27 @@ use thiserror::Error;
 pub type Result<T> = std::result::Result<T, GazenotError>;
 pub type ResultInner<T> = std::result::Result<T, GazenotErrorInner>;
 
-#[derive(Error, Debug, Diagnostic)]
+#[derive(Error, Debug)]
 #[error("couldn't {operation}")]
 pub struct GazenotError {
     pub operation: String,
-    #[help]
     pub help: Option<String>,
+    /// Boxed so that `Result<T, GazenotError>` stays small
     #[source]
-    #[diagnostic_source]
-    pub cause: GazenotErrorInner,
+    pub cause: Box<GazenotErrorInner>,
+}
+
+// Implemented by hand because miette's derive can't see through the Box in `cause`
+impl Diagnostic for GazenotError {
+    fn help<'a>(&'a self) -> Option<Box<dyn std::fmt::Display + 'a>> {
+        self.help
+            .as_ref()
+            .map(|help| Box::new(help) as Box<dyn std::fmt::Display>)
+    }
+
+    fn diagnostic_source(&self) -> Option<&dyn Diagnostic> {
+        Some(&*self.cause)
+    }
 }
 
 impl GazenotError {
@@ -20,7 +32,7 @@ impl GazenotError {
         Self {
             operation: operation.into(),
             help: None,
-            cause: err.into(),
+            cause: Box::new(err.into()),
         }
     }
     pub fn with_url(
@@ -31,7 +43,7 @@ impl GazenotError {
         Self {
             operation: operation.into(),
             help: Some(format!("was accessing this endpoint: {url}")),
-            cause: err.into(),
+            cause: Box::new(err.into()),
         }
     }
 }
@@ -52,19 +64,163 @@ pub enum GazenotErrorInner {
     Url(#[from] url::ParseError),
     #[error(transparent)]
     Asset(#[from] axoasset::AxoassetError),
+    #[error(transparent)]
+    Json(#[from] serde_json::Error),
+    #[error("failed to access {path}")]
+    Io {
+        path: camino::Utf8PathBuf,
+        #[source]
+        details: std::io::Error,
+    },
+    #[error("downloaded {actual} bytes, but the server said there would be {expected}")]
+    LengthMismatch { expected: u64, actual: u64 },
+    #[error("server received a file with SHA-256 {actual}, but we sent {expected}")]
+    ChecksumMismatch { expected: String, actual: String },
+    #[error(transparent)]
+    #[diagnostic(transparent)]
+    ReleaseKey(#[from] crate::InvalidReleaseKey),
+    #[error(transparent)]
+    #[diagnostic(transparent)]
+    InvalidValue(#[from] crate::types::InvalidValue),
+    #[error("{url} can't be used as a base for other URLs")]
+    InvalidBaseUrl { url: String },
+    #[error("{filename:?} isn't a valid filename")]
+    #[diagnostic(help("filenames can't contain path separators"))]
+    InvalidFilename { filename: String },
+    #[error("{segment:?} can't be part of a URL path")]
+    #[diagnostic(help("path segments can't be empty, \".\", or \"..\""))]
+    InvalidPathSegment { segment: String },
+    #[error("request made no progress for {}s", timeout.as_secs_f32())]
+    Stalled { timeout: std::time::Duration },
     #[error("server error {status}")]
     ResponseError {
         status: reqwest::StatusCode,
         #[related]
         errors: Vec<SimpleError>,
+        /// How long the server asked us to wait before trying again
+        retry_after: Option<std::time::Duration>,
+    },
+    #[error("gave up after {attempts} attempts")]
+    Retried {
+        attempts: u32,
+        /// The errors from each attempt before the last
+        #[related]
+        history: Vec<SimpleError>,
+        /// The error from the last attempt
+        #[source]
+        cause: Box<GazenotErrorInner>,
+    },
+    #[error("{} operations failed", errors.len())]
+    Batch {
+        #[related]
+        errors: Vec<GazenotError>,
     },
     #[error("failed to load axodotdev api credentials for Abyss: {reason}")]
-    #[diagnostic(help("is {env_var_name} properly set?"))]
+    #[diagnostic(help("is {provider} properly set?"))]
     AuthKey {
-        reason: &'static str,
-        env_var_name: &'static str,
+        reason: String,
+        /// Description of the [`CredentialProvider`][crate::credentials::CredentialProvider]
+        /// that failed
+        provider: String,
+    },
+    #[error("refusing to send credentials to {url}")]
+    #[diagnostic(help(
+        "credentials are only sent to {allowed}, was an ArtifactSet tampered with?"
+    ))]
+    UntrustedHost { url: String, allowed: String },
+    #[error("dry run has no credentials to read {url} with")]
+    #[diagnostic(help("dry runs still read from The Abyss for real: {reason}"))]
+    DryRunWithoutCredentials { url: String, reason: String },
+    #[error("sealed value is malformed or truncated")]
+    EnvelopeMalformed {
+        #[source]
+        details: serde_json::Error,
     },
+    #[error("sealed value has format version {found}, but we only support {expected}")]
+    #[diagnostic(help("was it sealed by a different version of gazenot?"))]
+    EnvelopeVersion { found: u32, expected: u32 },
+    #[error("sealed value's signature doesn't match its contents")]
+    #[diagnostic(help("it was either modified, or sealed with a different key"))]
+    EnvelopeSignature,
+    #[error("sealed value is a {found}, but we expected a {expected}")]
+    EnvelopeKind {
+        found: String,
+        expected: &'static str,
+    },
+    #[error("the release plan has no ArtifactSet for {package}")]
+    #[diagnostic(help("create one with Gazenot::create_artifact_sets_in_plan first"))]
+    NotInPlan { package: crate::PackageName },
+    #[error("the release plan is for {plan}, but the client is for {client}")]
+    PlanOwner { plan: String, client: String },
+    #[error("no files were given to upload for {package}")]
+    NothingUploaded { package: crate::PackageName },
+    #[error("more than one file would be downloaded to {path}")]
+    #[diagnostic(help(
+        "files with the same name (from different packages, say) need to be downloaded to different directories"
+    ))]
+    DuplicateDownload { path: camino::Utf8PathBuf },
     #[error("attempted to access production API with mock hosting info")]
     #[diagnostic(help("did you run 'cargo dist host create'?"))]
     IsMocked,
+    #[error("{reason}")]
+    #[diagnostic(help("this was reported by {backend}"))]
+    Backend {
+        /// Description of the [`Backend`][crate::backend::Backend] that failed
+        backend: String,
+        reason: String,
+    },
+}
+
+impl GazenotErrorInner {
+    /// Whether this error is plausibly transient, so the request could be retried
+    ///
+    /// If `idempotent` is false, only errors where the request definitely
+    /// wasn't acted on are considered retryable: failing to connect at all,
+    /// or the server rejecting it with a 429 (or a 503 with a `Retry-After`).
+    pub fn is_retryable(&self, idempotent: bool) -> bool {
+        use reqwest::StatusCode;
+        match self {
+            GazenotErrorInner::Reqwest(e) => {
+                e.is_connect() || (idempotent && (e.is_timeout() || e.is_request() || e.is_body()))
+            }
+            GazenotErrorInner::Stalled { .. } | GazenotErrorInner::LengthMismatch { .. } => {
+                idempotent
+            }
+            // Most likely the file changed while we were uploading it,
+            // which will just happen again
+            GazenotErrorInner::ChecksumMismatch { .. } => false,
+            // The server is explicitly telling us it turned the request away
+            // without doing anything, so it's safe to retry no matter what
+            GazenotErrorInner::ResponseError {
+                status: StatusCode::TOO_MANY_REQUESTS,
+                ..
+            }
+            | GazenotErrorInner::ResponseError {
+                status: StatusCode::SERVICE_UNAVAILABLE,
+                retry_after: Some(_),
+                ..
+            } => true,
+            GazenotErrorInner::ResponseError { status, .. } => {
+                idempotent
+                    && matches!(
+                        *status,
+                        StatusCode::REQUEST_TIMEOUT
+                            | StatusCode::TOO_MANY_REQUESTS
+                            | StatusCode::INTERNAL_SERVER_ERROR
+                            | StatusCode::BAD_GATEWAY
+                            | StatusCode::SERVICE_UNAVAILABLE
+                            | StatusCode::GATEWAY_TIMEOUT
+                    )
+            }
+            _ => false,
+        }
+    }
+
+    /// How long the server asked us to wait before retrying, if it did
+    pub fn retry_after(&self) -> Option<std::time::Duration> {
+        match self {
+            GazenotErrorInner::ResponseError { retry_after, .. } => *retry_after,
+            _ => None,
+        }
+    }
 }
@@ -5,46 +5,75 @@
 //!
 #![cfg_attr(feature = "client_lib", doc = include_str!("../example.md"))]
 #[cfg(feature = "client_lib")]
+pub mod backend;
+#[cfg(feature = "client_lib")]
 mod client;
 #[cfg(feature = "client_lib")]
+pub mod credentials;
+#[cfg(feature = "client_lib")]
+pub mod dry_run;
+#[cfg(feature = "client_lib")]
+pub mod envelope;
+#[cfg(feature = "client_lib")]
 pub mod error;
 #[cfg(feature = "client_lib")]
-pub use client::Gazenot;
+pub mod lifecycle;
+#[cfg(feature = "client_lib")]
+pub mod plan;
+#[cfg(feature = "client_lib")]
+pub mod report;
+#[cfg(feature = "client_lib")]
+mod retry;
+#[cfg(feature = "client_lib")]
+pub mod route;
+#[cfg(feature = "testing")]
+pub mod testing;
+pub mod types;
+#[cfg(feature = "client_lib")]
+pub use client::{
+    ArtifactSetFilter, AuthSource, DownloadSource, FailureMode, Gazenot, GazenotBuilder, Operation,
+};
+#[cfg(feature = "client_lib")]
+pub use retry::RetryPolicy;
+
+pub use types::{
+    ArtifactSetId, InvalidReleaseKey, Owner, PackageName, ReleaseTag, SourceHost, UnparsedUrl,
+    UnparsedVersion,
+};
 
 use schemars::JsonSchema;
 use serde::{Deserialize, Serialize};
 
-/// The public_id of an ArtifactSet
-pub type ArtifactSetId = String;
-/// The owner of a package
-pub type Owner = String;
-/// The source hosting provider (e.g. "github")
-pub type SourceHost = String;
-/// The name of a package
-pub type PackageName = String;
-/// The tag for a Release
-pub type ReleaseTag = String;
-/// An unparsed URL
-pub type UnparsedUrl = String;
-/// An unparsed SemVer Version
-pub type UnparsedVersion = String;
+/// An unparsed RFC 3339 timestamp
+pub type UnparsedTimestamp = String;
 
 /// A handle for talking about ArtifactSets
+///
+/// Everything but the package came from the server, so (like in [`ArtifactSetInfo`][])
+/// it isn't validated when deserialized, and whatever the server said survives a round trip.
 #[derive(Serialize, Deserialize, Debug, Clone, JsonSchema)]
 pub struct ArtifactSet {
     pub package: PackageName,
+    #[serde(deserialize_with = "crate::types::lenient")]
     pub public_id: ArtifactSetId,
 
+    #[serde(default, deserialize_with = "crate::types::lenient_option")]
     pub set_download_url: Option<UnparsedUrl>,
+    #[serde(default, deserialize_with = "crate::types::lenient_option")]
     pub upload_url: Option<UnparsedUrl>,
+    #[serde(default, deserialize_with = "crate::types::lenient_option")]
     pub release_url: Option<UnparsedUrl>,
+    #[serde(default, deserialize_with = "crate::types::lenient_option")]
     pub announce_url: Option<UnparsedUrl>,
 }
 
 pub const MOCK_ARTIFACT_SET_PUBLIC_ID: &str = "fake-id-do-not-upload";
 
+/// Where mock ArtifactSets and Releases pretend to be hosted
+pub const MOCK_HOSTING_URL: &str = "https://fake.axo.dev/faker";
+
 impl ArtifactSet {
-    pub fn new(package: String, public_id: ArtifactSetId) -> Self {
+    pub fn new(package: PackageName, public_id: ArtifactSetId) -> Self {
         Self {
             package,
             public_id,
@@ -58,16 +87,23 @@ impl ArtifactSet {
     /// Create a mock ArtifactSet that can be used for internal consistency checks
     /// without hitting the server.
     ///
+    /// Mock ArtifactSets can be passed to any operation that changes something
+    /// ([`Gazenot::upload_files`][], [`Gazenot::create_releases`][], etc.), which will do
+    /// all the local work (like reading the files) but only pretend to talk to the server.
+    /// This lets a whole release be rehearsed. Nothing is really hosted for them, so
+    /// operations that read from The Abyss (like downloads) still refuse them.
     /// Also can be used for tests.
-    pub fn mock(package: String) -> Self {
+    pub fn mock(package: PackageName) -> Self {
         // This URL is gibberish but it needs to exist for some things
-        let set_download_url = Some(format!(
-            "https://fake.axo.dev/faker/{package}/{MOCK_ARTIFACT_SET_PUBLIC_ID}"
-        ));
+        let set_download_url = UnparsedUrl::new(format!(
+            "{MOCK_HOSTING_URL}/{package}/{MOCK_ARTIFACT_SET_PUBLIC_ID}"
+        ))
+        .expect("mock url should be valid");
         Self {
             package,
-            public_id: MOCK_ARTIFACT_SET_PUBLIC_ID.to_owned(),
-            set_download_url,
+            public_id: ArtifactSetId::new(MOCK_ARTIFACT_SET_PUBLIC_ID)
+                .expect("mock id should be valid"),
+            set_download_url: Some(set_download_url),
             upload_url: None,
             release_url: None,
             announce_url: None,
@@ -79,6 +115,9 @@ impl ArtifactSet {
     }
 
     pub fn to_release(&self, tag: ReleaseTag) -> Release {
+        if self.is_mock() {
+            return Release::mock(self.package.clone(), tag);
+        }
         Release {
             package: self.package.clone(),
             tag,
@@ -88,16 +127,35 @@ impl ArtifactSet {
     }
 }
 
+/// A file that was uploaded to an ArtifactSet
+#[derive(Serialize, Deserialize, Debug, Clone, JsonSchema)]
+pub struct UploadedFile {
+    /// The package the ArtifactSet belongs to
+    pub package: PackageName,
+    /// The public_id of the ArtifactSet
+    #[serde(deserialize_with = "crate::types::lenient")]
+    pub public_id: ArtifactSetId,
+    /// Name of the file
+    pub filename: String,
+    /// Size of the file in bytes
+    pub size: u64,
+    /// SHA-256 of the file, hex-encoded
+    pub sha256: String,
+}
+
 #[derive(Serialize, Deserialize, Debug, Clone, JsonSchema)]
 pub struct Release {
     pub package: PackageName,
     pub tag: ReleaseTag,
+    // Like in ArtifactSet, these came from the server so they aren't validated
+    #[serde(default, deserialize_with = "crate::types::lenient_option")]
     pub release_download_url: Option<UnparsedUrl>,
+    #[serde(default, deserialize_with = "crate::types::lenient_option")]
     pub announce_url: Option<UnparsedUrl>,
 }
 
 impl Release {
-    pub fn new(package: String, tag: ReleaseTag) -> Self {
+    pub fn new(package: PackageName, tag: ReleaseTag) -> Self {
         Self {
             package,
             tag,
@@ -105,6 +163,29 @@ impl Release {
             announce_url: None,
         }
     }
+
+    /// Create a mock Release, like the ones created from a mock ArtifactSet
+    ///
+    /// See [`ArtifactSet::mock`][].
+    pub fn mock(package: PackageName, tag: ReleaseTag) -> Self {
+        // Just as gibberish as a mock ArtifactSet's, nothing is ever downloaded from it
+        let release_download_url = UnparsedUrl::new(format!(
+            "{MOCK_HOSTING_URL}/{package}/{MOCK_ARTIFACT_SET_PUBLIC_ID}"
+        ))
+        .expect("mock url should be valid");
+        Self {
+            package,
+            tag,
+            release_download_url: Some(release_download_url),
+            announce_url: None,
+        }
+    }
+
+    pub fn is_mock(&self) -> bool {
+        self.release_download_url
+            .as_ref()
+            .is_some_and(|url| url.starts_with(&format!("{MOCK_HOSTING_URL}/")))
+    }
 }
 
 /// Info needed to create a release
@@ -122,15 +203,94 @@ pub struct ReleaseKey {
     pub tag: ReleaseTag,
     /// Version of the package
     ///
-    /// This must agree with the tag, the server will check it with axotag.
+    /// This must agree with the tag, the server will check it with axotag
+    /// (and so will [`ReleaseKey::validate`][] before anything is sent).
     pub version: UnparsedVersion,
 
     /// Whether this release should be considered a prerelease
     ///
-    /// This must agree with the tag, the server will check it with axotag.
+    /// This must agree with the tag, the server will check it with axotag
+    /// (and so will [`ReleaseKey::validate`][] before anything is sent).
     pub is_prerelease: bool,
 }
 
+impl ReleaseKey {
+    /// Compute the version and whether it's a prerelease from a tag
+    ///
+    /// The tag can be just a version ("1.2.3" or "v1.2.3"), or be prefixed with
+    /// the name of the package ("my-app-v1.2.3" or "my-app/v1.2.3").
+    ///
+    /// ```
+    /// use gazenot::ReleaseKey;
+    ///
+    /// let key = ReleaseKey::from_tag("my-app", "my-app-v1.2.3-beta.1".parse().unwrap()).unwrap();
+    /// assert_eq!(key.version, "1.2.3-beta.1");
+    /// assert!(key.is_prerelease);
+    ///
+    /// assert!(ReleaseKey::from_tag("my-app", "other-app-v1.2.3".parse().unwrap()).is_err());
+    /// ```
+    pub fn from_tag(package: &str, tag: ReleaseTag) -> Result<Self, InvalidReleaseKey> {
+        let version = types::version_from_tag(package, &tag).map_err(|(range, reason)| {
+            let key = format!("tag: {tag}\n");
+            let offset = "tag: ".len();
+            InvalidReleaseKey {
+                package: package.to_owned(),
+                key,
+                reason,
+                span: (offset + range.start, range.end - range.start).into(),
+                help: Some(format!("tags look like \"v1.2.3\" or \"{package}-v1.2.3\"")),
+            }
+        })?;
+        Ok(Self {
+            is_prerelease: types::is_prerelease(&version),
+            version,
+            tag,
+        })
+    }
+
+    /// Check that the version and prerelease flag agree with the tag
+    ///
+    /// The Abyss checks this too, but checking first catches mistakes before anything
+    /// is sent to the server. See [`ReleaseKey::from_tag`][] for the tags that are understood.
+    pub fn validate(&self, package: &str) -> Result<(), InvalidReleaseKey> {
+        let expected = Self::from_tag(package, self.tag.clone())?;
+        let Self {
+            tag,
+            version,
+            is_prerelease,
+        } = self;
+
+        let tag_line = format!("tag: {tag}\n");
+        let version_line = format!("version: {version}\n");
+        let version_offset = tag_line.len() + "version: ".len();
+        let prerelease_offset = tag_line.len() + version_line.len() + "is_prerelease: ".len();
+        let key = format!("{tag_line}{version_line}is_prerelease: {is_prerelease}\n");
+
+        let (reason, span) = if *version != expected.version {
+            (
+                format!("the tag has version {}", expected.version),
+                (version_offset, version.len()),
+            )
+        } else if *is_prerelease != expected.is_prerelease {
+            let reason = if expected.is_prerelease {
+                format!("{version} is a prerelease")
+            } else {
+                format!("{version} isn't a prerelease")
+            };
+            (reason, (prerelease_offset, is_prerelease.to_string().len()))
+        } else {
+            return Ok(());
+        };
+        Err(InvalidReleaseKey {
+            package: package.to_owned(),
+            key,
+            reason,
+            span: span.into(),
+            help: Some("use ReleaseKey::from_tag to compute these from the tag".to_owned()),
+        })
+    }
+}
+
 /// Info needed to create an announement
 #[derive(Debug, Clone)]
 pub struct AnnouncementKey {
@@ -140,12 +300,112 @@ pub struct AnnouncementKey {
     pub body: String,
 }
 
+/// Everything The Abyss knows about an ArtifactSet, see [`Gazenot::get_artifact_set`][]
+#[derive(Serialize, Deserialize, Debug, Clone, JsonSchema)]
+pub struct ArtifactSetInfo {
+    pub package: PackageName,
+    #[serde(deserialize_with = "crate::types::lenient")]
+    pub public_id: ArtifactSetId,
+
+    #[serde(default, deserialize_with = "crate::types::lenient_option")]
+    pub set_download_url: Option<UnparsedUrl>,
+    #[serde(default, deserialize_with = "crate::types::lenient_option")]
+    pub upload_url: Option<UnparsedUrl>,
+    #[serde(default, deserialize_with = "crate::types::lenient_option")]
+    pub release_url: Option<UnparsedUrl>,
+    #[serde(default, deserialize_with = "crate::types::lenient_option")]
+    pub announce_url: Option<UnparsedUrl>,
+
+    /// When the ArtifactSet was created
+    pub created_at: UnparsedTimestamp,
+    /// What the ArtifactSet has been used for
+    pub state: ArtifactSetState,
+    /// The files that have been uploaded to the ArtifactSet
+    #[serde(default)]
+    pub files: Vec<ArtifactSetFile>,
+}
+
+impl ArtifactSetInfo {
+    /// Get the file with the given name, if it was uploaded
+    pub fn file(&self, name: &str) -> Option<&ArtifactSetFile> {
+        self.files.iter().find(|file| file.name == name)
+    }
+
+    pub fn to_artifact_set(&self) -> ArtifactSet {
+        ArtifactSet {
+            package: self.package.clone(),
+            public_id: self.public_id.clone(),
+            set_download_url: self.set_download_url.clone(),
+            upload_url: self.upload_url.clone(),
+            release_url: self.release_url.clone(),
+            announce_url: self.announce_url.clone(),
+        }
+    }
+}
+
+/// The state of an ArtifactSet
+#[derive(Serialize, Deserialize, Debug, Clone, Copy, PartialEq, Eq, JsonSchema)]
+#[serde(rename_all = "snake_case")]
+pub enum ArtifactSetState {
+    /// Files can still be uploaded, and no Release has been created from it
+    Open,
+    /// A Release has been created from it
+    Released,
+    /// A state this version of gazenot doesn't know about
+    #[serde(other)]
+    Unknown,
+}
+
+/// A file that was uploaded to an ArtifactSet, as reported by The Abyss
+#[derive(Serialize, Deserialize, Debug, Clone, JsonSchema)]
+pub struct ArtifactSetFile {
+    /// Name of the file
+    pub name: String,
+    /// Size of the file in bytes
+    pub size: u64,
+    /// SHA-256 of the file, hex-encoded
+    pub sha256: String,
+    /// URL the file can be downloaded from
+    #[serde(default, deserialize_with = "crate::types::lenient_option")]
+    pub download_url: Option<UnparsedUrl>,
+}
+
 /// A listing of the releases for a package
-///
-/// CURRENTLY A STUB, TBD
-#[derive(Debug, Clone)]
+#[derive(Serialize, Deserialize, Debug, Clone, JsonSchema)]
 pub struct ReleaseList {
     /// Name of the package
     pub package: PackageName,
-    // TBD
+    /// The releases of the package, in the order the server listed them
+    pub releases: Vec<ReleaseInfo>,
+}
+
+/// Info about a Release that The Abyss is hosting
+#[derive(Serialize, Deserialize, Debug, Clone, JsonSchema)]
+pub struct ReleaseInfo {
+    /// Git tag for the release
+    #[serde(deserialize_with = "crate::types::lenient")]
+    pub tag: ReleaseTag,
+    /// Version of the package
+    #[serde(deserialize_with = "crate::types::lenient")]
+    pub version: UnparsedVersion,
+    /// Whether this release is considered a prerelease
+    pub is_prerelease: bool,
+    /// When the release was created
+    pub created_at: UnparsedTimestamp,
+    /// URL that the release's artifacts can be downloaded from
+    #[serde(default, deserialize_with = "crate::types::lenient_option")]
+    pub release_download_url: Option<UnparsedUrl>,
+    /// The artifacts (files) in the release
+    #[serde(default)]
+    pub artifacts: Vec<ReleaseArtifact>,
+}
+
+/// A file in a Release
+#[derive(Serialize, Deserialize, Debug, Clone, JsonSchema)]
+pub struct ReleaseArtifact {
+    /// Name of the file
+    pub name: String,
+    /// URL the file can be downloaded from
+    #[serde(deserialize_with = "crate::types::lenient")]
+    pub download_url: UnparsedUrl,
 }
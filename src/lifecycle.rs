@@ -0,0 +1,256 @@
+//! A typed version of the release process, where each step can only follow the previous one
+//!
+//! The methods in the rest of the crate take plain [`ArtifactSet`][]s and [`Release`][]s,
+//! which makes it easy to do things out of order, like creating a Release from an
+//! ArtifactSet that never had anything uploaded to it, or announcing a Release made up with
+//! [`ArtifactSet::to_release`][]. The methods here instead consume one state and return the
+//! next, so the compiler makes sure every step happened:
+//!
+//! ```text
+//! PendingSet -> UploadedSet -> PublishedRelease -> Announced
+//! ```
+//!
+//! The states can only be created by these methods, so they can't be passed between
+//! machines. If the steps of your release happen on different machines, use the untyped API.
+//!
+//! ```no_run
+//! use camino::Utf8PathBuf;
+//! use gazenot::{AnnouncementKey, Gazenot, ReleaseKey};
+//!
+//! # #[tokio::main]
+//! # async fn main() -> Result<(), miette::Report> {
+//! let abyss = Gazenot::into_the_abyss("github".parse()?, "axodotdev".parse()?)?;
+//!
+//! let pending = abyss.create_pending_sets(["app1".parse()?]).await?;
+//! let uploads = pending.into_iter().map(|set| {
+//!     let files = vec![Utf8PathBuf::from("dist-manifest.json")];
+//!     (set, files)
+//! });
+//! let uploaded = abyss.upload_pending_sets(uploads).await?;
+//! let key = ReleaseKey {
+//!     version: "1.0.1".parse()?,
+//!     tag: "v1.0.1".parse()?,
+//!     is_prerelease: false,
+//! };
+//! let releases = uploaded.into_iter().map(|set| (set, key.clone()));
+//! let published = abyss.publish_uploaded_sets(releases).await?;
+//! let announcement = AnnouncementKey {
+//!     body: "# v1.0.1\n\nWow Cool Changelog".to_owned(),
+//! };
+//! let _announced = abyss
+//!     .announce_published_releases(published, announcement)
+//!     .await?;
+//! # Ok(())
+//! # }
+//! ```
+//!
+//! Skipping a step doesn't compile:
+//!
+//! ```compile_fail
+//! # use gazenot::{Gazenot, ReleaseKey};
+//! # async fn skip(abyss: Gazenot, key: ReleaseKey) -> Result<(), miette::Report> {
+//! let pending = abyss.create_pending_sets(["app1".parse()?]).await?;
+//! let releases = pending.into_iter().map(|set| (set, key.clone()));
+//! abyss.publish_uploaded_sets(releases).await?;
+//! # Ok(())
+//! # }
+//! ```
+//!
+//! And neither does doing one twice, since the states can't be cloned:
+//!
+//! ```compile_fail
+//! # use gazenot::{lifecycle::PublishedRelease, AnnouncementKey, Gazenot};
+//! # async fn twice(
+//! #     abyss: Gazenot,
+//! #     published: Vec<PublishedRelease>,
+//! #     announcement: AnnouncementKey,
+//! # ) -> Result<(), miette::Report> {
+//! abyss
+//!     .announce_published_releases(published.clone(), announcement.clone())
+//!     .await?;
+//! abyss
+//!     .announce_published_releases(published, announcement)
+//!     .await?;
+//! # Ok(())
+//! # }
+//! ```
+
+use camino::Utf8PathBuf;
+
+use crate::{
+    error::{GazenotError, GazenotErrorInner, Result},
+    AnnouncementKey, ArtifactSet, Gazenot, PackageName, Release, ReleaseKey, UploadedFile,
+};
+
+/// An ArtifactSet that was just created, and has nothing uploaded to it yet
+#[derive(Debug)]
+pub struct PendingSet {
+    set: ArtifactSet,
+}
+
+impl PendingSet {
+    /// The underlying ArtifactSet
+    pub fn artifact_set(&self) -> &ArtifactSet {
+        &self.set
+    }
+
+    /// Go back to the untyped API
+    pub fn into_artifact_set(self) -> ArtifactSet {
+        self.set
+    }
+}
+
+/// An ArtifactSet that has had files uploaded to it
+#[derive(Debug)]
+pub struct UploadedSet {
+    set: ArtifactSet,
+    files: Vec<UploadedFile>,
+}
+
+impl UploadedSet {
+    /// The underlying ArtifactSet
+    pub fn artifact_set(&self) -> &ArtifactSet {
+        &self.set
+    }
+
+    /// The files that were uploaded to it
+    pub fn uploaded_files(&self) -> &[UploadedFile] {
+        &self.files
+    }
+
+    /// Go back to the untyped API
+    pub fn into_artifact_set(self) -> ArtifactSet {
+        self.set
+    }
+}
+
+/// A Release that was created from an [`UploadedSet`][], but not yet announced
+#[derive(Debug)]
+pub struct PublishedRelease {
+    set: ArtifactSet,
+    files: Vec<UploadedFile>,
+    release: Release,
+}
+
+impl PublishedRelease {
+    /// The ArtifactSet the Release was created from
+    pub fn artifact_set(&self) -> &ArtifactSet {
+        &self.set
+    }
+
+    /// The files that were uploaded to the ArtifactSet
+    pub fn uploaded_files(&self) -> &[UploadedFile] {
+        &self.files
+    }
+
+    /// The underlying Release
+    pub fn release(&self) -> &Release {
+        &self.release
+    }
+
+    /// Go back to the untyped API
+    pub fn into_release(self) -> Release {
+        self.release
+    }
+}
+
+/// Releases that have been announced, the end of the process
+#[derive(Debug)]
+pub struct Announced {
+    releases: Vec<PublishedRelease>,
+}
+
+impl Announced {
+    /// The Releases that were announced
+    pub fn releases(&self) -> &[PublishedRelease] {
+        &self.releases
+    }
+}
+
+impl Gazenot {
+    /// [`Gazenot::create_artifact_sets`][], returning [`PendingSet`][]s
+    pub async fn create_pending_sets(
+        &self,
+        packages: impl IntoIterator<Item = PackageName>,
+    ) -> Result<Vec<PendingSet>> {
+        let sets = self.create_artifact_sets(packages).await?;
+        Ok(sets.into_iter().map(|set| PendingSet { set }).collect())
+    }
+
+    /// [`Gazenot::upload_files`][], turning [`PendingSet`][]s into [`UploadedSet`][]s
+    ///
+    /// Every set must have at least one file to upload.
+    pub async fn upload_pending_sets(
+        &self,
+        sets: impl IntoIterator<Item = (PendingSet, Vec<Utf8PathBuf>)>,
+    ) -> Result<Vec<UploadedSet>> {
+        let sets = sets.into_iter().collect::<Vec<_>>();
+        for (PendingSet { set }, files) in &sets {
+            if files.is_empty() {
+                return Err(GazenotError::new(
+                    format!(
+                        "upload files to hosting for {}/{}/{}",
+                        self.endpoints.source_host, self.endpoints.owner, set.package
+                    ),
+                    GazenotErrorInner::NothingUploaded {
+                        package: set.package.clone(),
+                    },
+                ));
+            }
+        }
+
+        let uploads = self
+            .upload_files(
+                sets.iter()
+                    .map(|(pending, files)| (&pending.set, files.clone())),
+            )
+            .await?;
+
+        // Uploads come back in the same order they went in, so hand them back out in order
+        let mut uploads = uploads.into_iter();
+        Ok(sets
+            .into_iter()
+            .map(|(PendingSet { set }, files)| UploadedSet {
+                set,
+                files: uploads.by_ref().take(files.len()).collect(),
+            })
+            .collect())
+    }
+
+    /// [`Gazenot::create_releases`][], turning [`UploadedSet`][]s into [`PublishedRelease`][]s
+    pub async fn publish_uploaded_sets(
+        &self,
+        sets: impl IntoIterator<Item = (UploadedSet, ReleaseKey)>,
+    ) -> Result<Vec<PublishedRelease>> {
+        let sets = sets.into_iter().collect::<Vec<_>>();
+        let releases = self
+            .create_releases(
+                sets.iter()
+                    .map(|(uploaded, key)| (&uploaded.set, key.clone())),
+            )
+            .await?;
+        Ok(sets
+            .into_iter()
+            .zip(releases)
+            .map(
+                |((UploadedSet { set, files }, _), release)| PublishedRelease {
+                    set,
+                    files,
+                    release,
+                },
+            )
+            .collect())
+    }
+
+    /// [`Gazenot::create_announcements`][] for [`PublishedRelease`][]s
+    pub async fn announce_published_releases(
+        &self,
+        releases: impl IntoIterator<Item = PublishedRelease>,
+        announcement: AnnouncementKey,
+    ) -> Result<Announced> {
+        let releases = releases.into_iter().collect::<Vec<_>>();
+        self.create_announcements(releases.iter().map(|r| &r.release), announcement)
+            .await?;
+        Ok(Announced { releases })
+    }
+}
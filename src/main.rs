@@ -0,0 +1,474 @@
+//! The `gazenot` command line tool
+//!
+//! Each subcommand is one step of a release, and steps hand each other
+//! ArtifactSets and Releases as json files, so each stage of CI can run
+//! one command and pass its output along:
+//!
+//! ```text
+//! gazenot --owner axodotdev create-artifact-sets app1 app2 -o sets.json
+//! gazenot --owner axodotdev upload --sets sets.json app1=dist/app1.tar.gz app2=dist/app2.zip
+//! gazenot --owner axodotdev create-releases --sets sets.json --tag v1.0.0 -o releases.json
+//! gazenot --owner axodotdev announce --releases releases.json --body-file RELEASE_NOTES.md
+//! ```
+
+use axoasset::LocalAsset;
+use camino::{Utf8Path, Utf8PathBuf};
+use clap::{Args, Parser, Subcommand};
+use gazenot::{
+    backend::LocalBackend,
+    credentials::{CommandCredentials, EnvVarCredentials, FileCredentials},
+    error::{GazenotError, Result},
+    AnnouncementKey, ArtifactSet, AuthSource, DownloadSource, Gazenot, Owner, PackageName, Release,
+    ReleaseKey, ReleaseTag, SourceHost,
+};
+use serde::{de::DeserializeOwned, Serialize};
+
+#[derive(Parser)]
+#[command(
+    version,
+    about = "Gaze Not Into The Abyss, Lest You Become A Release Engineer"
+)]
+struct Cli {
+    #[command(flatten)]
+    client: ClientArgs,
+    /// Write a json report of every request that was made to this file (even if some failed)
+    #[arg(long)]
+    report: Option<Utf8PathBuf>,
+    #[command(subcommand)]
+    command: Command,
+}
+
+/// Which Abyss to talk to, and as who
+#[derive(Args)]
+struct ClientArgs {
+    /// The source hosting provider of the owner
+    #[arg(long, env = "GAZENOT_SOURCE_HOST", default_value = "github")]
+    source_host: SourceHost,
+    /// The owner of the packages
+    #[arg(long, env = "GAZENOT_OWNER")]
+    owner: Owner,
+    /// Domain of the API server
+    #[arg(long, env = "GAZENOT_API_SERVER")]
+    api_server: Option<String>,
+    /// Domain of the hosting server
+    #[arg(long, env = "GAZENOT_HOSTING_SERVER")]
+    hosting_server: Option<String>,
+    /// URL scheme for both servers
+    #[arg(long, env = "GAZENOT_SCHEME")]
+    scheme: Option<String>,
+    /// Put the owner in the path of hosting URLs, instead of in a subdomain
+    #[arg(long)]
+    no_owner_subdomain: bool,
+    /// Don't authenticate (only works for listing releases and downloading)
+    #[arg(long)]
+    unauthed: bool,
+    /// Where to get the Axo Releases Token: env:VAR, file:PATH, or command:PROGRAM [ARGS...]
+    ///
+    /// A command is run as a git-style credential helper.
+    /// Defaults to env:AXO_RELEASES_TOKEN.
+    #[arg(
+        long,
+        env = "GAZENOT_CREDENTIALS",
+        value_parser = parse_credentials,
+        conflicts_with = "unauthed"
+    )]
+    credentials: Option<Credentials>,
+    /// Don't send anything that would change something, print the requests to stderr instead
+    ///
+    /// No token is needed for a dry run, unless it has to read from The Abyss.
+    #[arg(long)]
+    dry_run: bool,
+    /// Keep everything in this directory instead of on The Abyss (no token needed)
+    #[arg(long, env = "GAZENOT_LOCAL_DIR")]
+    local_dir: Option<Utf8PathBuf>,
+}
+
+#[derive(Subcommand)]
+enum Command {
+    /// Create ArtifactSets for packages, and write them out as json
+    CreateArtifactSets {
+        /// The packages to create ArtifactSets for
+        #[arg(required = true)]
+        packages: Vec<PackageName>,
+        #[command(flatten)]
+        output: Output,
+    },
+    /// Upload files to ArtifactSets, and write out what was uploaded as json
+    Upload {
+        /// A json file of ArtifactSets, from create-artifact-sets
+        #[arg(long)]
+        sets: Utf8PathBuf,
+        /// The files to upload, as PACKAGE=PATH
+        #[arg(required = true, value_parser = parse_package_pair::<Utf8PathBuf>)]
+        files: Vec<(PackageName, Utf8PathBuf)>,
+        #[command(flatten)]
+        output: Output,
+    },
+    /// Create Releases from ArtifactSets, and write them out as json
+    CreateReleases {
+        /// A json file of ArtifactSets, from create-artifact-sets
+        #[arg(long)]
+        sets: Utf8PathBuf,
+        /// The git tag of the release (e.g. "v1.0.0" or "my-app-v1.0.0")
+        ///
+        /// The version, and whether it's a prerelease, are computed from this.
+        #[arg(long)]
+        tag: ReleaseTag,
+        /// Only release these packages (defaults to every ArtifactSet in --sets)
+        packages: Vec<PackageName>,
+        #[command(flatten)]
+        output: Output,
+    },
+    /// Announce Releases
+    Announce {
+        /// A json file of Releases, from create-releases
+        #[arg(long)]
+        releases: Utf8PathBuf,
+        /// The markdown body of the announcement
+        #[arg(
+            long,
+            required_unless_present = "body_file",
+            conflicts_with = "body_file"
+        )]
+        body: Option<String>,
+        /// A file containing the markdown body of the announcement
+        #[arg(long)]
+        body_file: Option<Utf8PathBuf>,
+    },
+    /// List the releases of packages as json
+    ListReleases {
+        /// The packages to list the releases of
+        #[arg(required = true)]
+        packages: Vec<PackageName>,
+        #[command(flatten)]
+        output: Output,
+    },
+    /// Download files from ArtifactSets or Releases, printing where they were saved
+    Download {
+        /// A json file of ArtifactSets to download from, from create-artifact-sets
+        #[arg(
+            long,
+            required_unless_present = "releases",
+            conflicts_with = "releases"
+        )]
+        sets: Option<Utf8PathBuf>,
+        /// A json file of Releases to download from, from create-releases
+        #[arg(long)]
+        releases: Option<Utf8PathBuf>,
+        /// The directory to download the files to
+        #[arg(long, default_value = ".")]
+        dir: Utf8PathBuf,
+        /// The files to download, as PACKAGE=FILENAME
+        #[arg(required = true, value_parser = parse_package_pair::<String>)]
+        files: Vec<(PackageName, String)>,
+    },
+}
+
+/// Where to get the Axo Releases Token, see `--credentials`
+#[derive(Debug, Clone, PartialEq)]
+enum Credentials {
+    Env(String),
+    File(Utf8PathBuf),
+    Command(String, Vec<String>),
+}
+
+fn parse_credentials(value: &str) -> std::result::Result<Credentials, String> {
+    let expected = || format!("expected env:VAR, file:PATH, or command:PROGRAM, got {value:?}");
+    let (kind, rest) = value.split_once(':').ok_or_else(expected)?;
+    if rest.trim().is_empty() {
+        return Err(expected());
+    }
+    match kind {
+        "env" => Ok(Credentials::Env(rest.to_owned())),
+        "file" => Ok(Credentials::File(rest.into())),
+        "command" => {
+            let mut words = rest.split_whitespace().map(|word| word.to_owned());
+            let program = words.next().ok_or_else(expected)?;
+            Ok(Credentials::Command(program, words.collect()))
+        }
+        _ => Err(expected()),
+    }
+}
+
+/// Where to write json output
+#[derive(Args)]
+struct Output {
+    /// Write the output to this file instead of stdout
+    #[arg(short, long)]
+    output: Option<Utf8PathBuf>,
+}
+
+fn parse_package_pair<T: From<String>>(
+    value: &str,
+) -> std::result::Result<(PackageName, T), String> {
+    let (package, rest) = value
+        .split_once('=')
+        .ok_or_else(|| format!("expected PACKAGE=VALUE, got {value:?}"))?;
+    let package = package.parse::<PackageName>().map_err(|e| e.to_string())?;
+    Ok((package, T::from(rest.to_owned())))
+}
+
+#[tokio::main]
+async fn main() -> std::result::Result<(), miette::Report> {
+    let Cli {
+        client,
+        report,
+        command,
+    } = Cli::parse();
+    let abyss = client.build(report.is_some())?;
+
+    let result = run(&abyss, command).await;
+    if abyss.is_dry_run() {
+        let planned = abyss.take_planned_requests();
+        let json = serde_json::to_string_pretty(&planned)
+            .map_err(|e| GazenotError::new("print planned requests", e))?;
+        eprintln!("{json}");
+    }
+    if let Some(path) = report {
+        abyss.take_report().save(path)?;
+    }
+    result
+}
+
+async fn run(abyss: &Gazenot, command: Command) -> miette::Result<()> {
+    match command {
+        Command::CreateArtifactSets { packages, output } => {
+            let sets = abyss.create_artifact_sets(packages).await?;
+            output.write(&sets)?;
+        }
+        Command::Upload {
+            sets,
+            files,
+            output,
+        } => {
+            let sets = read_json::<Vec<ArtifactSet>>(&sets)?;
+            let mut uploads = Vec::<(&ArtifactSet, Vec<Utf8PathBuf>)>::new();
+            for (package, file) in files {
+                let set = find_package(&sets, &package, |set| &set.package)?;
+                match uploads.iter_mut().find(|(s, _)| s.package == package) {
+                    Some((_, files)) => files.push(file),
+                    None => uploads.push((set, vec![file])),
+                }
+            }
+            let uploaded = abyss.upload_files(uploads).await?;
+            output.write(&uploaded)?;
+        }
+        Command::CreateReleases {
+            sets,
+            tag,
+            packages,
+            output,
+        } => {
+            let sets = read_json::<Vec<ArtifactSet>>(&sets)?;
+            let to_release = if packages.is_empty() {
+                sets.iter().collect::<Vec<_>>()
+            } else {
+                packages
+                    .iter()
+                    .map(|package| find_package(&sets, package, |set| &set.package))
+                    .collect::<miette::Result<_>>()?
+            };
+            let mut releases = vec![];
+            for set in to_release {
+                let key = ReleaseKey::from_tag(&set.package, tag.clone()).map_err(|e| {
+                    GazenotError::new(format!("create release for {}", set.package), e)
+                })?;
+                releases.push((set, key));
+            }
+            let releases = abyss.create_releases(releases).await?;
+            output.write(&releases)?;
+        }
+        Command::Announce {
+            releases,
+            body,
+            body_file,
+        } => {
+            let releases = read_json::<Vec<Release>>(&releases)?;
+            let body = match (body, body_file) {
+                (Some(body), _) => body,
+                (None, Some(path)) => LocalAsset::load_string(&path)
+                    .map_err(|e| GazenotError::new("read announcement body", e))?,
+                (None, None) => unreachable!("clap requires one of --body and --body-file"),
+            };
+            abyss
+                .create_announcements(&releases, AnnouncementKey { body })
+                .await?;
+        }
+        Command::ListReleases { packages, output } => {
+            let releases = abyss.list_releases_many(packages).await?;
+            output.write(&releases)?;
+        }
+        Command::Download {
+            sets,
+            releases,
+            dir,
+            files,
+        } => {
+            let paths = match (sets, releases) {
+                (Some(sets), _) => {
+                    let sets = read_json::<Vec<ArtifactSet>>(&sets)?;
+                    download(abyss, &sets, |set| &set.package, files, &dir).await?
+                }
+                (None, Some(releases)) => {
+                    let releases = read_json::<Vec<Release>>(&releases)?;
+                    download(abyss, &releases, |r| &r.package, files, &dir).await?
+                }
+                (None, None) => unreachable!("clap requires one of --sets and --releases"),
+            };
+            for path in paths {
+                println!("{path}");
+            }
+        }
+    }
+    Ok(())
+}
+
+impl ClientArgs {
+    fn build(self, record_report: bool) -> Result<Gazenot> {
+        let Self {
+            source_host,
+            owner,
+            api_server,
+            hosting_server,
+            scheme,
+            no_owner_subdomain,
+            unauthed,
+            credentials,
+            dry_run,
+            local_dir,
+        } = self;
+        let mut builder = Gazenot::builder(source_host, owner)
+            .hosting_owner_subdomain(!no_owner_subdomain)
+            .record_report(record_report)
+            .dry_run(dry_run);
+        if let Some(api_server) = api_server {
+            builder = builder.api_server(api_server);
+        }
+        if let Some(hosting_server) = hosting_server {
+            builder = builder.hosting_server(hosting_server);
+        }
+        if let Some(scheme) = scheme {
+            builder = builder.scheme(scheme);
+        }
+        if unauthed {
+            builder = builder.auth(AuthSource::None);
+        }
+        match credentials {
+            Some(Credentials::Env(var)) => {
+                builder = builder.credentials(EnvVarCredentials::new(var));
+            }
+            Some(Credentials::File(path)) => {
+                builder = builder.credentials(FileCredentials::new(path));
+            }
+            Some(Credentials::Command(program, args)) => {
+                builder = builder.credentials(CommandCredentials::new(program, args));
+            }
+            None => {}
+        }
+        if let Some(local_dir) = local_dir {
+            builder = builder.backend(LocalBackend::new(local_dir)?);
+        }
+        builder.build()
+    }
+}
+
+impl Output {
+    fn write(&self, value: &impl Serialize) -> Result<()> {
+        let desc = "write output";
+        let json = serde_json::to_string_pretty(value).map_err(|e| GazenotError::new(desc, e))?;
+        match &self.output {
+            Some(path) => {
+                LocalAsset::write_new(&json, path).map_err(|e| GazenotError::new(desc, e))?;
+                Ok(())
+            }
+            None => {
+                println!("{json}");
+                Ok(())
+            }
+        }
+    }
+}
+
+fn read_json<T: DeserializeOwned>(path: &Utf8Path) -> Result<T> {
+    let desc = format!("read {path}");
+    let contents = LocalAsset::load_string(path).map_err(|e| GazenotError::new(&desc, e))?;
+    serde_json::from_str(&contents).map_err(|e| GazenotError::new(&desc, e))
+}
+
+/// Find the ArtifactSet or Release for a package in a json file
+fn find_package<'a, T>(
+    items: &'a [T],
+    package: &PackageName,
+    package_of: impl Fn(&T) -> &PackageName,
+) -> miette::Result<&'a T> {
+    items
+        .iter()
+        .find(|item| package_of(item) == package)
+        .ok_or_else(|| miette::miette!("there's nothing for {package} in the given json file"))
+}
+
+async fn download<'a, T>(
+    abyss: &Gazenot,
+    sources: &'a [T],
+    package_of: impl Fn(&T) -> &PackageName,
+    files: Vec<(PackageName, String)>,
+    dir: &Utf8Path,
+) -> miette::Result<Vec<Utf8PathBuf>>
+where
+    &'a T: Into<DownloadSource<'a>>,
+{
+    let mut downloads = Vec::<(&T, Vec<String>)>::new();
+    for (package, filename) in files {
+        let source = find_package(sources, &package, &package_of)?;
+        match downloads
+            .iter_mut()
+            .find(|(s, _)| package_of(s) == &package)
+        {
+            Some((_, filenames)) => filenames.push(filename),
+            None => downloads.push((source, vec![filename])),
+        }
+    }
+    Ok(abyss.download_files(downloads, dir).await?)
+}
+
+#[cfg(test)]
+mod tests {
+    use clap::CommandFactory;
+
+    use super::*;
+
+    #[test]
+    fn cli_is_well_formed() {
+        Cli::command().debug_assert();
+    }
+
+    #[test]
+    fn credentials() {
+        assert_eq!(
+            parse_credentials("env:MY_TOKEN"),
+            Ok(Credentials::Env("MY_TOKEN".to_owned()))
+        );
+        assert_eq!(
+            parse_credentials("file:/run/secrets/axo token"),
+            Ok(Credentials::File("/run/secrets/axo token".into()))
+        );
+        assert_eq!(
+            parse_credentials("command:pass show axo"),
+            Ok(Credentials::Command(
+                "pass".to_owned(),
+                vec!["show".to_owned(), "axo".to_owned()]
+            ))
+        );
+        for bad in ["MY_TOKEN", "env:", "command: ", "keyring:axo"] {
+            assert!(parse_credentials(bad).is_err(), "{bad}");
+        }
+    }
+
+    #[test]
+    fn credentials_and_unauthed_conflict() {
+        let args = ["gazenot", "--owner", "axodotdev", "--unauthed"];
+        let with_credentials = ["--credentials", "env:MY_TOKEN", "list-releases", "app1"];
+        assert!(Cli::try_parse_from(args.iter().chain(&with_credentials)).is_err());
+        assert!(Cli::try_parse_from(args.iter().chain(&with_credentials[2..])).is_ok());
+    }
+}
@@ -0,0 +1,628 @@
+//! A record of a release in progress, so it can be resumed if something fails
+//!
+//! The `*_in_plan` methods on [`Gazenot`][] do the same thing as their normal
+//! counterparts, but record everything that succeeds in a [`ReleasePlan`][], and
+//! skip anything the plan says was already done. If the plan is backed by a file,
+//! it's saved after every change, so even a crashed process can be picked up
+//! where it left off by just running the same steps again.
+//!
+//! A [dry run][crate::dry_run] never records anything in the plan, so a real run
+//! afterwards isn't fooled into skipping what the dry run only pretended to do.
+//! Packages the plan has no ArtifactSet for get a [mock][ArtifactSet::mock] one
+//! instead, so the later steps can still be dry-run:
+//!
+//! ```no_run
+//! use camino::Utf8PathBuf;
+//! use gazenot::{
+//!     plan::ReleasePlan, AnnouncementKey, Gazenot, Owner, PackageName, ReleaseKey, SourceHost,
+//! };
+//!
+//! # #[tokio::main]
+//! # async fn main() -> Result<(), miette::Report> {
+//! let source_host: SourceHost = "github".parse()?;
+//! let owner: Owner = "axodotdev".parse()?;
+//! let abyss = Gazenot::into_the_abyss(source_host.clone(), owner.clone())?;
+//! let mut plan = ReleasePlan::open("release-plan.json", source_host, owner)?;
+//! let package: PackageName = "app1".parse()?;
+//!
+//! abyss
+//!     .create_artifact_sets_in_plan(&mut plan, [package.clone()])
+//!     .await?;
+//! let files = vec![Utf8PathBuf::from("dist-manifest.json")];
+//! abyss
+//!     .upload_files_in_plan(&mut plan, [(package.clone(), files)])
+//!     .await?;
+//! let key = ReleaseKey {
+//!     version: "1.0.1".parse()?,
+//!     tag: "v1.0.1".parse()?,
+//!     is_prerelease: false,
+//! };
+//! abyss
+//!     .create_releases_in_plan(&mut plan, [(package, key)])
+//!     .await?;
+//! let announcement = AnnouncementKey {
+//!     body: "# v1.0.1\n\nWow Cool Changelog".to_owned(),
+//! };
+//! abyss
+//!     .create_announcements_in_plan(&mut plan, announcement)
+//!     .await?;
+//! # Ok(())
+//! # }
+//! ```
+
+use axoasset::LocalAsset;
+use camino::{Utf8Path, Utf8PathBuf};
+use schemars::JsonSchema;
+use serde::{Deserialize, Serialize};
+
+use crate::{
+    client::{sha256_file, upload_filename},
+    error::{GazenotError, GazenotErrorInner, Result},
+    AnnouncementKey, ArtifactSet, Gazenot, Operation, Owner, PackageName, Release, ReleaseKey,
+    SourceHost, UploadedFile,
+};
+
+/// Everything that has been done so far for a release
+#[derive(Serialize, Deserialize, Debug, Clone, JsonSchema)]
+pub struct ReleasePlan {
+    /// The source hosting provider of the owner (e.g. "github")
+    pub source_host: SourceHost,
+    /// The owner of the packages being released
+    pub owner: Owner,
+    /// ArtifactSets that have been created
+    #[serde(default)]
+    pub artifact_sets: Vec<ArtifactSet>,
+    /// Files that have been uploaded to those ArtifactSets
+    #[serde(default)]
+    pub uploads: Vec<UploadedFile>,
+    /// Releases that have been created from those ArtifactSets
+    #[serde(default)]
+    pub releases: Vec<Release>,
+    /// Whether those Releases have been announced
+    #[serde(default)]
+    pub announced: bool,
+    /// Where the plan is saved, if anywhere
+    #[serde(skip)]
+    #[schemars(skip)]
+    path: Option<Utf8PathBuf>,
+    /// Releases a dry run pretended to create, which are only kept so it can announce them
+    #[serde(skip)]
+    #[schemars(skip)]
+    dry_run_releases: Vec<Release>,
+}
+
+impl ReleasePlan {
+    /// Start a new plan that only lives in memory
+    ///
+    /// Use [`ReleasePlan::save_as`][] to back it with a file.
+    pub fn new(source_host: SourceHost, owner: Owner) -> Self {
+        Self {
+            source_host,
+            owner,
+            artifact_sets: vec![],
+            uploads: vec![],
+            releases: vec![],
+            announced: false,
+            path: None,
+            dry_run_releases: vec![],
+        }
+    }
+
+    /// Load the plan saved at `path`
+    pub fn load(path: impl Into<Utf8PathBuf>) -> Result<Self> {
+        let path = path.into();
+        let desc = format!("load release plan from {path}");
+        let contents = LocalAsset::load_string(&path).map_err(|e| GazenotError::new(&desc, e))?;
+        let mut plan: Self =
+            serde_json::from_str(&contents).map_err(|e| GazenotError::new(&desc, e))?;
+        plan.path = Some(path);
+        Ok(plan)
+    }
+
+    /// Load the plan saved at `path`, or start a new one there if it doesn't exist yet
+    ///
+    /// The new plan isn't written until something is recorded in it.
+    pub fn open(
+        path: impl Into<Utf8PathBuf>,
+        source_host: SourceHost,
+        owner: Owner,
+    ) -> Result<Self> {
+        let path = path.into();
+        if path.exists() {
+            return Self::load(path);
+        }
+        let mut plan = Self::new(source_host, owner);
+        plan.path = Some(path);
+        Ok(plan)
+    }
+
+    /// Where the plan is saved, if anywhere
+    pub fn path(&self) -> Option<&Utf8Path> {
+        self.path.as_deref()
+    }
+
+    /// Save the plan to `path`, and keep saving it there whenever it changes
+    pub fn save_as(&mut self, path: impl Into<Utf8PathBuf>) -> Result<()> {
+        self.path = Some(path.into());
+        self.save()
+    }
+
+    /// Save the plan to its file (does nothing for in-memory plans)
+    ///
+    /// The plan is written to a temporary file and then moved into place,
+    /// so a crash never leaves a half-written plan behind.
+    pub fn save(&self) -> Result<()> {
+        let Some(path) = &self.path else {
+            return Ok(());
+        };
+        let desc = format!("save release plan to {path}");
+        let contents =
+            serde_json::to_string_pretty(self).map_err(|e| GazenotError::new(&desc, e))?;
+        let tmp_path = Utf8PathBuf::from(format!("{path}.tmp"));
+        LocalAsset::write_new(&contents, &tmp_path).map_err(|e| GazenotError::new(&desc, e))?;
+        std::fs::rename(&tmp_path, path).map_err(|details| {
+            GazenotError::new(
+                &desc,
+                GazenotErrorInner::Io {
+                    path: path.clone(),
+                    details,
+                },
+            )
+        })
+    }
+
+    /// Get the ArtifactSet that was created for a package
+    pub fn artifact_set(&self, package: &str) -> Option<&ArtifactSet> {
+        self.artifact_sets.iter().find(|set| set.package == package)
+    }
+
+    /// Get the record of a file that was uploaded for a package
+    pub fn uploaded_file(&self, package: &str, filename: &str) -> Option<&UploadedFile> {
+        self.uploads
+            .iter()
+            .find(|file| file.package == package && file.filename == filename)
+    }
+
+    /// Get the Release that was created for a package with the given tag
+    pub fn release(&self, package: &str, tag: &str) -> Option<&Release> {
+        self.releases
+            .iter()
+            .find(|release| release.package == package && release.tag == tag)
+    }
+
+    fn record_artifact_set(&mut self, set: &ArtifactSet) -> Result<()> {
+        self.artifact_sets.push(set.clone());
+        self.save()
+    }
+
+    fn record_upload(&mut self, file: &UploadedFile) -> Result<()> {
+        // A file may have been re-uploaded after a partial failure, keep the latest record
+        self.uploads
+            .retain(|old| !(old.package == file.package && old.filename == file.filename));
+        self.uploads.push(file.clone());
+        self.save()
+    }
+
+    fn record_release(&mut self, release: &Release) -> Result<()> {
+        self.releases.push(release.clone());
+        self.save()
+    }
+
+    fn record_announced(&mut self) -> Result<()> {
+        self.announced = true;
+        self.save()
+    }
+}
+
+impl Gazenot {
+    /// [`Gazenot::create_artifact_sets`][], skipping packages that already have one in the plan
+    ///
+    /// The new ArtifactSets are recorded in the plan.
+    pub async fn create_artifact_sets_in_plan(
+        &self,
+        plan: &mut ReleasePlan,
+        packages: impl IntoIterator<Item = PackageName>,
+    ) -> Result<()> {
+        self.check_plan(plan)?;
+        let packages = packages
+            .into_iter()
+            .filter(|package| plan.artifact_set(package).is_none())
+            .collect::<Vec<_>>();
+        let queries = self.create_artifact_set_queries(packages)?;
+        let dry_run = self.is_dry_run();
+        self.join_all_recording(
+            Operation::CreateArtifactSets,
+            "create hosting",
+            queries,
+            |set| {
+                if dry_run {
+                    return Ok(());
+                }
+                plan.record_artifact_set(set)
+            },
+        )
+        .await?;
+        Ok(())
+    }
+
+    /// [`Gazenot::upload_files`][], skipping files the plan says were already uploaded
+    ///
+    /// Files are uploaded to the ArtifactSet the plan has for each package, and the
+    /// uploads (with their checksums) are recorded in the plan. Files the plan has a
+    /// record of are hashed again, and a file that changed since it was uploaded is
+    /// uploaded again (replacing the old one).
+    pub async fn upload_files_in_plan(
+        &self,
+        plan: &mut ReleasePlan,
+        files: impl IntoIterator<Item = (PackageName, Vec<Utf8PathBuf>)>,
+    ) -> Result<()> {
+        self.check_plan(plan)?;
+        let mut uploads = vec![];
+        for (package, files) in files {
+            let desc = format!(
+                "upload files to hosting for {}/{}/{}",
+                self.endpoints.source_host, self.endpoints.owner, package
+            );
+            let set = self.artifact_set_in_plan(plan, &desc, &package)?;
+            let mut to_upload = vec![];
+            for file in files {
+                let filename = upload_filename(&file).map_err(|e| GazenotError::new(&desc, e))?;
+                if let Some(uploaded) = plan.uploaded_file(&package, filename) {
+                    let sha256 = sha256_file(file.clone())
+                        .await
+                        .map_err(|e| GazenotError::new(&desc, e))?;
+                    if uploaded.sha256.eq_ignore_ascii_case(&sha256) {
+                        continue;
+                    }
+                    tracing::info!("{file} changed since it was uploaded, uploading it again");
+                }
+                to_upload.push(file);
+            }
+            uploads.push((set, to_upload));
+        }
+        let queries =
+            self.upload_file_queries(uploads.iter().map(|(set, files)| (set, files.clone())))?;
+        let dry_run = self.is_dry_run();
+        self.join_all_recording(Operation::UploadFiles, "upload files", queries, |file| {
+            if dry_run {
+                return Ok(());
+            }
+            plan.record_upload(file)
+        })
+        .await?;
+        Ok(())
+    }
+
+    /// [`Gazenot::create_releases`][], skipping releases that are already in the plan
+    ///
+    /// Releases are created from the ArtifactSet the plan has for each package,
+    /// and recorded in the plan.
+    pub async fn create_releases_in_plan(
+        &self,
+        plan: &mut ReleasePlan,
+        releases: impl IntoIterator<Item = (PackageName, ReleaseKey)>,
+    ) -> Result<()> {
+        self.check_plan(plan)?;
+        let mut to_create = vec![];
+        for (package, key) in releases {
+            if plan.release(&package, &key.tag).is_some() {
+                continue;
+            }
+            let desc = format!(
+                "create release for {}/{}/{}",
+                self.endpoints.source_host, self.endpoints.owner, package
+            );
+            let set = self.artifact_set_in_plan(plan, &desc, &package)?;
+            to_create.push((set, key));
+        }
+        let queries =
+            self.create_release_queries(to_create.iter().map(|(set, key)| (set, key.clone())))?;
+        let dry_run = self.is_dry_run();
+        self.join_all_recording(
+            Operation::CreateReleases,
+            "create releases",
+            queries,
+            |release| {
+                if dry_run {
+                    plan.dry_run_releases.push(release.clone());
+                    return Ok(());
+                }
+                plan.record_release(release)
+            },
+        )
+        .await?;
+        Ok(())
+    }
+
+    /// [`Gazenot::create_announcements`][] for all the Releases in the plan,
+    /// unless they were already announced
+    ///
+    /// A dry run also announces the Releases it pretended to create.
+    pub async fn create_announcements_in_plan(
+        &self,
+        plan: &mut ReleasePlan,
+        announcement: AnnouncementKey,
+    ) -> Result<()> {
+        self.check_plan(plan)?;
+        if plan.announced {
+            return Ok(());
+        }
+        let dry_run = self.is_dry_run();
+        let releases = plan
+            .releases
+            .iter()
+            .chain(plan.dry_run_releases.iter().filter(|_| dry_run))
+            .collect::<Vec<_>>();
+        if releases.is_empty() {
+            return Ok(());
+        }
+        self.create_announcements(releases, announcement).await?;
+        if dry_run {
+            return Ok(());
+        }
+        plan.record_announced()
+    }
+
+    /// Get the ArtifactSet the plan has for a package, which must have been created already
+    ///
+    /// Dry runs don't record the ArtifactSets they pretend to create, so they get a mock.
+    fn artifact_set_in_plan(
+        &self,
+        plan: &ReleasePlan,
+        desc: &str,
+        package: &PackageName,
+    ) -> Result<ArtifactSet> {
+        match plan.artifact_set(package) {
+            Some(set) => Ok(set.clone()),
+            None if self.is_dry_run() => Ok(ArtifactSet::mock(package.clone())),
+            None => Err(GazenotError::new(
+                desc,
+                GazenotErrorInner::NotInPlan {
+                    package: package.clone(),
+                },
+            )),
+        }
+    }
+
+    /// Check that a plan is for the same owner as this client
+    fn check_plan(&self, plan: &ReleasePlan) -> Result<()> {
+        if plan.source_host == self.endpoints.source_host && plan.owner == self.endpoints.owner {
+            return Ok(());
+        }
+        Err(GazenotError::new(
+            "use release plan",
+            GazenotErrorInner::PlanOwner {
+                plan: format!("{}/{}", plan.source_host, plan.owner),
+                client: format!("{}/{}", self.endpoints.source_host, self.endpoints.owner),
+            },
+        ))
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use reqwest::StatusCode;
+    use sha2::{Digest, Sha256};
+
+    use super::*;
+    use crate::{
+        testing::{scratch_dir, FakeAbyss},
+        FailureMode,
+    };
+
+    fn client(fake: &FakeAbyss) -> Gazenot {
+        fake.client_builder("github".parse().unwrap(), "axodotdev".parse().unwrap())
+            .record_report(true)
+            .build()
+            .unwrap()
+    }
+
+    fn open_plan(dir: &Utf8Path) -> ReleasePlan {
+        let path = dir.join("release-plan.json");
+        ReleasePlan::open(
+            path,
+            "github".parse().unwrap(),
+            "axodotdev".parse().unwrap(),
+        )
+        .unwrap()
+    }
+
+    /// The files that were uploaded since the report was last taken
+    fn uploaded(abyss: &Gazenot) -> Vec<String> {
+        abyss
+            .take_report()
+            .entries
+            .into_iter()
+            .filter(|entry| entry.operation == Operation::UploadFiles)
+            .map(|entry| entry.description)
+            .collect()
+    }
+
+    #[tokio::test]
+    async fn upload_reuploads_changed_files() {
+        let dir = scratch_dir("plan-reupload");
+        let fake = FakeAbyss::start().await.unwrap();
+        let abyss = client(&fake);
+        let package: PackageName = "app1".parse().unwrap();
+        let files = vec![dir.join("a.txt"), dir.join("b.txt")];
+        std::fs::write(&files[0], "one").unwrap();
+        std::fs::write(&files[1], "two").unwrap();
+
+        let mut plan = open_plan(&dir);
+        abyss
+            .create_artifact_sets_in_plan(&mut plan, [package.clone()])
+            .await
+            .unwrap();
+        abyss
+            .upload_files_in_plan(&mut plan, [(package.clone(), files.clone())])
+            .await
+            .unwrap();
+        assert_eq!(uploaded(&abyss).len(), 2);
+
+        // Pick the plan back up, after one of the files was rebuilt
+        std::fs::write(&files[0], "uno").unwrap();
+        let mut plan = open_plan(&dir);
+        abyss
+            .upload_files_in_plan(&mut plan, [(package.clone(), files.clone())])
+            .await
+            .unwrap();
+
+        let uploaded = uploaded(&abyss);
+        assert_eq!(uploaded.len(), 1);
+        assert!(uploaded[0].contains("a.txt"), "{uploaded:?}");
+        assert_eq!(fake.state().artifact_sets[0].files["a.txt"], b"uno");
+        let record = plan.uploaded_file(&package, "a.txt").unwrap();
+        assert_eq!(record.sha256, format!("{:x}", Sha256::digest("uno")));
+        assert_eq!(plan.uploads.len(), 2);
+    }
+
+    #[tokio::test]
+    async fn upload_resumes_after_partial_failure() {
+        let dir = scratch_dir("plan-resume");
+        let fake = FakeAbyss::start().await.unwrap();
+        let abyss = fake
+            .client_builder("github".parse().unwrap(), "axodotdev".parse().unwrap())
+            .failure_mode(FailureMode::CollectAll)
+            .max_concurrency(1)
+            .record_report(true)
+            .build()
+            .unwrap();
+        let package: PackageName = "app1".parse().unwrap();
+        let files = vec![dir.join("a.txt"), dir.join("b.txt")];
+        std::fs::write(&files[0], "one").unwrap();
+        std::fs::write(&files[1], "two").unwrap();
+
+        let mut plan = open_plan(&dir);
+        abyss
+            .create_artifact_sets_in_plan(&mut plan, [package.clone()])
+            .await
+            .unwrap();
+        fake.fail_next_requests(1, StatusCode::BAD_REQUEST);
+        abyss
+            .upload_files_in_plan(&mut plan, [(package.clone(), files.clone())])
+            .await
+            .unwrap_err();
+        assert_eq!(uploaded(&abyss).len(), 2);
+
+        // What made it was saved, so only the rest is uploaded again
+        let mut plan = open_plan(&dir);
+        assert_eq!(plan.uploads.len(), 1);
+        abyss
+            .upload_files_in_plan(&mut plan, [(package.clone(), files.clone())])
+            .await
+            .unwrap();
+        assert_eq!(uploaded(&abyss).len(), 1);
+        assert_eq!(plan.uploads.len(), 2);
+        assert_eq!(fake.state().artifact_sets[0].files.len(), 2);
+
+        // And running it all again does nothing
+        abyss
+            .create_artifact_sets_in_plan(&mut plan, [package.clone()])
+            .await
+            .unwrap();
+        abyss
+            .upload_files_in_plan(&mut plan, [(package, files)])
+            .await
+            .unwrap();
+        assert!(abyss.take_report().entries.is_empty());
+        assert_eq!(fake.state().artifact_sets.len(), 1);
+    }
+
+    #[tokio::test]
+    async fn upload_needs_a_filename() {
+        let dir = scratch_dir("plan-filename");
+        let fake = FakeAbyss::start().await.unwrap();
+        let abyss = client(&fake);
+        let package: PackageName = "app1".parse().unwrap();
+
+        let mut plan = open_plan(&dir);
+        abyss
+            .create_artifact_sets_in_plan(&mut plan, [package.clone()])
+            .await
+            .unwrap();
+        let err = abyss
+            .upload_files_in_plan(&mut plan, [(package, vec![Utf8PathBuf::from("/")])])
+            .await
+            .unwrap_err();
+        assert!(
+            matches!(*err.cause, GazenotErrorInner::InvalidFilename { .. }),
+            "{err:?}"
+        );
+        assert!(plan.uploads.is_empty());
+    }
+
+    /// Run every step of a release (with one file) in the plan
+    async fn release(abyss: &Gazenot, plan: &mut ReleasePlan, file: &Utf8Path) {
+        let package: PackageName = "app1".parse().unwrap();
+        abyss
+            .create_artifact_sets_in_plan(plan, [package.clone()])
+            .await
+            .unwrap();
+        abyss
+            .upload_files_in_plan(plan, [(package.clone(), vec![file.to_owned()])])
+            .await
+            .unwrap();
+        let key = ReleaseKey::from_tag(&package, "v1.0.0".parse().unwrap()).unwrap();
+        abyss
+            .create_releases_in_plan(plan, [(package, key)])
+            .await
+            .unwrap();
+        let announcement = AnnouncementKey {
+            body: "# v1.0.0".to_owned(),
+        };
+        abyss
+            .create_announcements_in_plan(plan, announcement)
+            .await
+            .unwrap();
+    }
+
+    #[tokio::test]
+    async fn dry_run_leaves_plan_alone() {
+        let dir = scratch_dir("plan-dry-run");
+        let fake = FakeAbyss::start().await.unwrap();
+        let file = dir.join("app.tar.gz");
+        std::fs::write(&file, "app").unwrap();
+
+        let dry_run = fake
+            .client_builder("github".parse().unwrap(), "axodotdev".parse().unwrap())
+            .dry_run(true)
+            .build()
+            .unwrap();
+        let mut plan = open_plan(&dir);
+        release(&dry_run, &mut plan, &file).await;
+        assert!(!dir.join("release-plan.json").exists());
+        assert!(plan.artifact_sets.is_empty() && plan.uploads.is_empty());
+        let planned = dry_run
+            .take_planned_requests()
+            .into_iter()
+            .map(|request| request.operation)
+            .collect::<Vec<_>>();
+        assert_eq!(
+            planned,
+            [
+                Operation::CreateArtifactSets,
+                Operation::UploadFiles,
+                Operation::CreateReleases,
+                Operation::CreateAnnouncements,
+            ]
+        );
+
+        let abyss = client(&fake);
+        let mut plan = open_plan(&dir);
+        release(&abyss, &mut plan, &file).await;
+        let state = fake.state();
+        assert_eq!(state.artifact_sets.len(), 1);
+        assert_eq!(state.artifact_sets[0].files["app.tar.gz"], b"app");
+        assert_eq!(state.releases.len(), 1);
+        assert_eq!(state.announcements.len(), 1);
+
+        let plan = ReleasePlan::load(dir.join("release-plan.json")).unwrap();
+        assert_eq!(
+            plan.artifact_sets[0].public_id,
+            state.artifact_sets[0].public_id
+        );
+        assert_eq!(plan.uploads.len(), 1);
+        assert_eq!(plan.releases.len(), 1);
+        assert!(plan.announced);
+    }
+}
@@ -0,0 +1,216 @@
+//! Machine-readable records of what a [`Gazenot`][] client did
+//!
+//! Clients built with [`GazenotBuilder::record_report`][] keep a [`ReportEntry`][] for every
+//! part of every batch operation: what it was, which URL it hit, whether it worked, how long
+//! it took, and what came back. This is handy for feeding the results of one CI step into
+//! later ones:
+//!
+//! ```no_run
+//! use gazenot::Gazenot;
+//!
+//! # #[tokio::main]
+//! # async fn main() -> Result<(), miette::Report> {
+//! let abyss = Gazenot::builder("github".parse()?, "axodotdev".parse()?)
+//!     .record_report(true)
+//!     .build()?;
+//! let result = abyss.create_artifact_sets(["app1".parse()?]).await;
+//!
+//! // Save the report even if something failed, so the failure can be inspected
+//! abyss.take_report().save("gazenot-report.json")?;
+//! result?;
+//! # Ok(())
+//! # }
+//! ```
+//!
+//! [`GazenotBuilder::record_report`]: crate::GazenotBuilder::record_report
+
+use std::time::Duration;
+
+use axoasset::LocalAsset;
+use camino::{Utf8Path, Utf8PathBuf};
+use schemars::JsonSchema;
+use serde::{Deserialize, Serialize};
+use url::Url;
+
+use crate::{
+    error::{GazenotError, Result},
+    ArtifactSet, Gazenot, Operation, Release, ReleaseList, UploadedFile,
+};
+
+/// Everything a client did since the report was last taken, see [`Gazenot::take_report`][]
+#[derive(Serialize, Deserialize, Debug, Clone, Default, JsonSchema)]
+pub struct Report {
+    /// One entry for every part of every batch operation, in the order the operations ran
+    pub entries: Vec<ReportEntry>,
+}
+
+/// One part of a batch operation, such as uploading a single file
+#[derive(Serialize, Deserialize, Debug, Clone, JsonSchema)]
+pub struct ReportEntry {
+    /// The batch operation this was part of
+    pub operation: Operation,
+    /// What this part of the operation was doing (e.g. "upload app.tar.gz to hosting for ...")
+    pub description: String,
+    /// The URL the request was sent to
+    pub url: String,
+    /// How it went
+    pub status: ReportStatus,
+    /// How long it took, including retries, in seconds
+    ///
+    /// This is missing if it never got to run.
+    pub duration_secs: Option<f64>,
+    /// The error, if it failed
+    pub error: Option<String>,
+    /// What came back, if it succeeded (and returned anything interesting)
+    pub result: Option<ReportResult>,
+}
+
+/// How a [`ReportEntry`][] went
+#[derive(Serialize, Deserialize, Debug, Clone, Copy, PartialEq, Eq, JsonSchema)]
+#[serde(rename_all = "snake_case")]
+pub enum ReportStatus {
+    /// It worked
+    Succeeded,
+    /// It failed
+    Failed,
+    /// It was cancelled because another part of the batch failed
+    /// (see [`FailureMode::FailFast`][crate::FailureMode::FailFast])
+    Cancelled,
+}
+
+/// What a successful [`ReportEntry`][] produced
+#[derive(Serialize, Deserialize, Debug, Clone, JsonSchema)]
+#[serde(tag = "kind", rename_all = "snake_case")]
+pub enum ReportResult {
+    /// An ArtifactSet was created
+    ArtifactSet(ArtifactSet),
+    /// A file was uploaded
+    UploadedFile(UploadedFile),
+    /// A Release was created
+    Release(Release),
+    /// Releases were listed
+    ReleaseList(ReleaseList),
+    /// A file was downloaded
+    DownloadedFile {
+        /// Where the file was saved
+        path: String,
+    },
+}
+
+impl Report {
+    /// Write the report to `path` as json
+    pub fn save(&self, path: impl AsRef<Utf8Path>) -> Result<()> {
+        let path = path.as_ref();
+        let desc = format!("save report to {path}");
+        let contents =
+            serde_json::to_string_pretty(self).map_err(|e| GazenotError::new(&desc, e))?;
+        LocalAsset::write_new(&contents, path).map_err(|e| GazenotError::new(&desc, e))?;
+        Ok(())
+    }
+}
+
+impl ReportEntry {
+    /// An entry for a part of a batch that hasn't finished (yet)
+    pub(crate) fn cancelled(operation: Operation, description: &str, url: &Url) -> Self {
+        Self {
+            operation,
+            description: description.to_owned(),
+            url: url.to_string(),
+            status: ReportStatus::Cancelled,
+            duration_secs: None,
+            error: None,
+            result: None,
+        }
+    }
+
+    /// Fill in how a part of a batch went
+    pub(crate) fn finish<T: Reportable>(&mut self, result: &Result<T>, duration: Option<Duration>) {
+        self.duration_secs = duration.map(|d| d.as_secs_f64());
+        match result {
+            Ok(value) => {
+                self.status = ReportStatus::Succeeded;
+                self.result = value.report_result();
+            }
+            Err(e) => {
+                self.status = ReportStatus::Failed;
+                self.error = Some(error_chain(e));
+            }
+        }
+    }
+}
+
+/// A result of a batch operation that can be put in a [`ReportEntry`][]
+pub(crate) trait Reportable {
+    fn report_result(&self) -> Option<ReportResult>;
+}
+
+impl Reportable for ArtifactSet {
+    fn report_result(&self) -> Option<ReportResult> {
+        Some(ReportResult::ArtifactSet(self.clone()))
+    }
+}
+
+impl Reportable for UploadedFile {
+    fn report_result(&self) -> Option<ReportResult> {
+        Some(ReportResult::UploadedFile(self.clone()))
+    }
+}
+
+impl Reportable for Release {
+    fn report_result(&self) -> Option<ReportResult> {
+        Some(ReportResult::Release(self.clone()))
+    }
+}
+
+impl Reportable for ReleaseList {
+    fn report_result(&self) -> Option<ReportResult> {
+        Some(ReportResult::ReleaseList(self.clone()))
+    }
+}
+
+impl Reportable for Utf8PathBuf {
+    fn report_result(&self) -> Option<ReportResult> {
+        Some(ReportResult::DownloadedFile {
+            path: self.to_string(),
+        })
+    }
+}
+
+impl Reportable for () {
+    fn report_result(&self) -> Option<ReportResult> {
+        None
+    }
+}
+
+/// An error and all its causes, on one line
+fn error_chain(err: &dyn std::error::Error) -> String {
+    let mut message = err.to_string();
+    let mut source = err.source();
+    while let Some(cause) = source {
+        message.push_str(": ");
+        message.push_str(&cause.to_string());
+        source = cause.source();
+    }
+    message
+}
+
+impl Gazenot {
+    /// Take everything recorded in the report so far, leaving it empty
+    ///
+    /// This is always empty unless the client was built with
+    /// [`GazenotBuilder::record_report`][crate::GazenotBuilder::record_report].
+    pub fn take_report(&self) -> Report {
+        match &self.report {
+            Some(report) => std::mem::take(&mut *report.lock().expect("report lock poisoned")),
+            None => Report::default(),
+        }
+    }
+
+    /// Add entries to the report, if we're recording one
+    pub(crate) fn extend_report(&self, entries: Vec<ReportEntry>) {
+        if let Some(report) = &self.report {
+            let mut report = report.lock().expect("report lock poisoned");
+            report.entries.extend(entries);
+        }
+    }
+}
@@ -0,0 +1,85 @@
+use std::{
+    hash::{BuildHasher, Hasher},
+    time::Duration,
+};
+
+/// How a [`Gazenot`][crate::Gazenot] client retries failed requests
+///
+/// Requests are retried if they fail in a way that's plausibly transient:
+/// connection failures, timeouts, and server errors like 502 or 503.
+/// If the server responds with a `Retry-After` header (typical of 429 and 503),
+/// it's honored in place of the usual backoff. If it asks us to wait longer
+/// than `max_backoff`, we give up instead of retrying early.
+///
+/// Only operations that are safe to repeat (uploading files, listing releases)
+/// are retried after the request may have reached the server. Operations that
+/// would create duplicates (creating ArtifactSets, announcing) or conflict with
+/// themselves (creating releases) are only retried if we failed to connect at all,
+/// or the server turned the request away with a 429 (or a 503 with a `Retry-After`).
+#[derive(Debug, Clone)]
+pub struct RetryPolicy {
+    /// Maximum number of attempts for a request (including the first)
+    ///
+    /// 1 (or 0) disables retries.
+    pub max_attempts: u32,
+    /// How long to wait before the first retry
+    ///
+    /// Each subsequent retry waits twice as long as the previous one.
+    pub initial_backoff: Duration,
+    /// The longest we'll ever wait between two attempts
+    pub max_backoff: Duration,
+    /// Whether to randomize backoffs, so that parallel requests don't all retry in lockstep
+    pub jitter: bool,
+}
+
+impl Default for RetryPolicy {
+    fn default() -> Self {
+        Self {
+            max_attempts: 3,
+            initial_backoff: Duration::from_millis(500),
+            max_backoff: Duration::from_secs(30),
+            jitter: true,
+        }
+    }
+}
+
+impl RetryPolicy {
+    /// A policy that never retries
+    pub fn none() -> Self {
+        Self {
+            max_attempts: 1,
+            ..Self::default()
+        }
+    }
+
+    /// How long to wait after the given (1-based) attempt failed
+    ///
+    /// Returns `None` if the server asked us to wait longer than `max_backoff`.
+    pub(crate) fn backoff(&self, attempt: u32, retry_after: Option<Duration>) -> Option<Duration> {
+        if let Some(retry_after) = retry_after {
+            return (retry_after <= self.max_backoff).then_some(retry_after);
+        }
+        let factor = 2u32.saturating_pow(attempt.saturating_sub(1));
+        let backoff = self
+            .initial_backoff
+            .saturating_mul(factor)
+            .min(self.max_backoff);
+        if self.jitter {
+            // "Equal jitter": wait somewhere between half and all of the backoff
+            Some(backoff / 2 + backoff.mul_f64(random_fraction() / 2.0))
+        } else {
+            Some(backoff)
+        }
+    }
+}
+
+/// A random number in [0, 1)
+///
+/// This doesn't need to be good randomness, so we just use the random keys
+/// that std seeds HashMaps with, instead of pulling in an rng.
+fn random_fraction() -> f64 {
+    let bits = std::collections::hash_map::RandomState::new()
+        .build_hasher()
+        .finish();
+    (bits >> 11) as f64 / (1u64 << 53) as f64
+}
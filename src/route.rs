@@ -0,0 +1,551 @@
+//! Every endpoint of The Abyss, and how to build URLs for them
+//!
+//! URLs are built a path segment at a time, so anything interpolated into them
+//! (filenames, tags, ...) is percent-encoded and can't change the shape of the URL.
+//!
+//! ```
+//! use gazenot::route::{Endpoints, Route};
+//!
+//! # fn main() -> Result<(), miette::Report> {
+//! let endpoints = Endpoints {
+//!     scheme: "https".to_owned(),
+//!     api_server: "axo-abyss.fly.dev".to_owned(),
+//!     hosting_server: "artifacts.axodotdev.host".to_owned(),
+//!     hosting_owner_subdomain: true,
+//!     source_host: "github".parse()?,
+//!     owner: "axodotdev".parse()?,
+//! };
+//! let route = Route::UploadFile {
+//!     package: &"my-app".parse()?,
+//!     public_id: &"abc123".parse()?,
+//!     filename: "my app #1.tar.gz",
+//! };
+//! assert_eq!(route.method(), reqwest::Method::POST);
+//! assert_eq!(
+//!     route.url(&endpoints)?.as_str(),
+//!     "https://axo-abyss.fly.dev/github/axodotdev/my-app/artifacts/abc123/upload/my%20app%20%231.tar.gz",
+//! );
+//! # Ok(())
+//! # }
+//! ```
+
+use std::str::FromStr;
+
+use reqwest::{Method, Url};
+
+use crate::{
+    error::{GazenotErrorInner, ResultInner},
+    ArtifactSetFilter, ArtifactSetId, Owner, PackageName, ReleaseTag, SourceHost,
+};
+
+/// Where The Abyss is, and whose packages we're talking to it about
+#[derive(Debug, Clone)]
+pub struct Endpoints {
+    /// URL scheme for all servers
+    pub scheme: String,
+    /// Domain for the main abyss API
+    pub api_server: String,
+    /// Domain where ArtifactSet downloads are GETtable from
+    pub hosting_server: String,
+    /// Whether the owner is a subdomain of hosting_server
+    pub hosting_owner_subdomain: bool,
+    /// The source hosting provider of the owner (e.g. "github")
+    pub source_host: SourceHost,
+    /// The owner of the packages
+    pub owner: Owner,
+}
+
+/// An endpoint of The Abyss
+#[derive(Debug, Clone, Copy)]
+pub enum Route<'a> {
+    /// `POST /:sourcehost/:owner/:package/artifacts`
+    CreateArtifactSet { package: &'a PackageName },
+    /// `GET /:sourcehost/:owner/:package/artifacts?state=open&older_than_secs=:secs`
+    ListArtifactSets {
+        package: &'a PackageName,
+        filter: &'a ArtifactSetFilter,
+    },
+    /// `GET /:sourcehost/:owner/:package/artifacts/:id`
+    GetArtifactSet {
+        package: &'a PackageName,
+        public_id: &'a ArtifactSetId,
+    },
+    /// `DELETE /:sourcehost/:owner/:package/artifacts/:id`
+    DeleteArtifactSet {
+        package: &'a PackageName,
+        public_id: &'a ArtifactSetId,
+    },
+    /// `POST /:sourcehost/:owner/:package/artifacts/:id/upload/:filename`
+    UploadFile {
+        package: &'a PackageName,
+        public_id: &'a ArtifactSetId,
+        filename: &'a str,
+    },
+    /// `POST /:sourcehost/:owner/:package/releases`
+    CreateRelease { package: &'a PackageName },
+    /// `GET /:sourcehost/:owner/:package/releases`
+    ListReleases { package: &'a PackageName },
+    /// `POST /:sourcehost/:owner/announcements`
+    CreateAnnouncement,
+    /// `GET :owner.:hosting_server/:package/:id/:filename` on the hosting server
+    ///
+    /// Or `GET :hosting_server/:owner/:package/:id/:filename` if the owner isn't a subdomain.
+    DownloadArtifactSetFile {
+        package: &'a PackageName,
+        public_id: &'a ArtifactSetId,
+        filename: &'a str,
+    },
+    /// `GET :owner.:hosting_server/:package/:tag/:filename` on the hosting server
+    ///
+    /// Or `GET :hosting_server/:owner/:package/:tag/:filename` if the owner isn't a subdomain.
+    DownloadReleaseFile {
+        package: &'a PackageName,
+        tag: &'a ReleaseTag,
+        filename: &'a str,
+    },
+}
+
+impl Route<'_> {
+    /// The HTTP method of the endpoint
+    pub fn method(&self) -> Method {
+        match self {
+            Route::CreateArtifactSet { .. }
+            | Route::UploadFile { .. }
+            | Route::CreateRelease { .. }
+            | Route::CreateAnnouncement => Method::POST,
+            Route::ListArtifactSets { .. }
+            | Route::GetArtifactSet { .. }
+            | Route::ListReleases { .. }
+            | Route::DownloadArtifactSetFile { .. }
+            | Route::DownloadReleaseFile { .. } => Method::GET,
+            Route::DeleteArtifactSet { .. } => Method::DELETE,
+        }
+    }
+
+    /// The URL of the endpoint
+    pub fn url(&self, endpoints: &Endpoints) -> ResultInner<Url> {
+        let source_host = endpoints.source_host.as_str();
+        let owner = endpoints.owner.as_str();
+        match *self {
+            Route::CreateArtifactSet { package } => {
+                api_url(endpoints, [source_host, owner, package, "artifacts"])
+            }
+            Route::ListArtifactSets { package, filter } => {
+                let mut url = api_url(endpoints, [source_host, owner, package, "artifacts"])?;
+                let ArtifactSetFilter {
+                    unreleased,
+                    older_than,
+                } = filter;
+                if *unreleased {
+                    url.query_pairs_mut().append_pair("state", "open");
+                }
+                if let Some(older_than) = older_than {
+                    url.query_pairs_mut()
+                        .append_pair("older_than_secs", &older_than.as_secs().to_string());
+                }
+                Ok(url)
+            }
+            Route::GetArtifactSet { package, public_id }
+            | Route::DeleteArtifactSet { package, public_id } => api_url(
+                endpoints,
+                [source_host, owner, package, "artifacts", public_id],
+            ),
+            Route::UploadFile {
+                package,
+                public_id,
+                filename,
+            } => api_url(
+                endpoints,
+                [
+                    source_host,
+                    owner,
+                    package,
+                    "artifacts",
+                    public_id,
+                    "upload",
+                    filename,
+                ],
+            ),
+            Route::CreateRelease { package } | Route::ListReleases { package } => {
+                api_url(endpoints, [source_host, owner, package, "releases"])
+            }
+            Route::CreateAnnouncement => api_url(endpoints, [source_host, owner, "announcements"]),
+            Route::DownloadArtifactSetFile {
+                package,
+                public_id,
+                filename,
+            } => hosting_url(endpoints, package, public_id, filename),
+            Route::DownloadReleaseFile {
+                package,
+                tag,
+                filename,
+            } => hosting_url(endpoints, package, tag, filename),
+        }
+    }
+}
+
+/// Parse a base URL (such as one the server gave us), and append path segments to it
+///
+/// The segments are percent-encoded, and any trailing slash on the base is ignored.
+/// Empty, `.`, and `..` segments are rejected, because they would change which path
+/// the URL points to instead of being part of it.
+pub(crate) fn join_segments<'a>(
+    base: &str,
+    segments: impl IntoIterator<Item = &'a str>,
+) -> ResultInner<Url> {
+    let mut url = Url::from_str(base)?;
+    let mut path = url
+        .path_segments_mut()
+        .map_err(|()| GazenotErrorInner::InvalidBaseUrl {
+            url: base.to_owned(),
+        })?;
+    path.pop_if_empty();
+    for segment in segments {
+        // Filenames, and ids the server gave us, aren't validated as strictly as our own values
+        if matches!(segment, "" | "." | "..") {
+            return Err(GazenotErrorInner::InvalidPathSegment {
+                segment: segment.to_owned(),
+            });
+        }
+        path.push(segment);
+    }
+    drop(path);
+    Ok(url)
+}
+
+/// A URL on the API server
+fn api_url<'a>(
+    endpoints: &Endpoints,
+    segments: impl IntoIterator<Item = &'a str>,
+) -> ResultInner<Url> {
+    let Endpoints {
+        scheme, api_server, ..
+    } = endpoints;
+    join_segments(&format!("{scheme}://{api_server}/"), segments)
+}
+
+/// A file in an ArtifactSet or Release on the hosting server
+fn hosting_url(
+    endpoints: &Endpoints,
+    package: &str,
+    id_or_tag: &str,
+    filename: &str,
+) -> ResultInner<Url> {
+    let Endpoints {
+        scheme,
+        hosting_server,
+        hosting_owner_subdomain,
+        owner,
+        ..
+    } = endpoints;
+    if *hosting_owner_subdomain {
+        join_segments(
+            &format!("{scheme}://{owner}.{hosting_server}/"),
+            [package, id_or_tag, filename],
+        )
+    } else {
+        join_segments(
+            &format!("{scheme}://{hosting_server}/"),
+            [owner.as_str(), package, id_or_tag, filename],
+        )
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use std::time::Duration;
+
+    use super::*;
+
+    fn endpoints(hosting_owner_subdomain: bool) -> Endpoints {
+        Endpoints {
+            scheme: "https".to_owned(),
+            api_server: "api.example.com".to_owned(),
+            hosting_server: "hosting.example.com".to_owned(),
+            hosting_owner_subdomain,
+            source_host: "github".parse().unwrap(),
+            owner: "axodotdev".parse().unwrap(),
+        }
+    }
+
+    fn package() -> PackageName {
+        "my-app".parse().unwrap()
+    }
+
+    fn public_id() -> ArtifactSetId {
+        "set-123".parse().unwrap()
+    }
+
+    fn check(route: Route, method: Method, url: &str) {
+        assert_eq!(route.method(), method, "{route:?}");
+        assert_eq!(route.url(&endpoints(true)).unwrap().as_str(), url);
+    }
+
+    #[test]
+    fn create_artifact_set() {
+        check(
+            Route::CreateArtifactSet {
+                package: &package(),
+            },
+            Method::POST,
+            "https://api.example.com/github/axodotdev/my-app/artifacts",
+        );
+    }
+
+    #[test]
+    fn list_artifact_sets() {
+        let package = package();
+        let base = "https://api.example.com/github/axodotdev/my-app/artifacts";
+        for (filter, query) in [
+            (ArtifactSetFilter::default(), ""),
+            (ArtifactSetFilter::default().unreleased(), "?state=open"),
+            (
+                ArtifactSetFilter::default().older_than(Duration::from_secs(90)),
+                "?older_than_secs=90",
+            ),
+            (
+                ArtifactSetFilter::default()
+                    .unreleased()
+                    .older_than(Duration::from_millis(86_400_500)),
+                "?state=open&older_than_secs=86400",
+            ),
+        ] {
+            check(
+                Route::ListArtifactSets {
+                    package: &package,
+                    filter: &filter,
+                },
+                Method::GET,
+                &format!("{base}{query}"),
+            );
+        }
+    }
+
+    #[test]
+    fn get_artifact_set() {
+        check(
+            Route::GetArtifactSet {
+                package: &package(),
+                public_id: &public_id(),
+            },
+            Method::GET,
+            "https://api.example.com/github/axodotdev/my-app/artifacts/set-123",
+        );
+    }
+
+    #[test]
+    fn delete_artifact_set() {
+        check(
+            Route::DeleteArtifactSet {
+                package: &package(),
+                public_id: &public_id(),
+            },
+            Method::DELETE,
+            "https://api.example.com/github/axodotdev/my-app/artifacts/set-123",
+        );
+    }
+
+    #[test]
+    fn upload_file() {
+        check(
+            Route::UploadFile {
+                package: &package(),
+                public_id: &public_id(),
+                filename: "my-app.tar.gz",
+            },
+            Method::POST,
+            "https://api.example.com/github/axodotdev/my-app/artifacts/set-123/upload/my-app.tar.gz",
+        );
+    }
+
+    #[test]
+    fn upload_file_encodes_filename() {
+        let package = package();
+        let public_id = public_id();
+        let route = |filename| Route::UploadFile {
+            package: &package,
+            public_id: &public_id,
+            filename,
+        };
+        let base = "https://api.example.com/github/axodotdev/my-app/artifacts/set-123/upload";
+        for (filename, encoded) in [
+            ("my app.zip", "my%20app.zip"),
+            ("notes#1.txt", "notes%231.txt"),
+            ("what?.txt", "what%3F.txt"),
+            ("100%.txt", "100%25.txt"),
+            ("a/b.txt", "a%2Fb.txt"),
+            ("ünïcode.txt", "%C3%BCn%C3%AFcode.txt"),
+        ] {
+            let url = route(filename).url(&endpoints(true)).unwrap();
+            assert_eq!(url.as_str(), format!("{base}/{encoded}"), "{filename}");
+            // Nothing leaked out of the path
+            assert_eq!(url.query(), None, "{filename}");
+            assert_eq!(url.fragment(), None, "{filename}");
+            assert_eq!(url.path_segments().unwrap().count(), 7, "{filename}");
+        }
+    }
+
+    #[test]
+    fn upload_file_rejects_dot_segments() {
+        let package = package();
+        let public_id = public_id();
+        for filename in ["", ".", ".."] {
+            let route = Route::UploadFile {
+                package: &package,
+                public_id: &public_id,
+                filename,
+            };
+            assert!(
+                matches!(
+                    route.url(&endpoints(true)),
+                    Err(GazenotErrorInner::InvalidPathSegment { .. })
+                ),
+                "{filename:?}"
+            );
+        }
+        // Dots are fine as part of a filename
+        let route = Route::UploadFile {
+            package: &package,
+            public_id: &public_id,
+            filename: "...",
+        };
+        assert!(route
+            .url(&endpoints(true))
+            .unwrap()
+            .path()
+            .ends_with("/..."));
+    }
+
+    #[test]
+    fn lenient_ids_are_checked_too() {
+        use crate::types::Unvalidated;
+
+        let package = package();
+        let public_id = ArtifactSetId::unvalidated("..".to_owned());
+        let route = Route::GetArtifactSet {
+            package: &package,
+            public_id: &public_id,
+        };
+        match route.url(&endpoints(true)) {
+            Err(GazenotErrorInner::InvalidPathSegment { segment }) => assert_eq!(segment, ".."),
+            other => panic!("unexpected result: {other:?}"),
+        }
+    }
+
+    #[test]
+    fn create_release() {
+        check(
+            Route::CreateRelease {
+                package: &package(),
+            },
+            Method::POST,
+            "https://api.example.com/github/axodotdev/my-app/releases",
+        );
+    }
+
+    #[test]
+    fn list_releases() {
+        check(
+            Route::ListReleases {
+                package: &package(),
+            },
+            Method::GET,
+            "https://api.example.com/github/axodotdev/my-app/releases",
+        );
+    }
+
+    #[test]
+    fn create_announcement() {
+        check(
+            Route::CreateAnnouncement,
+            Method::POST,
+            "https://api.example.com/github/axodotdev/announcements",
+        );
+    }
+
+    #[test]
+    fn download_artifact_set_file() {
+        let package = package();
+        let public_id = public_id();
+        let route = Route::DownloadArtifactSetFile {
+            package: &package,
+            public_id: &public_id,
+            filename: "my app.zip",
+        };
+        assert_eq!(route.method(), Method::GET);
+        assert_eq!(
+            route.url(&endpoints(true)).unwrap().as_str(),
+            "https://axodotdev.hosting.example.com/my-app/set-123/my%20app.zip"
+        );
+        assert_eq!(
+            route.url(&endpoints(false)).unwrap().as_str(),
+            "https://hosting.example.com/axodotdev/my-app/set-123/my%20app.zip"
+        );
+    }
+
+    #[test]
+    fn download_release_file() {
+        let package = package();
+        let tag = "my-app/v1.0.0".parse().unwrap();
+        let route = Route::DownloadReleaseFile {
+            package: &package,
+            tag: &tag,
+            filename: "my-app.zip",
+        };
+        assert_eq!(route.method(), Method::GET);
+        assert_eq!(
+            route.url(&endpoints(true)).unwrap().as_str(),
+            "https://axodotdev.hosting.example.com/my-app/my-app%2Fv1.0.0/my-app.zip"
+        );
+        assert_eq!(
+            route.url(&endpoints(false)).unwrap().as_str(),
+            "https://hosting.example.com/axodotdev/my-app/my-app%2Fv1.0.0/my-app.zip"
+        );
+    }
+
+    #[test]
+    fn api_server_with_port_and_scheme() {
+        let endpoints = Endpoints {
+            scheme: "http".to_owned(),
+            api_server: "127.0.0.1:8080".to_owned(),
+            ..endpoints(true)
+        };
+        let url = Route::CreateAnnouncement.url(&endpoints).unwrap();
+        assert_eq!(
+            url.as_str(),
+            "http://127.0.0.1:8080/github/axodotdev/announcements"
+        );
+    }
+
+    #[test]
+    fn join_segments_onto_server_urls() {
+        for base in [
+            "https://hosting.example.com/axodotdev/my-app/set-123",
+            "https://hosting.example.com/axodotdev/my-app/set-123/",
+        ] {
+            assert_eq!(
+                join_segments(base, ["my app#1.zip"]).unwrap().as_str(),
+                "https://hosting.example.com/axodotdev/my-app/set-123/my%20app%231.zip",
+                "{base}"
+            );
+        }
+        assert_eq!(
+            join_segments("https://hosting.example.com", ["a", "b"])
+                .unwrap()
+                .as_str(),
+            "https://hosting.example.com/a/b"
+        );
+    }
+
+    #[test]
+    fn join_segments_rejects_bad_bases() {
+        assert!(matches!(
+            join_segments("not a url", ["a"]),
+            Err(GazenotErrorInner::Url(_))
+        ));
+        assert!(matches!(
+            join_segments("mailto:someone@example.com", ["a"]),
+            Err(GazenotErrorInner::InvalidBaseUrl { .. })
+        ));
+    }
+}
@@ -0,0 +1,906 @@
+//! An in-process fake of The Abyss, for testing code that uses [`Gazenot`][crate::Gazenot]
+//!
+//! [`FakeAbyss`][] serves the same endpoints as the real thing on localhost,
+//! keeps everything in memory, and lets you inspect what it was sent.
+//! This is enough to run the whole release flow offline:
+//!
+//! ```
+//! use camino::Utf8PathBuf;
+//! use gazenot::{testing::FakeAbyss, AnnouncementKey, ReleaseKey};
+//!
+//! # #[tokio::main]
+//! # async fn main() -> Result<(), miette::Report> {
+//! let fake = FakeAbyss::start().await.expect("couldn't start fake abyss");
+//! let abyss = fake
+//!     .client_builder("github".parse()?, "axodotdev".parse()?)
+//!     .build()?;
+//!
+//! let sets = abyss.create_artifact_sets(vec!["app1".parse()?]).await?;
+//! let files = vec![Utf8PathBuf::from("Cargo.toml")];
+//! let uploads = abyss.upload_files(sets.iter().map(|set| (set, files.clone()))).await?;
+//! // Check that everything arrived before releasing it
+//! let info = abyss
+//!     .get_artifact_set(sets[0].package.clone(), sets[0].public_id.clone())
+//!     .await?;
+//! assert_eq!(info.file("Cargo.toml").unwrap().sha256, uploads[0].sha256);
+//! let key = ReleaseKey {
+//!     tag: "v1.0.0".parse()?,
+//!     version: "1.0.0".parse()?,
+//!     is_prerelease: false,
+//! };
+//! let releases = abyss
+//!     .create_releases(sets.iter().map(|set| (set, key.clone())))
+//!     .await?;
+//! let announcement = AnnouncementKey {
+//!     body: "# v1.0.0\n\nIt's out!".to_owned(),
+//! };
+//! abyss.create_announcements(&releases, announcement).await?;
+//!
+//! let state = fake.state();
+//! assert_eq!(state.artifact_sets[0].files["Cargo.toml"], std::fs::read("Cargo.toml").unwrap());
+//! assert_eq!(state.releases[0].tag, "v1.0.0");
+//! assert_eq!(state.announcements.len(), 1);
+//! # Ok(())
+//! # }
+//! ```
+
+use std::{
+    collections::{BTreeMap, VecDeque},
+    convert::Infallible,
+    net::{SocketAddr, TcpListener},
+    sync::{
+        atomic::{AtomicUsize, Ordering},
+        Arc, Mutex,
+    },
+    time::{Duration, SystemTime},
+};
+
+use hyper::{
+    header::{AUTHORIZATION, CONTENT_RANGE, CONTENT_TYPE, ETAG, IF_RANGE, RANGE},
+    service::{make_service_fn, service_fn},
+    Body, Method, Request, Response, Server, StatusCode,
+};
+use serde::Deserialize;
+use serde_json::json;
+use sha2::{Digest, Sha256};
+use tokio::sync::oneshot;
+
+use crate::{credentials::StaticCredentials, GazenotBuilder, Owner, SourceHost};
+
+/// A fake Abyss running in the background of the current tokio runtime
+///
+/// The server shuts down when this is dropped.
+pub struct FakeAbyss {
+    api_addr: SocketAddr,
+    hosting_addr: SocketAddr,
+    shared: Arc<Shared>,
+    shutdown: Vec<oneshot::Sender<()>>,
+}
+
+/// Everything a [`FakeAbyss`][] has been told, see [`FakeAbyss::state`][]
+#[derive(Debug, Clone, Default)]
+pub struct FakeAbyssState {
+    /// ArtifactSets, in the order they were created
+    pub artifact_sets: Vec<FakeArtifactSet>,
+    /// Releases, in the order they were created
+    pub releases: Vec<FakeRelease>,
+    /// Announcements, in the order they were created
+    pub announcements: Vec<FakeAnnouncement>,
+}
+
+/// An ArtifactSet stored by a [`FakeAbyss`][]
+#[derive(Debug, Clone)]
+pub struct FakeArtifactSet {
+    pub source_host: String,
+    pub owner: String,
+    pub package: String,
+    pub public_id: String,
+    pub created_at: SystemTime,
+    /// Contents of the files uploaded to the set, by filename
+    pub files: BTreeMap<String, Vec<u8>>,
+}
+
+/// A Release stored by a [`FakeAbyss`][]
+#[derive(Debug, Clone)]
+pub struct FakeRelease {
+    pub source_host: String,
+    pub owner: String,
+    pub package: String,
+    pub artifact_set_id: String,
+    pub tag: String,
+    pub version: String,
+    pub is_prerelease: bool,
+    pub created_at: SystemTime,
+}
+
+/// An announcement stored by a [`FakeAbyss`][]
+#[derive(Debug, Clone)]
+pub struct FakeAnnouncement {
+    pub source_host: String,
+    pub owner: String,
+    /// The (package, tag) of each release being announced
+    pub releases: Vec<(String, String)>,
+    pub body: String,
+}
+
+/// State shared by the server tasks
+struct Shared {
+    api_base: String,
+    hosting_base: String,
+    state: Mutex<FakeAbyssState>,
+    /// Statuses to respond to upcoming API requests with, instead of handling them
+    failures: Mutex<VecDeque<StatusCode>>,
+    /// Statuses to respond to upcoming API requests with, after handling them
+    late_failures: Mutex<VecDeque<StatusCode>>,
+    /// How many ArtifactSets have ever been created (even if they were deleted since)
+    sets_created: AtomicUsize,
+    /// How many upcoming Range requests to the hosting server get the wrong range
+    misaligned_ranges: AtomicUsize,
+    /// How many upcoming uploads get the wrong checksum reported back
+    misreported_checksums: AtomicUsize,
+    /// How long to wait before handling each request
+    response_delay: Mutex<Duration>,
+    /// How many requests are being handled right now
+    in_flight: AtomicUsize,
+    /// The most requests that have ever been handled at once
+    max_in_flight: AtomicUsize,
+}
+
+#[derive(Deserialize)]
+struct CreateReleaseRequest {
+    release: CreateReleaseRequestInner,
+}
+
+#[derive(Deserialize)]
+struct CreateReleaseRequestInner {
+    artifact_set_id: String,
+    tag: String,
+    version: String,
+    is_prerelease: bool,
+}
+
+#[derive(Deserialize)]
+struct AnnounceReleaseRequest {
+    releases: Vec<AnnounceReleaseKey>,
+    body: String,
+}
+
+#[derive(Deserialize)]
+struct AnnounceReleaseKey {
+    package: String,
+    tag: String,
+}
+
+/// Handlers bail out early with an error response (boxed, since it's much bigger than the Ok)
+type HandlerResult = Result<Response<Body>, Box<Response<Body>>>;
+
+impl FakeAbyss {
+    /// The Axo Releases Token the server requires for anything but GETs
+    pub const TOKEN: &'static str = "fake-abyss-token";
+
+    /// Start a fake Abyss on localhost
+    ///
+    /// The API and hosting are served on two different (random) ports.
+    pub async fn start() -> std::io::Result<Self> {
+        let api_listener = TcpListener::bind("127.0.0.1:0")?;
+        let hosting_listener = TcpListener::bind("127.0.0.1:0")?;
+        let api_addr = api_listener.local_addr()?;
+        let hosting_addr = hosting_listener.local_addr()?;
+
+        let shared = Arc::new(Shared {
+            api_base: format!("http://{api_addr}"),
+            hosting_base: format!("http://{hosting_addr}"),
+            state: Mutex::new(FakeAbyssState::default()),
+            failures: Mutex::new(VecDeque::new()),
+            late_failures: Mutex::new(VecDeque::new()),
+            sets_created: AtomicUsize::new(0),
+            misaligned_ranges: AtomicUsize::new(0),
+            misreported_checksums: AtomicUsize::new(0),
+            response_delay: Mutex::new(Duration::ZERO),
+            in_flight: AtomicUsize::new(0),
+            max_in_flight: AtomicUsize::new(0),
+        });
+
+        let api_shutdown = serve(api_listener, shared.clone(), Shared::handle_api)?;
+        let hosting_shutdown = serve(hosting_listener, shared.clone(), Shared::handle_hosting)?;
+
+        Ok(Self {
+            api_addr,
+            hosting_addr,
+            shared,
+            shutdown: vec![api_shutdown, hosting_shutdown],
+        })
+    }
+
+    /// The address of the API server, suitable for [`GazenotBuilder::api_server`][]
+    pub fn api_server(&self) -> String {
+        self.api_addr.to_string()
+    }
+
+    /// The address of the hosting server, suitable for [`GazenotBuilder::hosting_server`][]
+    pub fn hosting_server(&self) -> String {
+        self.hosting_addr.to_string()
+    }
+
+    /// A [`GazenotBuilder`][] that's configured to talk to this server
+    pub fn client_builder(&self, source_host: SourceHost, owner: Owner) -> GazenotBuilder {
+        crate::Gazenot::builder(source_host, owner)
+            .scheme("http")
+            .api_server(self.api_server())
+            .hosting_server(self.hosting_server())
+            .hosting_owner_subdomain(false)
+            .credentials(StaticCredentials::new(Self::TOKEN))
+    }
+
+    /// Get a snapshot of everything the server has been told so far
+    pub fn state(&self) -> FakeAbyssState {
+        self.shared.state.lock().unwrap().clone()
+    }
+
+    /// Make the next `count` API requests fail with the given status, without being handled
+    ///
+    /// Useful for testing retries and error handling.
+    pub fn fail_next_requests(&self, count: usize, status: StatusCode) {
+        let mut failures = self.shared.failures.lock().unwrap();
+        failures.extend(std::iter::repeat(status).take(count));
+    }
+
+    /// Make the next `count` API requests fail with the given status, after being handled
+    ///
+    /// Useful for testing what happens when a request went through, but the client
+    /// never heard about it (like a gateway timing out).
+    pub fn fail_next_responses(&self, count: usize, status: StatusCode) {
+        let mut failures = self.shared.late_failures.lock().unwrap();
+        failures.extend(std::iter::repeat(status).take(count));
+    }
+
+    /// Make the next `count` Range requests to the hosting server get a different
+    /// range than they asked for (starting one byte later), like a misbehaving cache
+    ///
+    /// Useful for testing resumed downloads.
+    pub fn misalign_next_ranges(&self, count: usize) {
+        self.shared
+            .misaligned_ranges
+            .fetch_add(count, Ordering::SeqCst);
+    }
+
+    /// Make the next `count` uploads report a different SHA-256 than what was received
+    /// (though what was received is still stored)
+    ///
+    /// Useful for testing that clients check what the server got.
+    pub fn misreport_next_checksums(&self, count: usize) {
+        self.shared
+            .misreported_checksums
+            .fetch_add(count, Ordering::SeqCst);
+    }
+
+    /// Wait this long before handling each request (to both servers)
+    ///
+    /// Useful for making requests overlap, to test concurrency limits.
+    pub fn delay_responses(&self, delay: Duration) {
+        *self.shared.response_delay.lock().unwrap() = delay;
+    }
+
+    /// The most requests (to both servers) that have ever been handled at once
+    pub fn max_in_flight(&self) -> usize {
+        self.shared.max_in_flight.load(Ordering::SeqCst)
+    }
+}
+
+impl Drop for FakeAbyss {
+    fn drop(&mut self) {
+        for shutdown in self.shutdown.drain(..) {
+            let _ = shutdown.send(());
+        }
+    }
+}
+
+/// Spawn a server on the given listener, returning a handle to shut it down
+fn serve(
+    listener: TcpListener,
+    shared: Arc<Shared>,
+    handler: fn(&Shared, Method, Vec<String>, Request<Body>) -> ResponseFuture<'_>,
+) -> std::io::Result<oneshot::Sender<()>> {
+    listener.set_nonblocking(true)?;
+    let make_service = make_service_fn(move |_| {
+        let shared = shared.clone();
+        async move {
+            Ok::<_, Infallible>(service_fn(move |req: Request<Body>| {
+                let shared = shared.clone();
+                async move {
+                    let in_flight = shared.in_flight.fetch_add(1, Ordering::SeqCst) + 1;
+                    shared.max_in_flight.fetch_max(in_flight, Ordering::SeqCst);
+                    let delay = *shared.response_delay.lock().unwrap();
+                    tokio::time::sleep(delay).await;
+
+                    let method = req.method().clone();
+                    let segments = path_segments(req.uri().path());
+                    let response = match handler(&shared, method, segments, req).await {
+                        Ok(response) => response,
+                        Err(response) => *response,
+                    };
+                    shared.in_flight.fetch_sub(1, Ordering::SeqCst);
+                    Ok::<_, Infallible>(response)
+                }
+            }))
+        }
+    });
+    let server = Server::from_tcp(listener)
+        .map_err(|e| std::io::Error::new(std::io::ErrorKind::Other, e))?
+        .serve(make_service);
+
+    let (shutdown, shutdown_rx) = oneshot::channel();
+    tokio::spawn(server.with_graceful_shutdown(async {
+        let _ = shutdown_rx.await;
+    }));
+    Ok(shutdown)
+}
+
+type ResponseFuture<'a> =
+    std::pin::Pin<Box<dyn std::future::Future<Output = HandlerResult> + Send + 'a>>;
+
+impl Shared {
+    fn handle_api(
+        &self,
+        method: Method,
+        segments: Vec<String>,
+        req: Request<Body>,
+    ) -> ResponseFuture<'_> {
+        Box::pin(async move {
+            if let Some(status) = self.failures.lock().unwrap().pop_front() {
+                return Err(error_response(status, "injected failure"));
+            }
+
+            // Only reads are allowed without auth
+            if method != Method::GET {
+                let expected = format!("Bearer {}", FakeAbyss::TOKEN);
+                let auth = req.headers().get(AUTHORIZATION);
+                if auth.map(|v| v.as_bytes()) != Some(expected.as_bytes()) {
+                    return Err(error_response(
+                        StatusCode::UNAUTHORIZED,
+                        "missing or invalid Axo Releases Token",
+                    ));
+                }
+            }
+
+            let segments = segments.iter().map(|s| s.as_str()).collect::<Vec<_>>();
+            let response = match (method, segments.as_slice()) {
+                // POST /:sourcehost/:owner/:package/artifacts
+                (Method::POST, [source_host, owner, package, "artifacts"]) => {
+                    self.create_artifact_set(source_host, owner, package)
+                }
+                // GET /:sourcehost/:owner/:package/artifacts
+                (Method::GET, [source_host, owner, package, "artifacts"]) => {
+                    let query = req.uri().query().unwrap_or_default();
+                    self.list_artifact_sets(source_host, owner, package, query)
+                }
+                // GET /:sourcehost/:owner/:package/artifacts/:id
+                (Method::GET, [source_host, owner, package, "artifacts", public_id]) => {
+                    self.get_artifact_set(source_host, owner, package, public_id)
+                }
+                // DELETE /:sourcehost/:owner/:package/artifacts/:id
+                (Method::DELETE, [source_host, owner, package, "artifacts", public_id]) => {
+                    self.delete_artifact_set(source_host, owner, package, public_id)
+                }
+                // POST /:sourcehost/:owner/:package/artifacts/:id/upload/:filename
+                (
+                    Method::POST,
+                    [source_host, owner, package, "artifacts", public_id, "upload", filename],
+                ) => {
+                    let checksum = req
+                        .headers()
+                        .get("x-axo-checksum-sha256")
+                        .and_then(|v| v.to_str().ok())
+                        .map(|v| v.to_owned());
+                    let body = read_body(req).await?;
+                    self.upload_file(
+                        (source_host, owner, package),
+                        public_id,
+                        filename,
+                        checksum,
+                        body,
+                    )
+                }
+                // POST /:sourcehost/:owner/:package/releases
+                (Method::POST, [source_host, owner, package, "releases"]) => {
+                    let request = read_json(req).await?;
+                    self.create_release(source_host, owner, package, request)
+                }
+                // GET /:sourcehost/:owner/:package/releases
+                (Method::GET, [source_host, owner, package, "releases"]) => {
+                    self.list_releases(source_host, owner, package)
+                }
+                // POST /:sourcehost/:owner/announcements
+                (Method::POST, [source_host, owner, "announcements"]) => {
+                    let request = read_json(req).await?;
+                    self.create_announcement(source_host, owner, request)
+                }
+                _ => Err(error_response(StatusCode::NOT_FOUND, "no such endpoint")),
+            };
+
+            if let Some(status) = self.late_failures.lock().unwrap().pop_front() {
+                return Err(error_response(status, "injected failure after handling"));
+            }
+            response
+        })
+    }
+
+    fn handle_hosting(
+        &self,
+        method: Method,
+        segments: Vec<String>,
+        req: Request<Body>,
+    ) -> ResponseFuture<'_> {
+        Box::pin(async move {
+            // GET /:owner/:package/:public_id_or_tag/:filename
+            let [owner, package, id_or_tag, filename] = segments.as_slice() else {
+                return Err(error_response(StatusCode::NOT_FOUND, "no such file"));
+            };
+            if method != Method::GET {
+                return Err(error_response(
+                    StatusCode::METHOD_NOT_ALLOWED,
+                    "hosting is read-only",
+                ));
+            }
+
+            let contents = {
+                let state = self.state.lock().unwrap();
+                // Releases are hosted by tag, ArtifactSets by public_id
+                let public_id = state
+                    .releases
+                    .iter()
+                    .find(|r| &r.owner == owner && &r.package == package && &r.tag == id_or_tag)
+                    .map(|r| &r.artifact_set_id)
+                    .unwrap_or(id_or_tag);
+                state
+                    .artifact_sets
+                    .iter()
+                    .find(|s| {
+                        &s.owner == owner && &s.package == package && &s.public_id == public_id
+                    })
+                    .and_then(|s| s.files.get(filename))
+                    .cloned()
+                    .ok_or_else(|| error_response(StatusCode::NOT_FOUND, "no such file"))?
+            };
+            let misalign = req.headers().contains_key(RANGE)
+                && self
+                    .misaligned_ranges
+                    .fetch_update(Ordering::SeqCst, Ordering::SeqCst, |n| n.checked_sub(1))
+                    .is_ok();
+            Ok(file_response(&req, contents, misalign))
+        })
+    }
+
+    fn create_artifact_set(&self, source_host: &str, owner: &str, package: &str) -> HandlerResult {
+        let mut state = self.state.lock().unwrap();
+        let set_number = self.sets_created.fetch_add(1, Ordering::Relaxed) + 1;
+        let public_id = format!("fake-set-{set_number}");
+        state.artifact_sets.push(FakeArtifactSet {
+            source_host: source_host.to_owned(),
+            owner: owner.to_owned(),
+            package: package.to_owned(),
+            public_id: public_id.clone(),
+            created_at: SystemTime::now(),
+            files: BTreeMap::new(),
+        });
+
+        let api = &self.api_base;
+        let hosting = &self.hosting_base;
+        Ok(success_response(json!({
+            "public_id": public_id,
+            "set_download_url": format!("{hosting}/{owner}/{package}/{public_id}"),
+            "upload_url": format!("{api}/{source_host}/{owner}/{package}/artifacts/{public_id}/upload"),
+            "release_url": format!("{api}/{source_host}/{owner}/{package}/releases"),
+            "announce_url": format!("{api}/{source_host}/{owner}/announcements"),
+        })))
+    }
+
+    fn get_artifact_set(
+        &self,
+        source_host: &str,
+        owner: &str,
+        package: &str,
+        public_id: &str,
+    ) -> HandlerResult {
+        let state = self.state.lock().unwrap();
+        let set = state
+            .artifact_sets
+            .iter()
+            .find(|s| {
+                s.source_host == source_host
+                    && s.owner == owner
+                    && s.package == package
+                    && s.public_id == public_id
+            })
+            .ok_or_else(|| error_response(StatusCode::NOT_FOUND, "no such artifact set"))?;
+        Ok(success_response(self.artifact_set_json(&state, set)))
+    }
+
+    fn list_artifact_sets(
+        &self,
+        source_host: &str,
+        owner: &str,
+        package: &str,
+        query: &str,
+    ) -> HandlerResult {
+        let mut only_open = false;
+        let mut older_than = None;
+        for (key, value) in url::form_urlencoded::parse(query.as_bytes()) {
+            match &*key {
+                "state" if value == "open" => only_open = true,
+                "older_than_secs" => {
+                    let secs = value.parse().map_err(|_| {
+                        error_response(StatusCode::BAD_REQUEST, "invalid older_than_secs")
+                    })?;
+                    older_than = Some(Duration::from_secs(secs));
+                }
+                _ => return Err(error_response(StatusCode::BAD_REQUEST, "invalid query")),
+            }
+        }
+
+        let state = self.state.lock().unwrap();
+        let now = SystemTime::now();
+        let artifact_sets = state
+            .artifact_sets
+            .iter()
+            .filter(|s| s.source_host == source_host && s.owner == owner && s.package == package)
+            .filter(|s| !only_open || !is_released(&state, s))
+            .filter(|s| {
+                older_than.map_or(true, |older_than| {
+                    now.duration_since(s.created_at).unwrap_or_default() >= older_than
+                })
+            })
+            .map(|s| self.artifact_set_json(&state, s))
+            .collect::<Vec<_>>();
+
+        Ok(success_response(json!({ "artifact_sets": artifact_sets })))
+    }
+
+    fn delete_artifact_set(
+        &self,
+        source_host: &str,
+        owner: &str,
+        package: &str,
+        public_id: &str,
+    ) -> HandlerResult {
+        let mut state = self.state.lock().unwrap();
+        let idx = state
+            .artifact_sets
+            .iter()
+            .position(|s| {
+                s.source_host == source_host
+                    && s.owner == owner
+                    && s.package == package
+                    && s.public_id == public_id
+            })
+            .ok_or_else(|| error_response(StatusCode::NOT_FOUND, "no such artifact set"))?;
+        if is_released(&state, &state.artifact_sets[idx]) {
+            return Err(error_response(
+                StatusCode::CONFLICT,
+                "artifact set has been released",
+            ));
+        }
+        state.artifact_sets.remove(idx);
+
+        Ok(success_response(json!({})))
+    }
+
+    fn artifact_set_json(
+        &self,
+        state: &FakeAbyssState,
+        set: &FakeArtifactSet,
+    ) -> serde_json::Value {
+        let FakeArtifactSet {
+            source_host,
+            owner,
+            package,
+            public_id,
+            created_at,
+            files,
+        } = set;
+        let api = &self.api_base;
+        let hosting = &self.hosting_base;
+        let set_download_url = format!("{hosting}/{owner}/{package}/{public_id}");
+        let files = files
+            .iter()
+            .map(|(name, contents)| {
+                json!({
+                    "name": name,
+                    "size": contents.len(),
+                    "sha256": format!("{:x}", Sha256::digest(contents)),
+                    "download_url": format!("{set_download_url}/{name}"),
+                })
+            })
+            .collect::<Vec<_>>();
+        json!({
+            "public_id": public_id,
+            "set_download_url": set_download_url,
+            "upload_url": format!("{api}/{source_host}/{owner}/{package}/artifacts/{public_id}/upload"),
+            "release_url": format!("{api}/{source_host}/{owner}/{package}/releases"),
+            "announce_url": format!("{api}/{source_host}/{owner}/announcements"),
+            "created_at": humantime::format_rfc3339_seconds(*created_at).to_string(),
+            "state": if is_released(state, set) { "released" } else { "open" },
+            "files": files,
+        })
+    }
+
+    fn upload_file(
+        &self,
+        (source_host, owner, package): (&str, &str, &str),
+        public_id: &str,
+        filename: &str,
+        checksum: Option<String>,
+        body: Vec<u8>,
+    ) -> HandlerResult {
+        let sha256 = format!("{:x}", Sha256::digest(&body));
+        if let Some(checksum) = checksum {
+            if !checksum.eq_ignore_ascii_case(&sha256) {
+                return Err(error_response(
+                    StatusCode::BAD_REQUEST,
+                    &format!("checksum mismatch: got {sha256}, expected {checksum}"),
+                ));
+            }
+        }
+
+        let mut state = self.state.lock().unwrap();
+        let set = state
+            .artifact_sets
+            .iter_mut()
+            .find(|s| {
+                s.source_host == source_host
+                    && s.owner == owner
+                    && s.package == package
+                    && s.public_id == public_id
+            })
+            .ok_or_else(|| error_response(StatusCode::NOT_FOUND, "no such artifact set"))?;
+        set.files.insert(filename.to_owned(), body);
+
+        let misreport = self
+            .misreported_checksums
+            .fetch_update(Ordering::SeqCst, Ordering::SeqCst, |n| n.checked_sub(1))
+            .is_ok();
+        let sha256 = if misreport {
+            format!("{:x}", Sha256::digest(b"something else"))
+        } else {
+            sha256
+        };
+        Ok(success_response(json!({ "sha256": sha256 })))
+    }
+
+    fn create_release(
+        &self,
+        source_host: &str,
+        owner: &str,
+        package: &str,
+        request: CreateReleaseRequest,
+    ) -> HandlerResult {
+        let CreateReleaseRequestInner {
+            artifact_set_id,
+            tag,
+            version,
+            is_prerelease,
+        } = request.release;
+
+        let mut state = self.state.lock().unwrap();
+        let set_exists = state.artifact_sets.iter().any(|s| {
+            s.source_host == source_host
+                && s.owner == owner
+                && s.package == package
+                && s.public_id == artifact_set_id
+        });
+        if !set_exists {
+            return Err(error_response(
+                StatusCode::NOT_FOUND,
+                "no such artifact set",
+            ));
+        }
+        let release_exists = state.releases.iter().any(|r| {
+            r.source_host == source_host && r.owner == owner && r.package == package && r.tag == tag
+        });
+        if release_exists {
+            return Err(error_response(
+                StatusCode::CONFLICT,
+                "release already exists",
+            ));
+        }
+
+        let hosting = &self.hosting_base;
+        let release_download_url = format!("{hosting}/{owner}/{package}/{tag}");
+        state.releases.push(FakeRelease {
+            source_host: source_host.to_owned(),
+            owner: owner.to_owned(),
+            package: package.to_owned(),
+            artifact_set_id,
+            tag,
+            version,
+            is_prerelease,
+            created_at: SystemTime::now(),
+        });
+
+        Ok(success_response(json!({
+            "release_download_url": release_download_url,
+        })))
+    }
+
+    fn list_releases(&self, source_host: &str, owner: &str, package: &str) -> HandlerResult {
+        let state = self.state.lock().unwrap();
+        let hosting = &self.hosting_base;
+        // Newest first
+        let releases = state
+            .releases
+            .iter()
+            .rev()
+            .filter(|r| r.source_host == source_host && r.owner == owner && r.package == package)
+            .map(|r| {
+                let release_download_url = format!("{hosting}/{owner}/{package}/{}", r.tag);
+                let artifacts = state
+                    .artifact_sets
+                    .iter()
+                    .find(|s| s.public_id == r.artifact_set_id)
+                    .map(|s| {
+                        s.files
+                            .keys()
+                            .map(|name| {
+                                json!({
+                                    "name": name,
+                                    "download_url": format!("{release_download_url}/{name}"),
+                                })
+                            })
+                            .collect::<Vec<_>>()
+                    })
+                    .unwrap_or_default();
+                json!({
+                    "tag": r.tag,
+                    "version": r.version,
+                    "is_prerelease": r.is_prerelease,
+                    "created_at": humantime::format_rfc3339_seconds(r.created_at).to_string(),
+                    "release_download_url": release_download_url,
+                    "artifacts": artifacts,
+                })
+            })
+            .collect::<Vec<_>>();
+
+        Ok(success_response(json!({ "releases": releases })))
+    }
+
+    fn create_announcement(
+        &self,
+        source_host: &str,
+        owner: &str,
+        request: AnnounceReleaseRequest,
+    ) -> HandlerResult {
+        let mut state = self.state.lock().unwrap();
+        for key in &request.releases {
+            let release_exists = state.releases.iter().any(|r| {
+                r.source_host == source_host
+                    && r.owner == owner
+                    && r.package == key.package
+                    && r.tag == key.tag
+            });
+            if !release_exists {
+                return Err(error_response(
+                    StatusCode::NOT_FOUND,
+                    &format!("no such release {} {}", key.package, key.tag),
+                ));
+            }
+        }
+        state.announcements.push(FakeAnnouncement {
+            source_host: source_host.to_owned(),
+            owner: owner.to_owned(),
+            releases: request
+                .releases
+                .into_iter()
+                .map(|key| (key.package, key.tag))
+                .collect(),
+            body: request.body,
+        });
+
+        Ok(success_response(json!({})))
+    }
+}
+
+/// Whether a Release has been created from an ArtifactSet
+fn is_released(state: &FakeAbyssState, set: &FakeArtifactSet) -> bool {
+    state.releases.iter().any(|r| {
+        r.source_host == set.source_host
+            && r.owner == set.owner
+            && r.package == set.package
+            && r.artifact_set_id == set.public_id
+    })
+}
+
+/// Split a URL path into percent-decoded segments
+fn path_segments(path: &str) -> Vec<String> {
+    path.split('/')
+        .filter(|s| !s.is_empty())
+        .map(|s| {
+            percent_encoding::percent_decode_str(s)
+                .decode_utf8_lossy()
+                .into_owned()
+        })
+        .collect()
+}
+
+async fn read_body(req: Request<Body>) -> Result<Vec<u8>, Box<Response<Body>>> {
+    hyper::body::to_bytes(req.into_body())
+        .await
+        .map(|bytes| bytes.to_vec())
+        .map_err(|e| error_response(StatusCode::BAD_REQUEST, &e.to_string()))
+}
+
+async fn read_json<T: for<'a> Deserialize<'a>>(
+    req: Request<Body>,
+) -> Result<T, Box<Response<Body>>> {
+    let body = read_body(req).await?;
+    serde_json::from_slice(&body)
+        .map_err(|e| error_response(StatusCode::BAD_REQUEST, &e.to_string()))
+}
+
+fn json_response(status: StatusCode, value: serde_json::Value) -> Response<Body> {
+    Response::builder()
+        .status(status)
+        .header(CONTENT_TYPE, "application/json")
+        .body(Body::from(value.to_string()))
+        .expect("failed to build response")
+}
+
+fn success_response(result: serde_json::Value) -> Response<Body> {
+    json_response(
+        StatusCode::OK,
+        json!({
+            "success": true,
+            "result": result,
+        }),
+    )
+}
+
+fn error_response(status: StatusCode, error: &str) -> Box<Response<Body>> {
+    Box::new(json_response(
+        status,
+        json!({
+            "success": false,
+            "errors": [error],
+        }),
+    ))
+}
+
+/// Serve a file, supporting `Range: bytes=N-` requests (guarded by If-Range)
+///
+/// If `misalign` is set, a Range request gets the range one byte later than it asked for.
+fn file_response(req: &Request<Body>, contents: Vec<u8>, misalign: bool) -> Response<Body> {
+    let etag = format!("\"{:x}\"", Sha256::digest(&contents));
+    let header = |name| req.headers().get(name).and_then(|v| v.to_str().ok());
+    let range_start = header(RANGE)
+        .and_then(|range| range.strip_prefix("bytes="))
+        .and_then(|range| range.strip_suffix('-'))
+        .and_then(|start| start.parse::<usize>().ok())
+        .map(|start| start + usize::from(misalign));
+    let if_range_ok = header(IF_RANGE).map_or(true, |if_range| if_range == etag);
+
+    let builder = Response::builder().header(ETAG, &etag);
+    let response = match range_start {
+        Some(start) if if_range_ok && start >= contents.len() => builder
+            .status(StatusCode::RANGE_NOT_SATISFIABLE)
+            .header(CONTENT_RANGE, format!("bytes */{}", contents.len()))
+            .body(Body::empty()),
+        Some(start) if if_range_ok => builder
+            .status(StatusCode::PARTIAL_CONTENT)
+            .header(
+                CONTENT_RANGE,
+                format!("bytes {start}-{}/{}", contents.len() - 1, contents.len()),
+            )
+            .body(Body::from(contents[start..].to_vec())),
+        _ => builder.status(StatusCode::OK).body(Body::from(contents)),
+    };
+    response.expect("failed to build response")
+}
+
+/// A fresh, empty directory for a test to work in
+#[cfg(test)]
+pub(crate) fn scratch_dir(name: &str) -> camino::Utf8PathBuf {
+    let dir = std::env::temp_dir().join(format!("gazenot-{name}-{}", std::process::id()));
+    let dir = camino::Utf8PathBuf::from_path_buf(dir).expect("temp dir isn't utf8");
+    let _ = std::fs::remove_dir_all(&dir);
+    std::fs::create_dir_all(&dir).expect("couldn't create scratch dir");
+    dir
+}
@@ -0,0 +1,626 @@
+//! Validated string types for the names and identifiers The Abyss deals in
+//!
+//! These are all checked when they're created (including when they're deserialized),
+//! so invalid values are caught with a precise error before they're ever sent to
+//! the server, and a package name can't be passed where an owner was expected.
+//! The exception is values the server sends back: it's the authority on what it
+//! accepts, so one value we don't expect shouldn't make a whole listing unreadable.
+//!
+//! ```
+//! use gazenot::{Owner, PackageName};
+//!
+//! let package: PackageName = "my-app".parse().unwrap();
+//! let owner = Owner::new("axodotdev").unwrap();
+//! assert_eq!(package, "my-app");
+//! assert!(PackageName::new("my app").is_err());
+//! # let _ = owner;
+//! ```
+
+use std::{borrow::Borrow, fmt, ops::Deref, str::FromStr};
+
+use miette::{Diagnostic, SourceSpan};
+use schemars::JsonSchema;
+use serde::{Deserialize, Deserializer, Serialize};
+use thiserror::Error;
+
+/// A value that isn't valid for the type it was supposed to become
+#[derive(Error, Debug, Diagnostic)]
+#[error("{value:?} isn't a valid {kind}")]
+pub struct InvalidValue {
+    /// What kind of value it was supposed to be (e.g. "package name")
+    pub kind: &'static str,
+    /// The value
+    #[source_code]
+    pub value: String,
+    /// What's wrong with the value
+    pub reason: String,
+    /// The part of the value that's wrong
+    #[label("{reason}")]
+    pub span: SourceSpan,
+    #[help]
+    pub help: Option<String>,
+}
+
+/// Why a value is invalid: the byte range that's wrong, and what's wrong with it
+type Invalid = (std::ops::Range<usize>, String);
+
+/// A [`ReleaseKey`][crate::ReleaseKey] that doesn't agree with its tag
+///
+/// The source code of the diagnostic is the ReleaseKey, with a label on the field that's wrong.
+#[derive(Error, Debug, Diagnostic)]
+#[error("ReleaseKey for {package} doesn't agree with its tag")]
+pub struct InvalidReleaseKey {
+    /// The package the release is for
+    pub package: String,
+    /// The ReleaseKey, one field per line
+    #[source_code]
+    pub key: String,
+    /// What's wrong with the ReleaseKey
+    pub reason: String,
+    /// The part of the ReleaseKey that's wrong
+    #[label("{reason}")]
+    pub span: SourceSpan,
+    #[help]
+    pub help: Option<String>,
+}
+
+macro_rules! validated_string {
+    ($(#[$meta:meta])* $name:ident, $kind:literal, $validate:path, $help:expr) => {
+        $(#[$meta])*
+        #[derive(
+            Serialize, Deserialize, Debug, Clone, PartialEq, Eq, PartialOrd, Ord, Hash, JsonSchema,
+        )]
+        #[serde(try_from = "String", into = "String")]
+        #[schemars(transparent)]
+        pub struct $name(String);
+
+        impl $name {
+            /// Check that the value is valid
+            pub fn new(value: impl Into<String>) -> Result<Self, InvalidValue> {
+                let value = value.into();
+                match $validate(&value) {
+                    Ok(()) => Ok(Self(value)),
+                    Err((range, reason)) => Err(InvalidValue {
+                        kind: $kind,
+                        span: (range.start, range.end - range.start).into(),
+                        value,
+                        reason,
+                        help: $help,
+                    }),
+                }
+            }
+
+            /// The value as a string
+            pub fn as_str(&self) -> &str {
+                &self.0
+            }
+
+            /// The value as a string
+            pub fn into_string(self) -> String {
+                self.0
+            }
+        }
+
+        impl Unvalidated for $name {
+            fn unvalidated(value: String) -> Self {
+                Self(value)
+            }
+        }
+
+        impl FromStr for $name {
+            type Err = InvalidValue;
+            fn from_str(value: &str) -> Result<Self, Self::Err> {
+                Self::new(value)
+            }
+        }
+
+        impl TryFrom<String> for $name {
+            type Error = InvalidValue;
+            fn try_from(value: String) -> Result<Self, Self::Error> {
+                Self::new(value)
+            }
+        }
+
+        impl TryFrom<&str> for $name {
+            type Error = InvalidValue;
+            fn try_from(value: &str) -> Result<Self, Self::Error> {
+                Self::new(value)
+            }
+        }
+
+        impl From<$name> for String {
+            fn from(value: $name) -> Self {
+                value.0
+            }
+        }
+
+        impl fmt::Display for $name {
+            fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+                self.0.fmt(f)
+            }
+        }
+
+        impl Deref for $name {
+            type Target = str;
+            fn deref(&self) -> &str {
+                &self.0
+            }
+        }
+
+        impl AsRef<str> for $name {
+            fn as_ref(&self) -> &str {
+                &self.0
+            }
+        }
+
+        impl Borrow<str> for $name {
+            fn borrow(&self) -> &str {
+                &self.0
+            }
+        }
+
+        impl PartialEq<str> for $name {
+            fn eq(&self, other: &str) -> bool {
+                self.0 == other
+            }
+        }
+
+        impl PartialEq<&str> for $name {
+            fn eq(&self, other: &&str) -> bool {
+                self.0 == *other
+            }
+        }
+
+        impl PartialEq<String> for $name {
+            fn eq(&self, other: &String) -> bool {
+                &self.0 == other
+            }
+        }
+    };
+}
+
+/// Validated string types that can be created without validating them, see [`lenient`][]
+pub(crate) trait Unvalidated {
+    fn unvalidated(value: String) -> Self;
+}
+
+/// Deserialize a validated string type without validating it
+///
+/// Use this (with `#[serde(deserialize_with = ...)]`) for fields of responses from the server.
+pub(crate) fn lenient<'de, D, T>(deserializer: D) -> Result<T, D::Error>
+where
+    D: Deserializer<'de>,
+    T: Unvalidated,
+{
+    String::deserialize(deserializer).map(T::unvalidated)
+}
+
+/// [`lenient`][] for optional fields (which also need `#[serde(default)]`)
+pub(crate) fn lenient_option<'de, D, T>(deserializer: D) -> Result<Option<T>, D::Error>
+where
+    D: Deserializer<'de>,
+    T: Unvalidated,
+{
+    Option::<String>::deserialize(deserializer).map(|value| value.map(T::unvalidated))
+}
+
+validated_string!(
+    /// The public_id of an ArtifactSet
+    ArtifactSetId,
+    "ArtifactSet id",
+    validate_artifact_set_id,
+    None
+);
+validated_string!(
+    /// The owner of a package (e.g. "axodotdev")
+    Owner,
+    "owner",
+    validate_owner,
+    Some("owners can only contain ascii letters, numbers, and '-'".to_owned())
+);
+validated_string!(
+    /// The source hosting provider (e.g. "github")
+    SourceHost,
+    "source host",
+    validate_source_host,
+    Some("source hosts are lowercase, like \"github\"".to_owned())
+);
+validated_string!(
+    /// The name of a package
+    PackageName,
+    "package name",
+    validate_name,
+    Some("package names can only contain ascii letters, numbers, '-', '_', and '.'".to_owned())
+);
+validated_string!(
+    /// The tag for a Release
+    ///
+    /// This has the same rules as a git tag.
+    ReleaseTag,
+    "release tag",
+    validate_tag,
+    Some("release tags must be valid git tags".to_owned())
+);
+validated_string!(
+    /// An unparsed URL
+    ///
+    /// This is only checked to be an absolute URL with no whitespace,
+    /// not fully parsed.
+    UnparsedUrl,
+    "URL",
+    validate_url,
+    None
+);
+validated_string!(
+    /// An unparsed SemVer Version
+    ///
+    /// This is checked to have the shape of a SemVer version (like "1.0.0-prerelease.1"),
+    /// but not parsed into one.
+    UnparsedVersion,
+    "version",
+    validate_version,
+    Some("versions look like \"1.2.3\" or \"1.2.3-prerelease.1\"".to_owned())
+);
+
+fn validate_artifact_set_id(value: &str) -> Result<(), Invalid> {
+    check_not_empty(value)?;
+    check_chars(value, |c| c.is_ascii_alphanumeric() || c == '-' || c == '_')
+}
+
+/// Package names
+///
+/// These get put in URLs, so we're fairly strict about them.
+fn validate_name(value: &str) -> Result<(), Invalid> {
+    check_not_empty(value)?;
+    check_chars(value, |c| {
+        c.is_ascii_alphanumeric() || c == '-' || c == '_' || c == '.'
+    })?;
+    if value.starts_with(['.', '-']) {
+        return Err((0..1, "can't start with this".to_owned()));
+    }
+    Ok(())
+}
+
+/// Owners are also used as subdomains of the hosting server,
+/// so they have to be valid DNS labels.
+fn validate_owner(value: &str) -> Result<(), Invalid> {
+    check_not_empty(value)?;
+    check_chars(value, |c| c.is_ascii_alphanumeric() || c == '-')?;
+    if value.starts_with('-') {
+        return Err((0..1, "can't start with this".to_owned()));
+    }
+    if value.ends_with('-') {
+        let last = value.len() - 1;
+        return Err((last..value.len(), "can't end with this".to_owned()));
+    }
+    if value.len() > 63 {
+        return Err((63..value.len(), "is too long to be a subdomain".to_owned()));
+    }
+    Ok(())
+}
+
+fn validate_source_host(value: &str) -> Result<(), Invalid> {
+    check_not_empty(value)?;
+    check_chars(value, |c| {
+        c.is_ascii_lowercase() || c.is_ascii_digit() || c == '-'
+    })?;
+    if value.starts_with('-') {
+        return Err((0..1, "can't start with this".to_owned()));
+    }
+    Ok(())
+}
+
+/// A subset of the rules from `git check-ref-format`
+fn validate_tag(value: &str) -> Result<(), Invalid> {
+    check_not_empty(value)?;
+    check_chars(value, |c| {
+        !c.is_ascii_control() && !c.is_whitespace() && !"~^:?*[\\".contains(c)
+    })?;
+    for bad in ["..", "@{", "//"] {
+        if let Some(idx) = value.find(bad) {
+            return Err((idx..idx + bad.len(), "not allowed in tags".to_owned()));
+        }
+    }
+    let last = value.len() - 1;
+    if value.starts_with('/') {
+        return Err((0..1, "can't start with this".to_owned()));
+    }
+    if value.ends_with(['/', '.']) {
+        return Err((last..value.len(), "can't end with this".to_owned()));
+    }
+    if value.ends_with(".lock") {
+        let start = value.len() - ".lock".len();
+        return Err((start..value.len(), "can't end with this".to_owned()));
+    }
+    let mut offset = 0;
+    for component in value.split('/') {
+        if component.starts_with('.') {
+            return Err((offset..offset + 1, "parts can't start with this".to_owned()));
+        }
+        offset += component.len() + 1;
+    }
+    Ok(())
+}
+
+fn validate_url(value: &str) -> Result<(), Invalid> {
+    check_not_empty(value)?;
+    check_chars(value, |c| !c.is_ascii_control() && !c.is_whitespace())?;
+    let Some(scheme_len) = value.find("://") else {
+        return Err((0..value.len(), "isn't an absolute URL".to_owned()));
+    };
+    let scheme = &value[..scheme_len];
+    let scheme_ok = scheme.starts_with(|c: char| c.is_ascii_alphabetic())
+        && scheme
+            .chars()
+            .all(|c| c.is_ascii_alphanumeric() || "+-.".contains(c));
+    if !scheme_ok {
+        return Err((0..scheme_len, "isn't a valid scheme".to_owned()));
+    }
+    if value.len() == scheme_len + "://".len() {
+        return Err((0..value.len(), "has no host".to_owned()));
+    }
+    Ok(())
+}
+
+/// Checks the shape of [SemVer 2.0](https://semver.org)
+fn validate_version(value: &str) -> Result<(), Invalid> {
+    check_not_empty(value)?;
+    let (rest, build) = match value.split_once('+') {
+        Some((rest, build)) => (rest, Some(build)),
+        None => (value, None),
+    };
+    let (core, pre) = match rest.split_once('-') {
+        Some((core, pre)) => (core, Some(pre)),
+        None => (rest, None),
+    };
+
+    let mut offset = 0;
+    let parts = core.split('.').collect::<Vec<_>>();
+    if parts.len() != 3 {
+        return Err((
+            0..core.len(),
+            "should be three numbers, like 1.2.3".to_owned(),
+        ));
+    }
+    for part in parts {
+        let range = offset..offset + part.len();
+        if part.is_empty() || !part.chars().all(|c| c.is_ascii_digit()) {
+            return Err((range, "isn't a number".to_owned()));
+        }
+        if part.len() > 1 && part.starts_with('0') {
+            return Err((range, "numbers can't have leading zeros".to_owned()));
+        }
+        offset += part.len() + 1;
+    }
+
+    // The identifiers of the prerelease and build metadata have the same rules,
+    // except prerelease numbers can't have leading zeros
+    let mut offset = core.len() + 1;
+    for (section, numeric_rules) in [(pre, true), (build, false)] {
+        let Some(section) = section else {
+            continue;
+        };
+        for ident in section.split('.') {
+            let range = offset..offset + ident.len();
+            if ident.is_empty() {
+                return Err((offset - 1..offset, "missing identifier here".to_owned()));
+            }
+            let is_ident_char = |c: char| c.is_ascii_alphanumeric() || c == '-';
+            if let Some((idx, c)) = ident.char_indices().find(|(_, c)| !is_ident_char(*c)) {
+                let start = offset + idx;
+                return Err((start..start + c.len_utf8(), "invalid character".to_owned()));
+            }
+            let is_number = ident.chars().all(|c| c.is_ascii_digit());
+            if numeric_rules && is_number && ident.len() > 1 && ident.starts_with('0') {
+                return Err((range, "numbers can't have leading zeros".to_owned()));
+            }
+            offset += ident.len() + 1;
+        }
+    }
+    Ok(())
+}
+
+/// Get the version out of a tag, the same way axotag does
+///
+/// The tag can be the version itself, optionally prefixed with "v", and optionally
+/// prefixed with the package name and a "-" or "/" before that:
+/// "1.2.3", "v1.2.3", "my-app-v1.2.3", and "my-app/1.2.3" are all version 1.2.3.
+pub(crate) fn version_from_tag(package: &str, tag: &str) -> Result<UnparsedVersion, Invalid> {
+    let mut start = 0;
+    if let Some(rest) = tag.strip_prefix(package) {
+        if rest.starts_with(['-', '/']) {
+            start = package.len() + 1;
+        }
+    }
+    if tag[start..].starts_with('v') {
+        start += 1;
+    }
+    if !tag[start..].starts_with(|c: char| c.is_ascii_digit()) {
+        // Point at whatever comes before something that looks like a version,
+        // which is probably the name of some other package
+        let looks_like_version = |rest: &str| {
+            let rest = rest.strip_prefix('v').unwrap_or(rest);
+            rest.starts_with(|c: char| c.is_ascii_digit())
+        };
+        let end = tag[start..]
+            .match_indices(['-', '/'])
+            .map(|(idx, _)| start + idx + 1)
+            .find(|&idx| looks_like_version(&tag[idx..]))
+            .unwrap_or(tag.len());
+        let reason = if start == 0 {
+            format!("expected a version, or \"{package}\" followed by a version")
+        } else {
+            "expected a version".to_owned()
+        };
+        return Err((start..end, reason));
+    }
+    UnparsedVersion::new(&tag[start..]).map_err(|e| {
+        let offset = start + e.span.offset();
+        (offset..offset + e.span.len(), e.reason)
+    })
+}
+
+/// Whether a (valid) version has a prerelease part, like the "-beta.1" in "1.2.3-beta.1"
+pub(crate) fn is_prerelease(version: &str) -> bool {
+    let without_build = version.split_once('+').map_or(version, |(rest, _)| rest);
+    without_build.contains('-')
+}
+
+/// Everything but the unreserved characters of RFC 3986
+#[cfg(feature = "client_lib")]
+const PATH_SEGMENT: &percent_encoding::AsciiSet = &percent_encoding::NON_ALPHANUMERIC
+    .remove(b'-')
+    .remove(b'.')
+    .remove(b'_')
+    .remove(b'~');
+
+/// Percent-encode a value so it can be used as a single URL path segment
+#[cfg(feature = "client_lib")]
+pub(crate) fn encode_path_segment(value: &str) -> String {
+    percent_encoding::utf8_percent_encode(value, PATH_SEGMENT).to_string()
+}
+
+fn check_not_empty(value: &str) -> Result<(), Invalid> {
+    if value.is_empty() {
+        Err((0..0, "is empty".to_owned()))
+    } else {
+        Ok(())
+    }
+}
+
+fn check_chars(value: &str, allowed: impl Fn(char) -> bool) -> Result<(), Invalid> {
+    match value.char_indices().find(|(_, c)| !allowed(*c)) {
+        Some((idx, c)) => Err((idx..idx + c.len_utf8(), "invalid character".to_owned())),
+        None => Ok(()),
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::ReleaseKey;
+
+    /// The part of the value an error points at
+    fn blamed(err: &InvalidValue) -> &str {
+        let start = err.span.offset();
+        &err.value[start..start + err.span.len()]
+    }
+
+    #[test]
+    fn names() {
+        for name in ["my-app", "my_app", "app.rs", "A1"] {
+            PackageName::new(name).unwrap();
+        }
+        assert_eq!(blamed(&PackageName::new("my app").unwrap_err()), " ");
+        assert_eq!(blamed(&PackageName::new(".hidden").unwrap_err()), ".");
+        assert!(PackageName::new("").is_err());
+        assert!(SourceHost::new("GitHub").is_err());
+    }
+
+    #[test]
+    fn owners_are_subdomains() {
+        for owner in ["axodotdev", "Axo-Dev", "a1", &"a".repeat(63)] {
+            Owner::new(owner).unwrap();
+        }
+        assert_eq!(blamed(&Owner::new("a_b").unwrap_err()), "_");
+        assert_eq!(blamed(&Owner::new("a.b").unwrap_err()), ".");
+        assert_eq!(blamed(&Owner::new("-owner").unwrap_err()), "-");
+        assert_eq!(blamed(&Owner::new("owner-").unwrap_err()), "-");
+        assert_eq!(blamed(&Owner::new("a".repeat(64)).unwrap_err()), "a");
+        assert!(Owner::new("").is_err());
+    }
+
+    #[test]
+    fn tags() {
+        for tag in ["v1.0.0", "my-app/v1.0.0", "release-2024"] {
+            ReleaseTag::new(tag).unwrap();
+        }
+        assert_eq!(blamed(&ReleaseTag::new("v1..0").unwrap_err()), "..");
+        assert_eq!(blamed(&ReleaseTag::new("v1.0.lock").unwrap_err()), ".lock");
+        assert_eq!(blamed(&ReleaseTag::new("v1 0").unwrap_err()), " ");
+        assert_eq!(blamed(&ReleaseTag::new("app/.v1").unwrap_err()), ".");
+        assert!(ReleaseTag::new("/v1").is_err());
+    }
+
+    #[test]
+    fn versions() {
+        for version in [
+            "1.2.3",
+            "0.0.0",
+            "1.2.3-beta.1",
+            "1.2.3+build.05",
+            "1.2.3-rc-1+x",
+        ] {
+            UnparsedVersion::new(version).unwrap();
+        }
+        assert_eq!(blamed(&UnparsedVersion::new("1.2").unwrap_err()), "1.2");
+        assert_eq!(blamed(&UnparsedVersion::new("1.02.3").unwrap_err()), "02");
+        assert_eq!(blamed(&UnparsedVersion::new("1.2.3-01").unwrap_err()), "01");
+        assert_eq!(
+            blamed(&UnparsedVersion::new("1.2.3-beta!").unwrap_err()),
+            "!"
+        );
+        assert!(UnparsedVersion::new("1.2.3-").is_err());
+    }
+
+    #[test]
+    #[cfg(feature = "client_lib")]
+    fn path_segments() {
+        assert_eq!(encode_path_segment("my-app_1.0~x"), "my-app_1.0~x");
+        assert_eq!(
+            encode_path_segment("my-app/v1.0.0 é%"),
+            "my-app%2Fv1.0.0%20%C3%A9%25"
+        );
+    }
+
+    #[test]
+    fn inputs_are_validated_when_deserialized() {
+        assert!(serde_json::from_str::<PackageName>(r#""my-app""#).is_ok());
+        assert!(serde_json::from_str::<PackageName>(r#""my app""#).is_err());
+        assert!(serde_json::from_str::<UnparsedVersion>(r#""1.2""#).is_err());
+    }
+
+    #[test]
+    fn release_key_from_tag() {
+        for tag in [
+            "1.2.3",
+            "v1.2.3",
+            "my-app-v1.2.3",
+            "my-app/1.2.3",
+            "my-app/v1.2.3",
+        ] {
+            let key = ReleaseKey::from_tag("my-app", tag.parse().unwrap()).unwrap();
+            assert_eq!(key.version, "1.2.3", "{tag}");
+            assert!(!key.is_prerelease, "{tag}");
+        }
+        let key = ReleaseKey::from_tag("my-app", "v1.2.3-beta.1+build".parse().unwrap()).unwrap();
+        assert!(key.is_prerelease);
+        assert!(!is_prerelease("1.2.3+build-5"));
+
+        let err = ReleaseKey::from_tag("my-app", "other-app-v1.2.3".parse().unwrap()).unwrap_err();
+        let start = err.span.offset();
+        assert_eq!(&err.key[start..start + err.span.len()], "other-app-");
+        assert!(ReleaseKey::from_tag("my-app", "v1.2".parse().unwrap()).is_err());
+    }
+
+    #[test]
+    fn release_key_validate() {
+        let key = ReleaseKey::from_tag("my-app", "v1.2.3".parse().unwrap()).unwrap();
+        key.validate("my-app").unwrap();
+        assert!(key.validate("other-app").is_ok());
+
+        let wrong_version = ReleaseKey {
+            version: "1.2.4".parse().unwrap(),
+            ..key.clone()
+        };
+        let err = wrong_version.validate("my-app").unwrap_err();
+        assert_eq!(err.reason, "the tag has version 1.2.3");
+
+        let wrong_prerelease = ReleaseKey {
+            is_prerelease: true,
+            ..key
+        };
+        let err = wrong_prerelease.validate("my-app").unwrap_err();
+        assert_eq!(err.reason, "1.2.3 isn't a prerelease");
+    }
+}